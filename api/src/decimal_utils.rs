@@ -1,5 +1,6 @@
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
+use database::{Decimal, DecimalError, RoundingMode};
 use crate::registry;
 use crate::redis_manager::UserTokenBalance;
 #[derive(Debug)]
@@ -8,6 +9,7 @@ pub enum ConversionError {
     MarketNotFound,
     InvalidAmount,
     Overflow,
+    PrecisionLoss { decimals: u32 },
 }
 
 impl std::fmt::Display for ConversionError {
@@ -17,6 +19,17 @@ impl std::fmt::Display for ConversionError {
             ConversionError::MarketNotFound => write!(f, "Market not found in registry"),
             ConversionError::InvalidAmount => write!(f, "Invalid amount provided"),
             ConversionError::Overflow => write!(f, "Amount overflow during conversion"),
+            ConversionError::PrecisionLoss { decimals } => write!(f, "Amount has more than {} decimal places", decimals),
+        }
+    }
+}
+
+impl From<DecimalError> for ConversionError {
+    fn from(e: DecimalError) -> Self {
+        match e {
+            DecimalError::Invalid(_) => ConversionError::InvalidAmount,
+            DecimalError::Overflow => ConversionError::Overflow,
+            DecimalError::PrecisionLoss { decimals, .. } => ConversionError::PrecisionLoss { decimals },
         }
     }
 }
@@ -28,8 +41,9 @@ pub struct DecimalCreateOrderRequest {
     pub market_id: Uuid,
     pub order_type: String, // "Buy" or "Sell"
     pub order_kind: String, // "Market" or "Limit"
-    pub price: Option<f64>,    // Decimal price (e.g., 150.25 USDC per SOL)
-    pub quantity: f64,         // Decimal quantity (e.g., 1.5 SOL)
+    pub price: Option<Decimal>, // Decimal price (e.g., "150.25" USDC per SOL)
+    pub quantity: Decimal,      // Decimal quantity (e.g., "1.5" SOL)
+    pub client_order_id: Option<String>, // Optional, unique per user; makes retries safe
 }
 
 // Response structures that return decimal amounts
@@ -37,8 +51,8 @@ pub struct DecimalCreateOrderRequest {
 pub struct DecimalUserTokenBalance {
     pub token_id: Uuid,
     pub token_symbol: String, // e.g., "USDC", "SOL"
-    pub available: f64,       // Decimal amount (e.g., 1.5)
-    pub locked: f64,          // Decimal amount (e.g., 0.25)
+    pub available: Decimal,   // Decimal amount (e.g., "1.5")
+    pub locked: Decimal,      // Decimal amount (e.g., "0.25")
 }
 
 #[derive(Serialize, Debug)]
@@ -48,54 +62,44 @@ pub struct DecimalBalanceResponse {
 
 /// Convert a decimal amount to atomic units for a specific token
 /// Example: 1.5 SOL (with 9 decimals) -> 1500000000 lamports
-pub fn to_atomic_units(amount: f64, token_id: Uuid) -> Result<i64, ConversionError> {
-    if amount < 0.0 || !amount.is_finite() {
+/// Amounts with more decimal places than the token supports are rejected, never rounded.
+pub fn to_atomic_units(amount: Decimal, token_id: Uuid) -> Result<i128, ConversionError> {
+    if amount.is_sign_negative() {
         return Err(ConversionError::InvalidAmount);
     }
 
     let decimals = registry::get_token_decimals(token_id)
         .ok_or(ConversionError::TokenNotFound)?;
-    println!("decimals: {:?}", decimals);
-    let multiplier = 10_f64.powi(decimals as i32);
-    let atomic_amount = amount * multiplier;
-    
-    // Check for overflow
-    if atomic_amount > i64::MAX as f64 {
-        return Err(ConversionError::Overflow);
-    }
-    
-    Ok(atomic_amount.round() as i64)
+    Ok(amount.to_atomic(decimals, RoundingMode::Exact)?)
 }
 
 /// Convert atomic units back to decimal for a specific token
 /// Example: 1500000000 lamports (with 9 decimals) -> 1.5 SOL
-pub fn from_atomic_units(atomic_amount: i64, token_id: Uuid) -> Result<f64, ConversionError> {
+pub fn from_atomic_units(atomic_amount: i128, token_id: Uuid) -> Result<Decimal, ConversionError> {
     let decimals = registry::get_token_decimals(token_id)
         .ok_or(ConversionError::TokenNotFound)?;
     
-    let divisor = 10_f64.powi(decimals as i32);
-    Ok(atomic_amount as f64 / divisor)
+    Ok(Decimal::from_atomic(atomic_amount, decimals))
 }
 
 /// Convert price from decimal to atomic units for a market
 /// Price is always in quote token units per base token
 /// Example: For SOL-USDC market, price 150.25 USDC -> atomic units in quote token (USDC)
-pub fn price_to_atomic_units(price: f64, market_id: Uuid) -> Result<i64, ConversionError> {
-    if price <= 0.0 || !price.is_finite() {
+pub fn price_to_atomic_units(price: Decimal, market_id: Uuid) -> Result<i128, ConversionError> {
+    if !price.is_sign_positive() {
         return Err(ConversionError::InvalidAmount);
     }
 
     let market_meta = registry::get_market_meta(market_id)
         .ok_or(ConversionError::MarketNotFound)?;
-    println!("market_meta: {:?}", market_meta);
     // Convert using the quote token's decimals
     to_atomic_units(price, market_meta.quote_token_id)
 }
 
 /// Convert quantity from decimal to atomic units for a market (base token)
 /// Example: For SOL-USDC market, quantity 1.5 SOL -> atomic units in base token (SOL)
-pub fn quantity_to_atomic_units(quantity: f64, market_id: Uuid) -> Result<i64, ConversionError> {
-    if quantity <= 0.0 || !quantity.is_finite() {
+pub fn quantity_to_atomic_units(quantity: Decimal, market_id: Uuid) -> Result<i128, ConversionError> {
+    if !quantity.is_sign_positive() {
         return Err(ConversionError::InvalidAmount);
     }
 
@@ -108,17 +112,17 @@ pub fn quantity_to_atomic_units(quantity: f64, market_id: Uuid) -> Result<i64, C
 
 /// Convert atomic price back to decimal for a market
 /// Example: atomic price in USDC -> 150.25 USDC per SOL
-pub fn price_from_atomic_units(atomic_price: i64, market_id: Uuid) -> Result<f64, ConversionError> {
+pub fn price_from_atomic_units(atomic_price: i128, market_id: Uuid) -> Result<Decimal, ConversionError> {
     let market_meta = registry::get_market_meta(market_id)
         .ok_or(ConversionError::MarketNotFound)?;
     
-    // Convert using the quote token's decimals
-    from_atomic_units(atomic_price, market_meta.quote_token_id)
+    // Quote token decimals, shown at the market's tick precision
+    Ok(Decimal::from_atomic_at_step(atomic_price, market_meta.quote_decimals, market_meta.tick_size))
 }
 
 /// Convert atomic quantity back to decimal for a market
 /// Example: atomic quantity in SOL -> 1.5 SOL
-pub fn quantity_from_atomic_units(atomic_quantity: i64, market_id: Uuid) -> Result<f64, ConversionError> {
+pub fn quantity_from_atomic_units(atomic_quantity: i128, market_id: Uuid) -> Result<Decimal, ConversionError> {
     let market_meta = registry::get_market_meta(market_id)
         .ok_or(ConversionError::MarketNotFound)?;
     
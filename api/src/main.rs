@@ -14,9 +14,12 @@ use routes::{
     token::{create_token, get_tokens, update_token, delete_token, get_public_tokens},
     market::{create_market, get_markets, update_market, delete_market, get_public_markets},
     balance::{get_user_balance, deposit_funds, withdraw_funds},
-    order::{create_order, cancel_order, get_orders},
+    order::{create_order, cancel_order, get_orders, get_open_orders, get_order_by_client_id},
     trade::get_trades,
+    candle::get_candles,
+    market_data::{get_depth, get_public_trades, get_ticker, get_tickers},
     simulator::start_simulator,
+    engine::resume_trading,
 };
 use routes::test::{get_user_profile, admin_dashboard};
 use jwt::{admin_auth, user_auth};
@@ -63,6 +66,11 @@ async fn main() -> std::io::Result<()> {
             .service(admin_login)
             .service(get_public_tokens)
             .service(get_public_markets)
+            .service(get_candles)
+            .service(get_depth)
+            .service(get_public_trades)
+            .service(get_ticker)
+            .service(get_tickers)
             // Protected routes at root level
             .service(
                 web::scope("/user")
@@ -74,6 +82,8 @@ async fn main() -> std::io::Result<()> {
                             .service(create_order)
                             .service(cancel_order)
                             .service(get_orders)
+                            .service(get_open_orders)
+                            .service(get_order_by_client_id)
                             .service(get_trades)
                             .service(start_simulator)
             )
@@ -89,6 +99,7 @@ async fn main() -> std::io::Result<()> {
                     .service(get_markets)
                     .service(update_market)
                     .service(delete_market)
+                    .service(resume_trading)
             )
     })
     .bind((host.as_str(), port))?
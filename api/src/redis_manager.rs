@@ -12,6 +12,8 @@ pub enum EngineMessage {
     Order(OrderRequest),
     Balance(BalanceRequest),
     CancelOrder(CancelOrderRequest),
+    OpenOrders(OpenOrdersRequest),
+    Resume(ResumeRequest),
     // Future: Trade queries, market data requests, etc.
 }
 
@@ -20,17 +22,28 @@ pub enum EngineMessage {
 pub enum EngineResponse {
     Order(OrderResponse),
     Balance(BalanceResponse),
+    OpenOrders(OpenOrdersResponse),
+    Resume(ResumeResponse),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CancelOrderRequest {
     pub request_id: String,
     pub user_id: Uuid,
-    pub order_id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub client_order_id: Option<String>, // Used when order_id is not given
     pub market_id: Uuid,
     pub timestamp: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenOrdersRequest {
+    pub request_id: String,
+    pub user_id: Uuid,
+    pub market_id: Option<Uuid>, // all markets when not given
+    pub timestamp: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderRequest {
     pub request_id: String,
@@ -38,8 +51,11 @@ pub struct OrderRequest {
     pub market_id: Uuid,
     pub order_type: String, // BUY/SELL
     pub order_kind: String, // MARKET/LIMIT
-    pub price: Option<i64>,
-    pub quantity: i64,
+    #[serde(default, with = "database::amount::option_string")]
+    pub price: Option<i128>,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
+    pub client_order_id: Option<String>,
     pub timestamp: i64,
 }
 
@@ -49,7 +65,8 @@ pub struct BalanceRequest {
     pub user_id: Uuid,
     pub token_id: Uuid,
     pub operation: BalanceOperation,
-    pub amount: i64,
+    #[serde(with = "database::amount::string")]
+    pub amount: i128,
     pub timestamp: i64,
 }
 
@@ -66,24 +83,73 @@ pub struct OrderResponse {
     pub success: bool,
     pub status: String, // "FILLED", "PARTIALLY_FILLED", "PENDING", "REJECTED"
     pub order_id: Option<Uuid>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     pub message: String,
-    pub filled_quantity: Option<i64>,
-    pub remaining_quantity: Option<i64>,
-    pub average_price: Option<i64>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub filled_quantity: Option<i128>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub remaining_quantity: Option<i128>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub average_price: Option<i128>,
     pub trades: Option<Vec<TradeInfo>>,
 }
 
+/// Resting order as held in the engine's book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineOrder {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub market_id: Uuid,
+    pub order_type: String, // "Buy" or "Sell"
+    pub order_kind: String, // "Market" or "Limit"
+    #[serde(default, with = "database::amount::option_string")]
+    pub price: Option<i128>,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
+    #[serde(with = "database::amount::string")]
+    pub filled_quantity: i128,
+    pub status: String, // "Pending" or "PartiallyFilled"
+    pub created_at: i64, // ms since epoch
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenOrdersResponse {
+    pub request_id: String,
+    pub success: bool,
+    pub message: String,
+    pub orders: Vec<EngineOrder>, // newest first
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeRequest {
+    pub request_id: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeResponse {
+    pub request_id: String,
+    pub success: bool,
+    pub message: String,
+    pub was_halted: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BalanceResponse {
     pub request_id: String,
     pub success: bool,
     pub message: String,
-    pub new_balance: i64,
+    #[serde(with = "database::amount::string")]
+    pub new_balance: i128,
     pub balances: Option<Vec<UserTokenBalance>>,
 }
 
 // Unified result type
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)] // short-lived, moved once per request
 pub enum EngineProcessingResult {
     Success(EngineResponse),
     Timeout,
@@ -93,19 +159,24 @@ pub enum EngineProcessingResult {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeInfo {
     pub trade_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    #[serde(with = "database::amount::string")]
+    pub price: i128,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
     pub timestamp: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserTokenBalance {
     pub token_id: Uuid,
-    pub available: i64,
-    pub locked: i64,
+    #[serde(with = "database::amount::string")]
+    pub available: i128,
+    #[serde(with = "database::amount::string")]
+    pub locked: i128,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)] // short-lived, moved once per request
 pub enum OrderProcessingResult {
     Success(OrderResponse),
     Timeout,
@@ -138,6 +209,8 @@ impl RedisManager {
             EngineMessage::Order(req) => req.request_id.clone(),
             EngineMessage::Balance(req) => req.request_id.clone(),
             EngineMessage::CancelOrder(req) => req.request_id.clone(),
+            EngineMessage::OpenOrders(req) => req.request_id.clone(),
+            EngineMessage::Resume(req) => req.request_id.clone(),
         };
         
         // Step 1: Subscribe to response channel BEFORE queuing
@@ -198,6 +271,36 @@ impl RedisManager {
         }
     }
 
+    /// Latest state the engine stored under `snapshot:<kind>:<market_id>`, as raw JSON
+    pub async fn get_snapshot(&self, key: &str) -> Result<Option<String>, redis::RedisError> {
+        let mut conn = self.connection_manager.clone();
+        redis::cmd("GET").arg(key).query_async(&mut conn).await
+    }
+
+    /// `get_snapshot` for several keys in one round trip, in the same order
+    pub async fn get_snapshots(&self, keys: &[String]) -> Result<Vec<Option<String>>, redis::RedisError> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.connection_manager.clone();
+        redis::cmd("MGET").arg(keys).query_async(&mut conn).await
+    }
+
+    /// Tell ws servers that an admin created, updated or deactivated a market,
+    /// so they reload their market list and notify connected clients
+    pub async fn publish_market_change(&self, market_id: Uuid, action: &str) {
+        let mut conn = self.connection_manager.clone();
+        let payload = serde_json::json!({ "market_id": market_id, "action": action }).to_string();
+        let published: Result<i64, redis::RedisError> = redis::cmd("PUBLISH")
+            .arg("markets:updated")
+            .arg(&payload)
+            .query_async(&mut conn)
+            .await;
+        if let Err(e) = published {
+            println!("Failed to publish market change for {}: {:?}", market_id, e);
+        }
+    }
+
     /// Internal function to queue order to Redis Stream
     async fn queue_message_internal(&self, message: EngineMessage) -> Result<String, redis::RedisError> {
         let mut conn = self.connection_manager.clone();
@@ -207,6 +310,8 @@ impl RedisManager {
             EngineMessage::Order(req) => (req.request_id.clone(), "ORDER"),
             EngineMessage::Balance(req) => (req.request_id.clone(), "BALANCE"),
             EngineMessage::CancelOrder(req) => (req.request_id.clone(), "CANCEL_ORDER"),
+            EngineMessage::OpenOrders(req) => (req.request_id.clone(), "OPEN_ORDERS"),
+            EngineMessage::Resume(req) => (req.request_id.clone(), "RESUME"),
         };
         // Add to Redis Stream - this is what the engine will consume
         let stream_id: String = redis::cmd("XADD")
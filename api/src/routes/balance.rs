@@ -150,7 +150,7 @@ pub async fn deposit_funds(req: HttpRequest, body: Json<DecimalDepositRequest>)
     let body = body.into_inner();
     
     // Validate request
-    if body.amount <= 0.0 {
+    if !body.amount.is_sign_positive() {
         return HttpResponse::BadRequest().json("Invalid amount");
     }
 
@@ -165,6 +165,9 @@ pub async fn deposit_funds(req: HttpRequest, body: Json<DecimalDepositRequest>)
         Err(ConversionError::Overflow) => {
             return HttpResponse::BadRequest().json(ErrorResponse::new("Amount too large"));
         },
+        Err(e @ ConversionError::PrecisionLoss { .. }) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(&e.to_string()));
+        },
         Err(e) => {
             return HttpResponse::InternalServerError().json(ErrorResponse::new(&format!("Conversion error: {}", e)));
         }
@@ -230,7 +233,7 @@ pub async fn withdraw_funds(req: HttpRequest, body: Json<DecimalWithdrawRequest>
 
     let body = body.into_inner();
 
-    if body.amount <= 0.0 {
+    if !body.amount.is_sign_positive() {
         return HttpResponse::BadRequest().json("Withdrawal amount must be positive");
     }
 
@@ -245,6 +248,9 @@ pub async fn withdraw_funds(req: HttpRequest, body: Json<DecimalWithdrawRequest>
         Err(ConversionError::Overflow) => {
             return HttpResponse::BadRequest().json(ErrorResponse::new("Amount too large"));
         },
+        Err(e @ ConversionError::PrecisionLoss { .. }) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(&e.to_string()));
+        },
         Err(e) => {
             return HttpResponse::InternalServerError().json(ErrorResponse::new(&format!("Conversion error: {}", e)));
         }
@@ -0,0 +1,94 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use diesel::prelude::*;
+use database::{
+    establish_connection, schema::candles, Candle, CandleInterval, Decimal,
+    amount::from_numeric,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::decimal_utils::{price_from_atomic_units, quantity_from_atomic_units};
+
+#[derive(Deserialize)]
+pub struct CandlesQuery {
+    pub interval: Option<String>, // 1m, 5m, 15m, 1h, 4h, 1d (default 1m)
+    pub from: Option<i64>,        // ms since epoch, inclusive
+    pub to: Option<i64>,          // ms since epoch, inclusive
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct DecimalCandle {
+    pub open_time: i64, // ms since epoch
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trade_count: i32,
+}
+
+fn to_decimal_candle(candle: Candle) -> Result<DecimalCandle, String> {
+    let price = |value| -> Result<Decimal, String> {
+        let atomic = from_numeric(value).map_err(|e| e.to_string())?;
+        price_from_atomic_units(atomic, candle.market_id).map_err(|e| e.to_string())
+    };
+    let volume_atomic = from_numeric(&candle.volume).map_err(|e| e.to_string())?;
+    Ok(DecimalCandle {
+        open_time: candle.open_time.and_utc().timestamp_millis(),
+        open: price(&candle.open)?,
+        high: price(&candle.high)?,
+        low: price(&candle.low)?,
+        close: price(&candle.close)?,
+        volume: quantity_from_atomic_units(volume_atomic, candle.market_id).map_err(|e| e.to_string())?,
+        trade_count: candle.trade_count,
+    })
+}
+
+/// OHLCV bars, oldest first; without `from` the most recent `limit` bars up to `to`
+#[get("/markets/{id}/candles")]
+pub async fn get_candles(path: web::Path<Uuid>, query: web::Query<CandlesQuery>) -> impl Responder {
+    let market_id = path.into_inner();
+    let interval = match query.interval.as_deref().unwrap_or("1m").parse::<CandleInterval>() {
+        Ok(interval) => interval,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+    let limit = query.limit.unwrap_or(500).clamp(1, 1000);
+    let to_time = |ms: i64| chrono::DateTime::from_timestamp_millis(ms).map(|d| d.naive_utc());
+
+    let mut q = candles::table
+        .filter(candles::market_id.eq(market_id))
+        .filter(candles::interval.eq(interval.as_str()))
+        .into_boxed();
+    if let Some(from) = query.from {
+        match to_time(interval.open_time(from)) {
+            Some(from) => q = q.filter(candles::open_time.ge(from)),
+            None => return HttpResponse::BadRequest().json("Invalid from timestamp"),
+        }
+    }
+    if let Some(to) = query.to {
+        match to_time(to) {
+            Some(to) => q = q.filter(candles::open_time.le(to)),
+            None => return HttpResponse::BadRequest().json("Invalid to timestamp"),
+        }
+    }
+    // Bars after `from` when it is given, otherwise the latest ones
+    q = if query.from.is_some() {
+        q.order(candles::open_time.asc())
+    } else {
+        q.order(candles::open_time.desc())
+    };
+
+    let mut conn = establish_connection();
+    match q.select(Candle::as_select()).limit(limit).load::<Candle>(&mut conn) {
+        Ok(mut rows) => {
+            if query.from.is_none() {
+                rows.reverse();
+            }
+            match rows.into_iter().map(to_decimal_candle).collect::<Result<Vec<_>, _>>() {
+                Ok(bars) => HttpResponse::Ok().json(bars),
+                Err(e) => HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+            }
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("DB error: {}", e)),
+    }
+}
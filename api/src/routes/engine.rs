@@ -0,0 +1,28 @@
+use actix_web::{post, HttpResponse, Responder};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::redis_manager::{get_redis_manager, EngineMessage, EngineProcessingResult, EngineResponse, ResumeRequest};
+
+/// Clear a trading halt raised by the engine's invariant checker. The engine checks
+/// again right after, so trading stays halted while the violation persists.
+#[post("/engine/resume")]
+pub async fn resume_trading() -> impl Responder {
+    let resume_req = ResumeRequest {
+        request_id: Uuid::new_v4().to_string(),
+        timestamp: Utc::now().timestamp_millis(),
+    };
+
+    match get_redis_manager().await.send_and_wait(EngineMessage::Resume(resume_req), 5).await {
+        EngineProcessingResult::Success(EngineResponse::Resume(response)) => HttpResponse::Ok().json(response),
+        EngineProcessingResult::Timeout => {
+            HttpResponse::GatewayTimeout().json("Engine did not answer in time")
+        }
+        EngineProcessingResult::Error(e) => {
+            HttpResponse::InternalServerError().json(format!("Resuming trading failed: {}", e))
+        }
+        _ => {
+            HttpResponse::InternalServerError().json("Unexpected response type")
+        }
+    }
+}
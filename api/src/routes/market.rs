@@ -8,6 +8,7 @@ use database::{
     schema::{markets, tokens},
 };
 use crate::jwt::Claims;
+use crate::redis_manager::get_redis_manager;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -143,6 +144,7 @@ pub async fn create_market(body: Json<CreateMarketRequest>, req: HttpRequest) ->
         .values(&new_market)
         .get_result::<Market>(&mut connection) {
         Ok(market) => {
+            get_redis_manager().await.publish_market_change(market.id, "created").await;
             let market_response = MarketWithTokens {
                 id: market.id,
                 symbol: market.symbol,
@@ -378,6 +380,7 @@ pub async fn update_market(
 
     match result {
         Ok(updated_market) => {
+            get_redis_manager().await.publish_market_change(updated_market.id, "updated").await;
             // Get token details for response
             let base_token = tokens::table
                 .filter(tokens::id.eq(updated_market.base_currency_id))
@@ -438,6 +441,7 @@ pub async fn delete_market(path: Path<Uuid>, req: HttpRequest) -> impl Responder
                 .set(markets::is_active.eq(false))
                 .get_result::<Market>(&mut connection) {
                 Ok(updated_market) => {
+                    get_redis_manager().await.publish_market_change(updated_market.id, "deactivated").await;
                     // Get token details for response
                     let base_token = tokens::table
                         .filter(tokens::id.eq(updated_market.base_currency_id))
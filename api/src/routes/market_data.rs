@@ -0,0 +1,223 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use diesel::prelude::*;
+use database::{
+    establish_connection, schema::trades, Trade as DbTrade, Decimal, OrderType,
+    amount::from_numeric,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::decimal_utils::{
+    ConversionError, price_from_atomic_units, price_to_atomic_units, quantity_from_atomic_units, quantity_to_atomic_units,
+};
+use crate::redis_manager::get_redis_manager;
+use crate::registry::{get_market_meta, MARKETS};
+
+#[derive(Deserialize)]
+pub struct DepthQuery {
+    pub limit: Option<usize>, // levels per side (default 100)
+    pub group: Option<Decimal>, // price increment to aggregate levels by, a multiple of the tick size
+}
+
+/// Full book stored by the engine under `snapshot:depth:<market_id>`
+#[derive(Deserialize)]
+struct DepthSnapshot {
+    seq: u64,
+    ts: i64,
+    bids: Vec<(Decimal, Decimal)>, // best (highest) first
+    asks: Vec<(Decimal, Decimal)>, // best (lowest) first
+    price_decimals: u32,
+    quantity_decimals: u32,
+}
+
+/// Same shape as the ws depth snapshot; `seq` is the last delta included,
+/// so ws deltas with a higher `seq` apply on top of it
+#[derive(Serialize)]
+pub struct DepthResponse {
+    pub market_id: Uuid,
+    pub seq: u64,
+    pub ts: i64,
+    pub group: Option<Decimal>,
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+    pub price_decimals: u32,
+    pub quantity_decimals: u32,
+}
+
+/// Sum levels into buckets of `group` atomic price units: bids round down and asks up,
+/// so a bucket never looks better than the levels in it. Input is best first.
+fn group_levels(
+    levels: &[(Decimal, Decimal)],
+    market_id: Uuid,
+    group: i128,
+    round_up: bool,
+) -> Result<Vec<(i128, i128)>, ConversionError> {
+    let mut grouped: Vec<(i128, i128)> = Vec::new();
+    for (price, quantity) in levels {
+        let price = price_to_atomic_units(*price, market_id)?;
+        let quantity = quantity_to_atomic_units(*quantity, market_id)?;
+        let mut bucket = price - price % group;
+        if round_up && bucket != price {
+            bucket += group;
+        }
+        match grouped.last_mut() {
+            Some((last, total)) if *last == bucket => *total += quantity,
+            _ => grouped.push((bucket, quantity)),
+        }
+    }
+    Ok(grouped)
+}
+
+/// Current order book from the engine's latest published depth
+#[get("/markets/{id}/depth")]
+pub async fn get_depth(path: web::Path<Uuid>, query: web::Query<DepthQuery>) -> impl Responder {
+    let market_id = path.into_inner();
+    let Some(market) = get_market_meta(market_id) else {
+        return HttpResponse::NotFound().json("Market not found");
+    };
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let group = match query.group {
+        None => None,
+        Some(group) => match price_to_atomic_units(group, market_id) {
+            // Grouped prices are rendered with an i64 step
+            Ok(atomic) if atomic > 0 && atomic <= i64::MAX as i128 && atomic % market.tick_size as i128 == 0 => Some(atomic),
+            _ => return HttpResponse::BadRequest().json(format!(
+                "group must be a positive multiple of the tick size {}",
+                Decimal::from_atomic(market.tick_size as i128, market.quote_decimals)
+            )),
+        },
+    };
+
+    let raw = match get_redis_manager().await.get_snapshot(&format!("snapshot:depth:{}", market_id)).await {
+        Ok(Some(raw)) => raw,
+        Ok(None) => return HttpResponse::ServiceUnavailable().json("Depth not available yet"),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("Redis error: {}", e)),
+    };
+    let snapshot: DepthSnapshot = match serde_json::from_str(&raw) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return HttpResponse::InternalServerError().json(format!("Invalid depth snapshot: {}", e)),
+    };
+
+    let (bids, asks) = match group {
+        None => (
+            snapshot.bids.into_iter().take(limit).collect(),
+            snapshot.asks.into_iter().take(limit).collect(),
+        ),
+        Some(group) => {
+            let to_decimal = |levels: Vec<(i128, i128)>| -> Result<Vec<(Decimal, Decimal)>, ConversionError> {
+                levels.into_iter().take(limit).map(|(price, quantity)| Ok((
+                    Decimal::from_atomic_at_step(price, market.quote_decimals, group as i64),
+                    quantity_from_atomic_units(quantity, market_id)?,
+                ))).collect()
+            };
+            match (
+                group_levels(&snapshot.bids, market_id, group, false).and_then(to_decimal),
+                group_levels(&snapshot.asks, market_id, group, true).and_then(to_decimal),
+            ) {
+                (Ok(bids), Ok(asks)) => (bids, asks),
+                (Err(e), _) | (_, Err(e)) => return HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+            }
+        }
+    };
+
+    HttpResponse::Ok().json(DepthResponse {
+        market_id,
+        seq: snapshot.seq,
+        ts: snapshot.ts,
+        group: query.group,
+        bids,
+        asks,
+        price_decimals: snapshot.price_decimals,
+        quantity_decimals: snapshot.quantity_decimals,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct PublicTradesQuery {
+    pub limit: Option<i64>, // default 100
+}
+
+/// One print on the public tape; carries no order or user ids
+#[derive(Serialize)]
+pub struct PublicTrade {
+    pub id: Uuid,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub taker_side: Option<OrderType>, // encoded like the ws `trades` feed
+    pub timestamp: i64, // engine match time, ms since epoch
+}
+
+/// Most recent trades of a market, newest first
+#[get("/markets/{id}/trades")]
+pub async fn get_public_trades(path: web::Path<Uuid>, query: web::Query<PublicTradesQuery>) -> impl Responder {
+    let market_id = path.into_inner();
+    if get_market_meta(market_id).is_none() {
+        return HttpResponse::NotFound().json("Market not found");
+    }
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    let mut conn = establish_connection();
+    match trades::table
+        .filter(trades::market_id.eq(market_id))
+        .order((trades::executed_at.desc(), trades::event_seq.desc().nulls_last(), trades::id.desc()))
+        .select(DbTrade::as_select())
+        .limit(limit)
+        .load::<DbTrade>(&mut conn) {
+        Ok(rows) => {
+            let tape: Result<Vec<PublicTrade>, String> = rows.into_iter().map(|trade| {
+                let price_atomic = from_numeric(&trade.price).map_err(|e| e.to_string())?;
+                let quantity_atomic = from_numeric(&trade.quantity).map_err(|e| e.to_string())?;
+                Ok(PublicTrade {
+                    id: trade.id,
+                    price: price_from_atomic_units(price_atomic, market_id).map_err(|e| e.to_string())?,
+                    quantity: quantity_from_atomic_units(quantity_atomic, market_id).map_err(|e| e.to_string())?,
+                    taker_side: match trade.taker_side.as_deref() {
+                        Some("BUY") => Some(OrderType::Buy),
+                        Some("SELL") => Some(OrderType::Sell),
+                        _ => None,
+                    },
+                    timestamp: trade.executed_at.and_utc().timestamp_millis(),
+                })
+            }).collect();
+            match tape {
+                Ok(tape) => HttpResponse::Ok().json(tape),
+                Err(e) => HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+            }
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("DB error: {}", e)),
+    }
+}
+
+/// 24h ticker as last published by the engine (same payload as the ws `ticker` feed)
+#[get("/markets/{id}/ticker")]
+pub async fn get_ticker(path: web::Path<Uuid>) -> impl Responder {
+    let market_id = path.into_inner();
+    if get_market_meta(market_id).is_none() {
+        return HttpResponse::NotFound().json("Market not found");
+    }
+    match get_redis_manager().await.get_snapshot(&format!("latest:ticker:{}", market_id)).await {
+        Ok(Some(raw)) => match serde_json::from_str::<serde_json::Value>(&raw) {
+            Ok(ticker) => HttpResponse::Ok().json(ticker),
+            Err(e) => HttpResponse::InternalServerError().json(format!("Invalid ticker: {}", e)),
+        },
+        Ok(None) => HttpResponse::NotFound().json("No ticker for this market yet"),
+        Err(e) => HttpResponse::InternalServerError().json(format!("Redis error: {}", e)),
+    }
+}
+
+/// Tickers of every active market that has traded, by symbol
+#[get("/tickers")]
+pub async fn get_tickers() -> impl Responder {
+    let mut markets: Vec<(String, Uuid)> = MARKETS.iter().map(|m| (m.symbol.clone(), m.market_id)).collect();
+    markets.sort();
+    let keys: Vec<String> = markets.iter().map(|(_, id)| format!("latest:ticker:{}", id)).collect();
+    match get_redis_manager().await.get_snapshots(&keys).await {
+        Ok(raw) => {
+            let tickers: Vec<serde_json::Value> = raw.into_iter()
+                .flatten()
+                .filter_map(|json| serde_json::from_str(&json).ok())
+                .collect();
+            HttpResponse::Ok().json(tickers)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("Redis error: {}", e)),
+    }
+}
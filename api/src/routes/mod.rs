@@ -5,4 +5,7 @@ pub mod test;
 pub mod balance;
 pub mod order;
 pub mod trade;
+pub mod candle;
+pub mod market_data;
 pub mod simulator;
+pub mod engine;
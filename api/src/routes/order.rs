@@ -1,11 +1,11 @@
-use actix_web::{post,get, web::Json, HttpRequest, HttpResponse, HttpMessage, Responder};
+use actix_web::{post,get, web::{Json, Path, Query}, HttpRequest, HttpResponse, HttpMessage, Responder};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::Utc;
 use crate::jwt::Claims;
 use crate::redis_manager::{
     get_redis_manager, EngineMessage, EngineProcessingResult, EngineResponse,
-    OrderRequest, CancelOrderRequest,
+    OrderRequest, CancelOrderRequest, OpenOrdersRequest, EngineOrder,
 };
 use crate::decimal_utils::{
     DecimalCreateOrderRequest, price_to_atomic_units, quantity_to_atomic_units, 
@@ -17,6 +17,8 @@ use database::{
     Order as DbOrder,
     Market,
     schema::{orders, markets},
+    Decimal,
+    amount::{from_numeric, from_numeric_opt},
 };
 
 #[derive(Deserialize, Debug)]
@@ -24,8 +26,10 @@ pub struct CreateOrderRequest {
     pub market_id: Uuid,
     pub order_type: String, // "Buy" or "Sell"
     pub order_kind: String, // "Market" or "Limit"
-    pub price: Option<i64>,
-    pub quantity: i64,
+    #[serde(default, with = "database::amount::option_string")]
+    pub price: Option<i128>,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
 }
 
 #[derive(Serialize)]
@@ -35,7 +39,8 @@ pub struct OrderResult {
     pub request_id: String,
     pub status: String,
     pub order_id: Option<Uuid>,
-    pub filled_quantity: Option<i64>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub filled_quantity: Option<i128>,
     pub trades: Option<Vec<serde_json::Value>>,
 }
 
@@ -47,18 +52,88 @@ pub struct DecimalOrderResult {
     pub request_id: String,
     pub status: String,
     pub order_id: Option<Uuid>,
-    pub filled_quantity: Option<f64>,        // Decimal quantity
-    pub filled_quantity_atomic: Option<i64>, // Atomic quantity for debugging
+    pub filled_quantity: Option<Decimal>,    // Decimal quantity
+    #[serde(default, with = "database::amount::option_string")]
+    pub filled_quantity_atomic: Option<i128>, // Atomic quantity for debugging
     pub trades: Option<Vec<serde_json::Value>>,
 }
 
 
 #[derive(Deserialize, Debug)]
 pub struct CancelOrderBody {
-    pub order_id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub client_order_id: Option<String>,
     pub market_id: Uuid,
 }
 
+#[derive(Serialize)]
+struct OrderWithMarket {
+    id: Uuid,
+    user_id: Uuid,
+    client_order_id: Option<String>,
+    market: Market,
+    order_type: String,
+    order_kind: String,
+    price: Option<Decimal>,    // Decimal price
+    #[serde(with = "database::amount::option_string")]
+    price_atomic: Option<i128>, // Atomic price for debugging
+    quantity: Decimal,         // Decimal quantity
+    #[serde(with = "database::amount::string")]
+    quantity_atomic: i128,     // Atomic quantity for debugging
+    filled_quantity: Decimal,  // Decimal filled quantity
+    #[serde(with = "database::amount::string")]
+    filled_quantity_atomic: i128, // Atomic filled quantity for debugging
+    status: String,
+    created_at: chrono::NaiveDateTime,
+    updated_at: chrono::NaiveDateTime,
+}
+
+impl TryFrom<(DbOrder, Market)> for OrderWithMarket {
+    type Error = ConversionError;
+
+    fn try_from((o, m): (DbOrder, Market)) -> Result<Self, Self::Error> {
+        let price_atomic = from_numeric_opt(&o.price)?;
+        let quantity_atomic = from_numeric(&o.quantity)?;
+        let filled_quantity_atomic = from_numeric(&o.filled_quantity)?;
+
+        // Convert price from atomic to decimal (if exists)
+        let price_decimal = price_atomic
+            .map(|atomic_price| price_from_atomic_units(atomic_price, m.id))
+            .transpose()?;
+
+        // Convert quantity and filled_quantity from atomic to decimal
+        let quantity_decimal = quantity_from_atomic_units(quantity_atomic, m.id)?;
+        let filled_quantity_decimal = quantity_from_atomic_units(filled_quantity_atomic, m.id)?;
+        Ok(OrderWithMarket {
+            id: o.id,
+            user_id: o.user_id,
+            client_order_id: o.client_order_id,
+            market: m,
+            order_type: o.order_type,
+            order_kind: o.order_kind,
+            // Decimal values (user-friendly)
+            price: price_decimal,
+            quantity: quantity_decimal,
+            filled_quantity: filled_quantity_decimal,
+            
+            // Atomic values (for debugging)
+            price_atomic,
+            quantity_atomic,
+            filled_quantity_atomic,
+            status: o.status,
+            created_at: o.created_at,
+            updated_at: o.updated_at,
+        })
+    }
+}
+
+/// Client order ids are 1-64 characters of `[A-Za-z0-9_-]`
+fn is_valid_client_order_id(client_order_id: &str) -> bool {
+    !client_order_id.is_empty()
+        && client_order_id.len() <= 64
+        && client_order_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
 #[post("/orders")]
 pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest>) -> impl Responder {
     // Extract user ID from JWT
@@ -73,13 +148,19 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
     let body = body.into_inner();
     println!("Creating order: {:?}", body);
 
-    if body.quantity <= 0.0 {
+    if !body.quantity.is_sign_positive() {
         return HttpResponse::BadRequest().json("Invalid quantity: Quantity must be greater than 0");
     }
 
+    if let Some(client_order_id) = &body.client_order_id {
+        if !is_valid_client_order_id(client_order_id) {
+            return HttpResponse::BadRequest().json("Invalid client_order_id: use 1-64 letters, digits, '_' or '-'");
+        }
+    }
+
     if body.order_kind == "Limit" {
         match body.price {
-            Some(price) if price <= 0.0 => {
+            Some(price) if !price.is_sign_positive() => {
                 return HttpResponse::BadRequest().json("Invalid price: must be positive for limit orders");
             }
             None => {
@@ -102,6 +183,9 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
             Err(ConversionError::Overflow) => {
                 return HttpResponse::BadRequest().json("Price too large");
             },
+            Err(e @ ConversionError::PrecisionLoss { .. }) => {
+                return HttpResponse::BadRequest().json(format!("Invalid price: {}", e));
+            },
             Err(e) => {
                 return HttpResponse::InternalServerError().json(format!("Price conversion error: {}", e));
             }
@@ -122,6 +206,9 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
         Err(ConversionError::Overflow) => {
             return HttpResponse::BadRequest().json("Quantity too large");
         },
+        Err(e @ ConversionError::PrecisionLoss { .. }) => {
+            return HttpResponse::BadRequest().json(format!("Invalid quantity: {}", e));
+        },
         Err(e) => {
             return HttpResponse::InternalServerError().json(format!("Quantity conversion error: {}", e));
         }
@@ -136,6 +223,7 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
         order_kind: body.order_kind,
         price: atomic_price,
         quantity: atomic_quantity,
+        client_order_id: body.client_order_id.clone(),
         timestamp: Utc::now().timestamp_millis(),
     };
     
@@ -188,23 +276,25 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
 
             // Convert trade data back to decimal
             let trades_decimal = response.trades.map(|trades| {
-                trades.into_iter().map(|trade| {
+                trades.into_iter().map(|trade| -> Result<serde_json::Value, ConversionError> {
                     // Convert trade price and quantity to decimal
-                    let trade_price_decimal = price_from_atomic_units(trade.price, body.market_id)
-                        .unwrap_or(trade.price as f64); // Fallback to atomic if conversion fails
-                    let trade_quantity_decimal = quantity_from_atomic_units(trade.quantity, body.market_id)
-                        .unwrap_or(trade.quantity as f64); // Fallback to atomic if conversion fails
+                    let trade_price_decimal = price_from_atomic_units(trade.price, body.market_id)?;
+                    let trade_quantity_decimal = quantity_from_atomic_units(trade.quantity, body.market_id)?;
             
-                    serde_json::json!({
+                    Ok(serde_json::json!({
                         "trade_id": trade.trade_id,
                         "price": trade_price_decimal,           // Decimal price (e.g., 50.0 USDC)
-                        "price_atomic": trade.price,            // Atomic price for debugging
+                        "price_atomic": trade.price.to_string(), // Atomic price for debugging
                         "quantity": trade_quantity_decimal,     // Decimal quantity (e.g., 1.0 SOL)  
-                        "quantity_atomic": trade.quantity,      // Atomic quantity for debugging
+                        "quantity_atomic": trade.quantity.to_string(), // Atomic quantity for debugging
                         "timestamp": trade.timestamp
-                    })
-                }).collect::<Vec<serde_json::Value>>()  // <-- Add explicit type annotation here
-            });
+                    }))
+                }).collect::<Result<Vec<serde_json::Value>, _>>()
+            }).transpose();
+            let trades_decimal = match trades_decimal {
+                Ok(trades) => trades,
+                Err(e) => return HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+            };
 
             HttpResponse::Ok().json(serde_json::json!({
                 "success": response.success,
@@ -212,6 +302,7 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
                 "request_id": response.request_id,
                 "status": response.status,
                 "order_id": response.order_id,
+                "client_order_id": response.client_order_id,
                 
                 // Quantities in decimal format
                 "filled_quantity": filled_quantity_decimal,
@@ -219,9 +310,9 @@ pub async fn create_order(req: HttpRequest, body: Json<DecimalCreateOrderRequest
                 "average_price": average_price_decimal,
                 
                 // Atomic values for debugging
-                "filled_quantity_atomic": response.filled_quantity,
-                "remaining_quantity_atomic": response.remaining_quantity,
-                "average_price_atomic": response.average_price,
+                "filled_quantity_atomic": response.filled_quantity.map(|v| v.to_string()),
+                "remaining_quantity_atomic": response.remaining_quantity.map(|v| v.to_string()),
+                "average_price_atomic": response.average_price.map(|v| v.to_string()),
                 
                 // Trades with both decimal and atomic values
                 "trades": trades_decimal
@@ -251,10 +342,15 @@ pub async fn cancel_order(req: HttpRequest, body: Json<CancelOrderBody>) -> impl
 
     let body = body.into_inner();
 
+    if body.order_id.is_none() && body.client_order_id.is_none() {
+        return HttpResponse::BadRequest().json("Either order_id or client_order_id is required");
+    }
+
     let cancel_req = CancelOrderRequest {
         request_id: Uuid::new_v4().to_string(),
         user_id,
         order_id: body.order_id,
+        client_order_id: body.client_order_id,
         market_id: body.market_id,
         timestamp: Utc::now().timestamp_millis(),
     };
@@ -277,8 +373,37 @@ pub async fn cancel_order(req: HttpRequest, body: Json<CancelOrderBody>) -> impl
     }
 }
 
+#[derive(Deserialize)]
+pub struct OrdersQuery {
+    pub market_id: Option<Uuid>,
+    pub status: Option<String>, // open (pending or partially filled), filled, cancelled
+    pub side: Option<String>,   // buy, sell
+    pub kind: Option<String>,   // market, limit
+    pub from: Option<i64>,      // created_at, ms since epoch, inclusive
+    pub to: Option<i64>,        // created_at, ms since epoch, inclusive
+    pub cursor: Option<String>, // `next_cursor` of the previous page
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct OrdersPage {
+    orders: Vec<OrderWithMarket>, // newest first
+    next_cursor: Option<String>,  // None on the last page
+}
+
+/// Keyset position after an order: `<created_at in µs>_<order id>`
+fn encode_cursor(order: &OrderWithMarket) -> String {
+    format!("{}_{}", order.created_at.and_utc().timestamp_micros(), order.id)
+}
+
+fn decode_cursor(cursor: &str) -> Option<(chrono::NaiveDateTime, Uuid)> {
+    let (micros, id) = cursor.split_once('_')?;
+    let created_at = chrono::DateTime::from_timestamp_micros(micros.parse().ok()?)?.naive_utc();
+    Some((created_at, Uuid::parse_str(id).ok()?))
+}
+
 #[get("/orders")]
-pub async fn get_orders(req: HttpRequest) -> impl Responder {
+pub async fn get_orders(req: HttpRequest, query: Query<OrdersQuery>) -> impl Responder {
     let user_id = match req.extensions().get::<Claims>() {
         Some(claims) => match Uuid::parse_str(&claims.user_id) {
             Ok(uuid) => uuid,
@@ -286,76 +411,198 @@ pub async fn get_orders(req: HttpRequest) -> impl Responder {
         },
         None => return HttpResponse::Unauthorized().json("Authentication required"),
     };
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+    let to_time = |ms: i64| chrono::DateTime::from_timestamp_millis(ms).map(|d| d.naive_utc());
+
+    let mut q = orders::table
+        .inner_join(markets::table.on(markets::id.eq(orders::market_id)))
+        .filter(orders::user_id.eq(user_id))
+        .into_boxed();
+
+    if let Some(market_id) = query.market_id {
+        q = q.filter(orders::market_id.eq(market_id));
+    }
+    if let Some(status) = &query.status {
+        let statuses: &[&str] = match status.to_lowercase().as_str() {
+            "open" => &["PENDING", "PARTIALLY_FILLED"],
+            "filled" => &["FILLED"],
+            "cancelled" => &["CANCELLED"],
+            _ => return HttpResponse::BadRequest().json("status must be open, filled or cancelled"),
+        };
+        q = q.filter(orders::status.eq_any(statuses));
+    }
+    if let Some(side) = &query.side {
+        match side.to_uppercase().as_str() {
+            side @ ("BUY" | "SELL") => q = q.filter(orders::order_type.eq(side.to_string())),
+            _ => return HttpResponse::BadRequest().json("side must be buy or sell"),
+        }
+    }
+    if let Some(kind) = &query.kind {
+        match kind.to_uppercase().as_str() {
+            kind @ ("MARKET" | "LIMIT") => q = q.filter(orders::order_kind.eq(kind.to_string())),
+            _ => return HttpResponse::BadRequest().json("kind must be market or limit"),
+        }
+    }
+    if let Some(from) = query.from {
+        match to_time(from) {
+            Some(from) => q = q.filter(orders::created_at.ge(from)),
+            None => return HttpResponse::BadRequest().json("Invalid from timestamp"),
+        }
+    }
+    if let Some(to) = query.to {
+        match to_time(to) {
+            Some(to) => q = q.filter(orders::created_at.le(to)),
+            None => return HttpResponse::BadRequest().json("Invalid to timestamp"),
+        }
+    }
+    // Orders strictly after the cursor in (created_at, id) descending order
+    if let Some(cursor) = &query.cursor {
+        match decode_cursor(cursor) {
+            Some((created_at, id)) => {
+                q = q.filter(
+                    orders::created_at.lt(created_at)
+                        .or(orders::created_at.eq(created_at).and(orders::id.lt(id))),
+                )
+            }
+            None => return HttpResponse::BadRequest().json("Invalid cursor"),
+        }
+    }
 
     let mut conn = establish_connection();
+    let result = q
+        .select((DbOrder::as_select(), Market::as_select()))
+        .order((orders::created_at.desc(), orders::id.desc()))
+        .limit(limit + 1) // one extra row tells whether there is a next page
+        .load::<(DbOrder, Market)>(&mut conn);
+
+    match result {
+        Ok(mut rows) => {
+            let has_more = rows.len() as i64 > limit;
+            rows.truncate(limit as usize);
+            let orders: Vec<OrderWithMarket> = match rows.into_iter().map(OrderWithMarket::try_from).collect() {
+                Ok(orders) => orders,
+                Err(e) => return HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+            };
+            let next_cursor = if has_more { orders.last().map(encode_cursor) } else { None };
+            HttpResponse::Ok().json(OrdersPage { orders, next_cursor })
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("Error fetching orders: {}", e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OpenOrdersQuery {
+    pub market_id: Option<Uuid>,
+}
+
+/// Resting order as the engine holds it right now
+#[derive(Serialize)]
+struct OpenOrder {
+    id: Uuid,
+    market_id: Uuid,
+    client_order_id: Option<String>,
+    order_type: String, // BUY, SELL
+    order_kind: String, // LIMIT
+    price: Option<Decimal>,
+    quantity: Decimal,
+    filled_quantity: Decimal,
+    remaining_quantity: Decimal,
+    status: String, // PENDING, PARTIALLY_FILLED
+    created_at: i64, // ms since epoch
+}
+
+impl TryFrom<EngineOrder> for OpenOrder {
+    type Error = ConversionError;
+
+    fn try_from(o: EngineOrder) -> Result<Self, Self::Error> {
+        let quantity = |atomic: i128| quantity_from_atomic_units(atomic, o.market_id);
+        Ok(OpenOrder {
+            id: o.id,
+            market_id: o.market_id,
+            client_order_id: o.client_order_id.clone(),
+            // Same spelling as the orders table
+            order_type: o.order_type.to_uppercase(),
+            order_kind: o.order_kind.to_uppercase(),
+            price: o.price.map(|price| price_from_atomic_units(price, o.market_id)).transpose()?,
+            quantity: quantity(o.quantity)?,
+            filled_quantity: quantity(o.filled_quantity)?,
+            remaining_quantity: quantity(o.quantity - o.filled_quantity)?,
+            status: match o.status.as_str() {
+                "PartiallyFilled" => "PARTIALLY_FILLED".to_string(),
+                status => status.to_uppercase(),
+            },
+            created_at: o.created_at,
+        })
+    }
+}
+
+/// Open orders from the engine's books rather than the database, which the
+/// db-updater brings up to date asynchronously
+#[get("/orders/open")]
+pub async fn get_open_orders(req: HttpRequest, query: Query<OpenOrdersQuery>) -> impl Responder {
+    let user_id = match req.extensions().get::<Claims>() {
+        Some(claims) => match Uuid::parse_str(&claims.user_id) {
+            Ok(uuid) => uuid,
+            Err(_) => return HttpResponse::BadRequest().json("Invalid user ID format"),
+        },
+        None => return HttpResponse::Unauthorized().json("Authentication required"),
+    };
+
+    let open_orders_req = OpenOrdersRequest {
+        request_id: Uuid::new_v4().to_string(),
+        user_id,
+        market_id: query.market_id,
+        timestamp: Utc::now().timestamp_millis(),
+    };
+
+    let redis_manager = get_redis_manager().await;
 
-    #[derive(Serialize)]
-    struct OrderWithMarket {
-        id: Uuid,
-        user_id: Uuid,
-        market: Market,
-        order_type: String,
-        order_kind: String,
-        price: Option<f64>,        // Decimal price
-        price_atomic: Option<i64>, // Atomic price for debugging
-        quantity: f64,             // Decimal quantity  
-        quantity_atomic: i64,      // Atomic quantity for debugging
-        filled_quantity: f64,      // Decimal filled quantity
-        filled_quantity_atomic: i64, // Atomic filled quantity for debugging
-        status: String,
-        created_at: chrono::NaiveDateTime,
-        updated_at: chrono::NaiveDateTime,
+    match redis_manager.send_and_wait(EngineMessage::OpenOrders(open_orders_req), 5).await {
+        EngineProcessingResult::Success(EngineResponse::OpenOrders(response)) => {
+            match response.orders.into_iter().map(OpenOrder::try_from).collect::<Result<Vec<_>, _>>() {
+                Ok(orders) => HttpResponse::Ok().json(orders),
+                Err(e) => HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+            }
+        }
+        EngineProcessingResult::Timeout => {
+            HttpResponse::GatewayTimeout().json("Engine did not answer in time")
+        }
+        EngineProcessingResult::Error(e) => {
+            HttpResponse::InternalServerError().json(format!("Fetching open orders failed: {}", e))
+        }
+        _ => {
+            HttpResponse::InternalServerError().json("Unexpected response type")
+        }
     }
+}
+
+#[get("/orders/client/{client_order_id}")]
+pub async fn get_order_by_client_id(req: HttpRequest, path: Path<String>) -> impl Responder {
+    let user_id = match req.extensions().get::<Claims>() {
+        Some(claims) => match Uuid::parse_str(&claims.user_id) {
+            Ok(uuid) => uuid,
+            Err(_) => return HttpResponse::BadRequest().json("Invalid user ID format"),
+        },
+        None => return HttpResponse::Unauthorized().json("Authentication required"),
+    };
+
+    let client_order_id = path.into_inner();
+    let mut conn = establish_connection();
 
     let result = orders::table
         .inner_join(markets::table.on(markets::id.eq(orders::market_id)))
         .filter(orders::user_id.eq(user_id))
+        .filter(orders::client_order_id.eq(&client_order_id))
         .select((DbOrder::as_select(), Market::as_select()))
-        .order(orders::created_at.desc())
-        .load::<(DbOrder, Market)>(&mut conn);
+        .first::<(DbOrder, Market)>(&mut conn)
+        .optional();
 
     match result {
-        Ok(rows) => {
-            let data: Vec<OrderWithMarket> = rows
-                .into_iter()
-                .map(|(o, m)| {
-                    // Convert price from atomic to decimal (if exists)
-                    let price_decimal = if let Some(atomic_price) = o.price {
-                        price_from_atomic_units(atomic_price, m.id).ok()
-                    } else {
-                        None
-                    };
-
-                    // Convert quantity from atomic to decimal
-                    let quantity_decimal = quantity_from_atomic_units(o.quantity, m.id)
-                        .unwrap_or(o.quantity as f64); // Fallback to atomic if conversion fails
-
-                    // Convert filled_quantity from atomic to decimal
-                    let filled_quantity_decimal = quantity_from_atomic_units(o.filled_quantity, m.id)
-                        .unwrap_or(o.filled_quantity as f64); // Fallback to atomic if conversion fails
-                    OrderWithMarket {
-                        id: o.id,
-                        user_id: o.user_id,
-                        market: m,
-                        order_type: o.order_type,
-                        order_kind: o.order_kind,
-                        // Decimal values (user-friendly)
-                        price: price_decimal,
-                        quantity: quantity_decimal,
-                        filled_quantity: filled_quantity_decimal,
-                        
-                        // Atomic values (for debugging)
-                        price_atomic: o.price,
-                        quantity_atomic: o.quantity,
-                        filled_quantity_atomic: o.filled_quantity,
-                        status: o.status,
-                        created_at: o.created_at,
-                        updated_at: o.updated_at,
-                    }
-                })
-                .collect();
-            HttpResponse::Ok().json(data)
-        }
-        Err(e) => HttpResponse::InternalServerError().json(format!("Error fetching orders: {}", e)),
+        Ok(Some(row)) => match OrderWithMarket::try_from(row) {
+            Ok(order) => HttpResponse::Ok().json(order),
+            Err(e) => HttpResponse::InternalServerError().json(format!("Conversion error: {}", e)),
+        },
+        Ok(None) => HttpResponse::NotFound().json("Order not found"),
+        Err(e) => HttpResponse::InternalServerError().json(format!("Error fetching order: {}", e)),
     }
-}
\ No newline at end of file
+}
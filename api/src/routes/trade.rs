@@ -1,7 +1,8 @@
 use actix_web::{get, HttpRequest,web,  HttpResponse, HttpMessage, Responder};
 use diesel::prelude::*;
 use database::{
-    establish_connection, schema::trades::{self, dsl as trades_dsl}, Trade as DbTrade
+    establish_connection, schema::trades::{self, dsl as trades_dsl}, Trade as DbTrade, Decimal,
+    amount::from_numeric,
 };
 use uuid::Uuid;
 use crate::jwt::Claims;
@@ -24,10 +25,12 @@ pub struct DecimalTrade {
     pub seller_order_id: Uuid,
     pub buyer_user_id: Uuid,
     pub seller_user_id: Uuid,
-    pub price: f64,              // Decimal price (e.g., 50.0 USDC)
-    pub price_atomic: i64,       // Atomic price for debugging
-    pub quantity: f64,           // Decimal quantity (e.g., 1.0 SOL)
-    pub quantity_atomic: i64,    // Atomic quantity for debugging
+    pub price: Decimal,          // Decimal price (e.g., "50" USDC)
+    #[serde(with = "database::amount::string")]
+    pub price_atomic: i128,      // Atomic price for debugging
+    pub quantity: Decimal,       // Decimal quantity (e.g., "1" SOL)
+    #[serde(with = "database::amount::string")]
+    pub quantity_atomic: i128,   // Atomic quantity for debugging
     pub created_at: chrono::NaiveDateTime,
 }
 
@@ -42,7 +45,7 @@ pub async fn get_trades(req: HttpRequest, query: web::Query<TradesQuery>) -> imp
     };
 
     let mut conn = establish_connection();
-    use trades_dsl::{trades, buyer_user_id, seller_user_id, market_id, created_at};
+    use trades_dsl::{trades, buyer_user_id, seller_user_id, market_id, executed_at, event_seq};
 
     let limit = query.limit.unwrap_or(50).clamp(1, 200);
     let offset = query.offset.unwrap_or(0);
@@ -57,7 +60,7 @@ pub async fn get_trades(req: HttpRequest, query: web::Query<TradesQuery>) -> imp
 
     match q
         .select(DbTrade::as_select())
-        .order(created_at.desc())
+        .order((executed_at.desc(), event_seq.desc().nulls_last()))
         .limit(limit)
         .offset(offset)
         .load::<DbTrade>(&mut conn) {
@@ -65,13 +68,16 @@ pub async fn get_trades(req: HttpRequest, query: web::Query<TradesQuery>) -> imp
             let decimal_trades: Vec<DecimalTrade> = rows
                 .into_iter()
                 .map(|trade| {
+                    let price_atomic = from_numeric(&trade.price).unwrap_or_default();
+                    let quantity_atomic = from_numeric(&trade.quantity).unwrap_or_default();
+
                     // Convert price from atomic to decimal
-                    let price_decimal = price_from_atomic_units(trade.price, trade.market_id)
-                        .unwrap_or(trade.price as f64); // Fallback to atomic if conversion fails
+                    let price_decimal = price_from_atomic_units(price_atomic, trade.market_id)
+                        .unwrap_or(Decimal::from_atomic(price_atomic, 0)); // Fallback to atomic if conversion fails
 
                     // Convert quantity from atomic to decimal
-                    let quantity_decimal = quantity_from_atomic_units(trade.quantity, trade.market_id)
-                        .unwrap_or(trade.quantity as f64); // Fallback to atomic if conversion fails
+                    let quantity_decimal = quantity_from_atomic_units(quantity_atomic, trade.market_id)
+                        .unwrap_or(Decimal::from_atomic(quantity_atomic, 0)); // Fallback to atomic if conversion fails
 
                     DecimalTrade {
                         id: trade.id,
@@ -86,10 +92,10 @@ pub async fn get_trades(req: HttpRequest, query: web::Query<TradesQuery>) -> imp
                         quantity: quantity_decimal,
                         
                         // Atomic values (for debugging)
-                        price_atomic: trade.price,
-                        quantity_atomic: trade.quantity,
+                        price_atomic,
+                        quantity_atomic,
                         
-                        created_at: trade.created_at,
+                        created_at: trade.executed_at,
                     }
                 })
                 .collect(); 
@@ -185,7 +185,7 @@ export default function DepositDialog({ open, onOpenChange }: Props) {
               if (!asset) return;
               setSubmitting(true);
               try {
-                await deposit(asset.id, Number(amount));
+                await deposit(asset.id, amount.trim());
                 setAmount("");
                 toast.success("Deposit successful");
                 onOpenChange(false);
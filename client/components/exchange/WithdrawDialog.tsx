@@ -171,7 +171,7 @@ export default function WithdrawDialog({ open, onOpenChange }: Props) {
             onClick={async () => {
               setSubmitting(true);
               try {
-                await withdraw(asset?.id || "", Number(amount));
+                await withdraw(asset?.id || "", amount.trim());
                 setAmount("");
                 toast.success("Withdrawal successful");
                 await loadBalances();
@@ -74,7 +74,10 @@ export default function BookTrades() {
     const list = trades.slice(0, 200);
     return list.map((t: any) => {
       const time = new Date(t.timestamp).toLocaleTimeString();
-      const side = mid !== undefined && t.price >= mid ? 'buy' as const : 'sell' as const;
+      // Taker side from the engine; older trades fall back to their price against the mid
+      const side = t.taker_side
+        ? (t.taker_side === 'Buy' ? 'buy' as const : 'sell' as const)
+        : mid !== undefined && t.price >= mid ? 'buy' as const : 'sell' as const;
       return { time, price: t.price, size: t.quantity, side };
     });
   }, [trades, mid]);
@@ -94,9 +94,9 @@ export default function OrderForm({ base, quote, marketId }: { base: string; quo
 
       const order_type = side === "buy" ? "Buy" : "Sell";
       const order_kind = kind === "Market" ? "Market" : "Limit";
-      const p = order_kind === "Market" ? null : Number(price || 0);
-      const q = Number(qty);
-      // TODO: convert p and q to engine integer units if needed (based on market tick_size/min_order_size)
+      // Sent as typed: the api parses decimal strings exactly and rejects fractional numbers
+      const p = order_kind === "Market" ? null : price.trim();
+      const q = qty.trim();
       await create({ market_id: marketId, order_type, order_kind, price: p, quantity: q });
       setQty("");
       setPrice("");
@@ -1,8 +1,10 @@
 // client/lib/wsClient.ts
-type Feed = 'depth' | 'ticker' | 'trades';
+// market_id '*' subscribes to every market (ticker, trades, candles, mini_ticker)
+type Feed = 'depth' | 'ticker' | 'trades' | 'l3' | 'candles' | 'mini_ticker';
 
 type WsEvent =
   | { type: 'event'; channel: string; payload: any }
+  | { type: 'snapshot'; channel: string; payload: any }
   | { type: 'info'; message: string }
   | Record<string, any>;
 
@@ -46,9 +48,22 @@ class WsClient {
   subscribe = (marketId: string, feeds: Feed[] = ['depth', 'ticker', 'trades']) => {
     this.wantSubscribe = { marketId, feeds };
     if (!this.ws || this.ws.readyState !== WebSocket.OPEN) { this.connect(); return; }
+    // The server answers with cached depth/ticker/trades snapshots before live events
     this.ws.send(JSON.stringify({ action: 'subscribe', market_id: marketId, feeds }));
   };
 
+  // Full book tagged with the seq of the last depth delta it includes
+  requestDepthSnapshot = (marketId: string) => {
+    if (!this.ws || this.ws.readyState !== WebSocket.OPEN) return;
+    this.ws.send(JSON.stringify({ action: 'depth_snapshot', market_id: marketId }));
+  };
+
+  // Replay a subscribed channel after the last seq seen; the server falls back to a snapshot
+  resume = (channel: string, fromSeq: number) => {
+    if (!this.ws || this.ws.readyState !== WebSocket.OPEN) return;
+    this.ws.send(JSON.stringify({ action: 'resume', channel, from_seq: fromSeq }));
+  };
+
   unsubscribe = () => {
     this.wantSubscribe = {};
     // Optional: implement server-side unsubscribe if supported
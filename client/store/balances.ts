@@ -18,8 +18,8 @@ type State = {
   error: string | null;
   lastTx: Tx | null;
   fetch: () => Promise<void>;
-  deposit: (token_id: string, amountUnits: number) => Promise<Tx>;
-  withdraw: (token_id: string, amountUnits: number) => Promise<Tx>;
+  deposit: (token_id: string, amount: string) => Promise<Tx>; // decimal string, e.g. "1.5"
+  withdraw: (token_id: string, amount: string) => Promise<Tx>;
 };
 
 export const useBalances = create<State>((set, get) => ({
@@ -36,8 +36,8 @@ export const useBalances = create<State>((set, get) => ({
       set({ 
         items: (data.balances  ?? []).map(b => ({
           token_id: b.token_id,
-          available: b.available,
-          locked: b.locked,
+          available: Number(b.available),
+          locked: Number(b.locked),
         }))
     });
     } catch (e: any) {
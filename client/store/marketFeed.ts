@@ -3,8 +3,28 @@ import { create } from 'zustand';
 import { wsClient } from '@/lib/wsClient';
 
 type Depth = { bids: [number, number][]; asks: [number, number][]; seq?: number };
-type Ticker = { last_price: number; volume_24h: number; high_24h: number; low_24h: number; change_24h: number; timestamp: number };
-type Trade = { id: string; price: number; quantity: number; buyer_user_id?: string; seller_user_id?: string; timestamp: number };
+type Ticker = { last_price: number; open_24h: number; volume_24h: number; quote_volume_24h: number; high_24h: number; low_24h: number; trade_count_24h: number; change_24h: number; timestamp: number };
+type Trade = { id: string; price: number; quantity: number; buyer_user_id?: string; seller_user_id?: string; taker_side?: 'Buy' | 'Sell'; timestamp: number };
+
+// Feeds carry exact decimal strings; the UI only needs numbers for display
+type Level = [string | number, string | number];
+
+// Depth arrives as a snapshot plus deltas (quantity 0 removes a level) with a contiguous seq
+type Book = { seq: number; bids: Map<string, number>; asks: Map<string, number> };
+const books: Record<string, Book | undefined> = {};
+const pendingDeltas: Record<string, any[]> = {};
+
+const applyLevels = (side: Map<string, number>, levels?: Level[]) => {
+  for (const [p, q] of levels ?? []) {
+    const qty = Number(q);
+    if (qty === 0) side.delete(String(p)); else side.set(String(p), qty);
+  }
+};
+const toDepth = (book: Book): Depth => ({
+  bids: [...book.bids].map(([p, q]) => [Number(p), q] as [number, number]).sort((a, b) => b[0] - a[0]),
+  asks: [...book.asks].map(([p, q]) => [Number(p), q] as [number, number]).sort((a, b) => a[0] - b[0]),
+  seq: book.seq,
+});
 
 type MarketSlice = {
   currentMarketId?: string;
@@ -20,26 +40,98 @@ export const useMarketFeedStore = create<MarketSlice>((set, get) => {
   if (typeof window !== 'undefined') {
     wsClient.connect();
     wsClient.addListener((msg: any) => {
-      if (msg?.type !== 'event' || !msg.channel) return;
+      if ((msg?.type !== 'event' && msg?.type !== 'snapshot') || !msg.channel) return;
       const ch: string = msg.channel;
       const payload = msg.payload;
 
-      if (ch.startsWith('depth:')) {
+      if (msg.type === 'snapshot' && ch.startsWith('depth:')) {
         const marketId = ch.split(':')[1];
-        set(state => ({
-          depthByMarket: { ...state.depthByMarket, [marketId]: { bids: payload.bids ?? [], asks: payload.asks ?? [], seq: payload.seq } }
-        }));
+        const book: Book = { seq: payload.seq, bids: new Map(), asks: new Map() };
+        applyLevels(book.bids, payload.bids);
+        applyLevels(book.asks, payload.asks);
+        // Replay deltas that arrived while the snapshot was in flight
+        for (const delta of pendingDeltas[marketId] ?? []) {
+          if (delta.seq !== book.seq + 1) continue;
+          applyLevels(book.bids, delta.bids);
+          applyLevels(book.asks, delta.asks);
+          book.seq = delta.seq;
+        }
+        pendingDeltas[marketId] = [];
+        books[marketId] = book;
+        set(state => ({ depthByMarket: { ...state.depthByMarket, [marketId]: toDepth(book) } }));
+      } else if (ch.startsWith('depth:')) {
+        const marketId = ch.split(':')[1];
+        const book = books[marketId];
+        if (!book) {
+          (pendingDeltas[marketId] ??= []).push(payload);
+          return;
+        }
+        if (payload.seq <= book.seq && payload.seq > 1) return; // already in the snapshot; seq 1 means the engine restarted
+        if (payload.seq === 1 && book.seq > 1) {
+          // Engine restart: rebuild from a fresh snapshot
+          books[marketId] = undefined;
+          pendingDeltas[marketId] = [payload];
+          wsClient.requestDepthSnapshot(marketId);
+          return;
+        }
+        if (payload.seq !== book.seq + 1) {
+          // Gap: hold this delta and ask the server to replay the missing ones
+          const pending = (pendingDeltas[marketId] ??= []);
+          if (pending.length === 0) wsClient.resume(ch, book.seq);
+          pending.push(payload);
+          return;
+        }
+        applyLevels(book.bids, payload.bids);
+        applyLevels(book.asks, payload.asks);
+        book.seq = payload.seq;
+        // Held deltas that now follow the book
+        const held = (pendingDeltas[marketId] ?? []).sort((a, b) => a.seq - b.seq);
+        pendingDeltas[marketId] = [];
+        for (const delta of held) {
+          if (delta.seq <= book.seq) continue;
+          if (delta.seq !== book.seq + 1) { pendingDeltas[marketId].push(delta); continue; }
+          applyLevels(book.bids, delta.bids);
+          applyLevels(book.asks, delta.asks);
+          book.seq = delta.seq;
+        }
+        set(state => ({ depthByMarket: { ...state.depthByMarket, [marketId]: toDepth(book) } }));
       } else if (ch.startsWith('ticker:')) {
         const marketId = ch.split(':')[1];
         set(state => ({
-          tickerByMarket: { ...state.tickerByMarket, [marketId]: payload }
+          tickerByMarket: {
+            ...state.tickerByMarket,
+            [marketId]: {
+              ...payload,
+              last_price: Number(payload.last_price),
+              open_24h: Number(payload.open_24h),
+              volume_24h: Number(payload.volume_24h),
+              quote_volume_24h: Number(payload.quote_volume_24h),
+              high_24h: Number(payload.high_24h),
+              low_24h: Number(payload.low_24h),
+              change_24h: Number(payload.change_24h),
+            }
+          }
         }));
+      } else if (msg.type === 'snapshot' && ch.startsWith('trades:')) {
+        // Recent trades cached by the ws service, oldest first
+        const marketId = ch.split(':')[1];
+        const recent: Trade[] = (payload.trades ?? [])
+          .map((t: any) => ({ ...t, price: Number(t.price), quantity: Number(t.quantity) }))
+          .reverse();
+        set(state => {
+          const ids = new Set(recent.map(t => t.id));
+          const older = (state.tradesByMarket[marketId] ?? []).filter(t => !ids.has(t.id));
+          return { tradesByMarket: { ...state.tradesByMarket, [marketId]: [...recent, ...older].slice(0, 200) } };
+        });
       } else if (ch.startsWith('trades:')) {
         const marketId = ch.split(':')[1];
         set(state => ({
           tradesByMarket: {
             ...state.tradesByMarket,
-            [marketId]: [payload, ...(state.tradesByMarket[marketId] ?? [])].slice(0, 200)
+            [marketId]: [
+              { ...payload, price: Number(payload.price), quantity: Number(payload.quantity) },
+              ...(state.tradesByMarket[marketId] ?? [])
+            ].slice(0, 200)
           }
         }));
       }
@@ -58,6 +150,8 @@ export const useMarketFeedStore = create<MarketSlice>((set, get) => {
     },
 
     clearMarket: (marketId: string) => {
+      books[marketId] = undefined;
+      pendingDeltas[marketId] = [];
       set(state => ({
         depthByMarket: { ...state.depthByMarket, [marketId]: { bids: [], asks: [] } },
         tickerByMarket: { ...state.tickerByMarket, [marketId]: undefined as any },
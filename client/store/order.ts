@@ -49,8 +49,8 @@ export type CreateOrderPayload = {
   market_id: string;
   order_type: "Buy" | "Sell";
   order_kind: "Market" | "Limit";
-  price?: number | null;
-  quantity: number;
+  price?: string | null; // decimal strings, sent as typed
+  quantity: string;
 };
 
 type State = {
@@ -70,8 +70,16 @@ export const useOrders = create<State>((set, get) => ({
   fetch: async () => {
     set({ loading: true, error: null });
     try {
-      const { data } = await api.get<EngineOrder[]>("/user/orders");
-      set({ items: Array.isArray(data) ? data : [] });
+      // First page (newest orders); older ones follow `next_cursor`
+      const { data } = await api.get<{ orders: EngineOrder[]; next_cursor: string | null }>("/user/orders");
+      // Amounts arrive as exact decimal strings
+      const items = (Array.isArray(data?.orders) ? data.orders : []).map(o => ({
+        ...o,
+        price: o.price == null ? null : Number(o.price),
+        quantity: Number(o.quantity),
+        filled_quantity: Number(o.filled_quantity),
+      }));
+      set({ items });
     } catch (e: any) {
       set({ error: e?.message || "Failed to load orders" });
       throw e;
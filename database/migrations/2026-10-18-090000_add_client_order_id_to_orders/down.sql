@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS idx_orders_user_client_order_id;
+ALTER TABLE orders
+    DROP COLUMN client_order_id;
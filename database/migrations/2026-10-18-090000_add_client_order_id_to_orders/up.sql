@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE orders
+    ADD COLUMN client_order_id VARCHAR(64);
+
+-- Client order ids are unique per user; orders without one are unconstrained
+CREATE UNIQUE INDEX idx_orders_user_client_order_id
+    ON orders (user_id, client_order_id)
+    WHERE client_order_id IS NOT NULL;
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE trades
+    ALTER COLUMN price TYPE BIGINT,
+    ALTER COLUMN quantity TYPE BIGINT;
+
+ALTER TABLE orders
+    ALTER COLUMN price TYPE BIGINT,
+    ALTER COLUMN quantity TYPE BIGINT,
+    ALTER COLUMN filled_quantity TYPE BIGINT;
+
+ALTER TABLE balances
+    ALTER COLUMN amount TYPE BIGINT,
+    ALTER COLUMN locked_amount TYPE BIGINT;
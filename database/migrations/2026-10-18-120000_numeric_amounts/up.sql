@@ -0,0 +1,13 @@
+-- Atomic amounts of 18-decimal tokens do not fit in BIGINT; NUMERIC(39,0) holds any i128
+ALTER TABLE balances
+    ALTER COLUMN amount TYPE NUMERIC(39, 0),
+    ALTER COLUMN locked_amount TYPE NUMERIC(39, 0);
+
+ALTER TABLE orders
+    ALTER COLUMN price TYPE NUMERIC(39, 0),
+    ALTER COLUMN quantity TYPE NUMERIC(39, 0),
+    ALTER COLUMN filled_quantity TYPE NUMERIC(39, 0);
+
+ALTER TABLE trades
+    ALTER COLUMN price TYPE NUMERIC(39, 0),
+    ALTER COLUMN quantity TYPE NUMERIC(39, 0);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM balances WHERE user_id = '00000000-0000-0000-0000-00000000d057';
+DELETE FROM users WHERE id = '00000000-0000-0000-0000-00000000d057';
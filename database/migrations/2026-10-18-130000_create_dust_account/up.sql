@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- System account credited with the rounding difference between what buyers pay
+-- (rounded up) and what sellers receive (rounded down). It cannot log in.
+INSERT INTO users (id, email, password_hash, is_admin)
+VALUES ('00000000-0000-0000-0000-00000000d057', 'dust@system.internal', '!', false)
+ON CONFLICT (id) DO NOTHING;
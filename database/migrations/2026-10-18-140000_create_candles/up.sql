@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- OHLCV bars per market and interval, aggregated from executed trades.
+-- Prices are quote atomic units, volume is base atomic units.
+CREATE TABLE candles (
+    market_id UUID NOT NULL REFERENCES markets(id) ON DELETE CASCADE,
+    interval VARCHAR(3) NOT NULL,
+    open_time TIMESTAMP NOT NULL,
+    open NUMERIC(39,0) NOT NULL,
+    high NUMERIC(39,0) NOT NULL,
+    low NUMERIC(39,0) NOT NULL,
+    close NUMERIC(39,0) NOT NULL,
+    volume NUMERIC(39,0) NOT NULL,
+    trade_count INTEGER NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (market_id, interval, open_time)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX idx_trades_market_created_at;
+ALTER TABLE trades DROP COLUMN taker_side;
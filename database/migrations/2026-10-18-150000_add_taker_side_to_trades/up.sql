@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Side of the order that took liquidity ('BUY' or 'SELL'), for the public trade tape.
+ALTER TABLE trades ADD COLUMN taker_side VARCHAR(10);
+
+-- Existing trades: the taker is the later of the two orders
+UPDATE trades t
+SET taker_side = CASE WHEN b.created_at > s.created_at THEN 'BUY' ELSE 'SELL' END
+FROM orders b, orders s
+WHERE b.id = t.buyer_order_id AND s.id = t.seller_order_id;
+
+CREATE INDEX idx_trades_market_created_at ON trades (market_id, created_at DESC);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX idx_orders_user_status_created_at;
+DROP INDEX idx_orders_user_market_created_at;
+DROP INDEX idx_orders_user_created_at;
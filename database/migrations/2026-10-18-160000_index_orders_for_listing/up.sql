@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Keyset paging of a user's orders on (created_at, id), optionally narrowed by market or status.
+CREATE INDEX idx_orders_user_created_at ON orders (user_id, created_at DESC, id DESC);
+CREATE INDEX idx_orders_user_market_created_at ON orders (user_id, market_id, created_at DESC, id DESC);
+CREATE INDEX idx_orders_user_status_created_at ON orders (user_id, status, created_at DESC, id DESC);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX idx_trades_market_executed_at;
+CREATE INDEX idx_trades_market_created_at ON trades (market_id, created_at DESC);
+ALTER TABLE trades DROP COLUMN event_seq;
+ALTER TABLE trades DROP COLUMN executed_at;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- When the engine matched the trade, and the engine event that carried it. `created_at` is only
+-- the time the db-updater batch was written, shared by every trade in it.
+ALTER TABLE trades ADD COLUMN executed_at TIMESTAMP;
+ALTER TABLE trades ADD COLUMN event_seq BIGINT;
+
+-- Existing trades: the insert time is the best record left
+UPDATE trades SET executed_at = created_at;
+ALTER TABLE trades ALTER COLUMN executed_at SET NOT NULL;
+
+DROP INDEX idx_trades_market_created_at;
+CREATE INDEX idx_trades_market_executed_at ON trades (market_id, executed_at DESC, event_seq DESC);
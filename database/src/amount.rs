@@ -0,0 +1,122 @@
+//! Atomic token amounts are `i128` everywhere: NUMERIC(39,0) in Postgres and
+//! decimal strings in the Redis protocols.
+
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, ToPrimitive};
+
+use crate::decimal::DecimalError;
+
+/// Postgres NUMERIC value of an atomic amount
+pub fn to_numeric(amount: i128) -> BigDecimal {
+    BigDecimal::from(amount)
+}
+
+/// Atomic amount stored in a NUMERIC column; fails on fractions or values beyond i128
+pub fn from_numeric(value: &BigDecimal) -> Result<i128, DecimalError> {
+    if !value.is_integer() {
+        return Err(DecimalError::Invalid(value.to_string()));
+    }
+    value.to_i128().ok_or(DecimalError::Overflow)
+}
+
+/// Same as `from_numeric` for nullable columns
+pub fn from_numeric_opt(value: &Option<BigDecimal>) -> Result<Option<i128>, DecimalError> {
+    value.as_ref().map(from_numeric).transpose()
+}
+
+fn parse(s: &str) -> Result<i128, DecimalError> {
+    i128::from_str(s.trim()).map_err(|_| DecimalError::Invalid(s.to_string()))
+}
+
+/// `#[serde(with = "database::amount::string")]` for `i128` amounts.
+/// Serialises as a string; also accepts JSON integers written by older producers.
+pub mod string {
+    use std::fmt;
+
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    struct AmountVisitor;
+
+    impl<'de> Visitor<'de> for AmountVisitor {
+        type Value = i128;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an integer amount as string or number")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<i128, E> {
+            super::parse(v).map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<i128, E> {
+            Ok(v as i128)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<i128, E> {
+            Ok(v as i128)
+        }
+
+        fn visit_i128<E: de::Error>(self, v: i128) -> Result<i128, E> {
+            Ok(v)
+        }
+
+        fn visit_u128<E: de::Error>(self, v: u128) -> Result<i128, E> {
+            i128::try_from(v).map_err(E::custom)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i128, D::Error> {
+        deserializer.deserialize_any(AmountVisitor)
+    }
+
+    pub(crate) fn visitor() -> impl for<'de> Visitor<'de, Value = i128> {
+        AmountVisitor
+    }
+}
+
+/// `#[serde(with = "database::amount::option_string")]` for `Option<i128>` amounts
+pub mod option_string {
+    use std::fmt;
+
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<i128>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(v) => serializer.collect_str(v),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    struct OptionVisitor;
+
+    impl<'de> Visitor<'de> for OptionVisitor {
+        type Value = Option<i128>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an optional integer amount")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(super::string::visitor()).map(Some)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i128>, D::Error> {
+        deserializer.deserialize_option(OptionVisitor)
+    }
+}
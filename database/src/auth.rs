@@ -0,0 +1,39 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+/// JWT claims issued by the api at login and accepted by every service
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Claims {
+    pub user_id: String,
+    pub email: String,
+    pub is_admin: bool,
+    pub exp: usize,
+}
+
+const JWT_SECRET: &[u8] = b"secret";
+
+pub fn create_jwt(user_id: String, email: String, is_admin: bool) -> Result<String, jsonwebtoken::errors::Error> {
+    let expiration = Utc::now()
+        .checked_add_signed(Duration::days(1))
+        .expect("Invalid expiration date");
+
+    let claims = Claims { 
+        user_id, 
+        email,
+        is_admin,
+        exp: expiration.timestamp() as usize 
+    };
+
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET))?;
+
+    Ok(token)
+}
+
+pub fn verify_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let token_data = decode::<Claims>(
+        token,
+         &DecodingKey::from_secret(JWT_SECRET), 
+         &Validation::default())?;
+    Ok(token_data.claims)
+}
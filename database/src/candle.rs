@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Candle bar sizes, stored as their short name in `candles.interval`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    M1,
+    #[serde(rename = "5m")]
+    M5,
+    #[serde(rename = "15m")]
+    M15,
+    #[serde(rename = "1h")]
+    H1,
+    #[serde(rename = "4h")]
+    H4,
+    #[serde(rename = "1d")]
+    D1,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 6] = [
+        CandleInterval::M1,
+        CandleInterval::M5,
+        CandleInterval::M15,
+        CandleInterval::H1,
+        CandleInterval::H4,
+        CandleInterval::D1,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandleInterval::M1 => "1m",
+            CandleInterval::M5 => "5m",
+            CandleInterval::M15 => "15m",
+            CandleInterval::H1 => "1h",
+            CandleInterval::H4 => "4h",
+            CandleInterval::D1 => "1d",
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleInterval::M1 => 60,
+            CandleInterval::M5 => 5 * 60,
+            CandleInterval::M15 => 15 * 60,
+            CandleInterval::H1 => 60 * 60,
+            CandleInterval::H4 => 4 * 60 * 60,
+            CandleInterval::D1 => 24 * 60 * 60,
+        }
+    }
+
+    /// Start of the bar containing `ts_ms`; bars are aligned to the Unix epoch (UTC)
+    pub fn open_time(&self, ts_ms: i64) -> i64 {
+        let ms = self.seconds() * 1000;
+        ts_ms.div_euclid(ms) * ms
+    }
+}
+
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CandleInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CandleInterval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == s)
+            .ok_or_else(|| format!("Unknown candle interval: {}", s))
+    }
+}
+
+#[derive(diesel::Queryable, diesel::QueryableByName, diesel::Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::candles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Candle {
+    pub market_id: Uuid,
+    pub interval: String,
+    pub open_time: NaiveDateTime,
+    pub open: BigDecimal,
+    pub high: BigDecimal,
+    pub low: BigDecimal,
+    pub close: BigDecimal,
+    pub volume: BigDecimal,
+    pub trade_count: i32,
+    pub updated_at: NaiveDateTime,
+}
@@ -0,0 +1,363 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Largest scale a `Decimal` can carry (10^38 still fits in an i128)
+pub const MAX_SCALE: u32 = 38;
+
+/// Exact fixed-point decimal: `mantissa * 10^-scale`.
+/// Serialised as a decimal string; deserialised from a string or a JSON integer.
+/// Fractional JSON numbers are rejected, they have already lost precision as floats.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+/// How to handle digits that do not fit into the target number of decimals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    Exact,  // fail with PrecisionLoss
+    Down,   // towards zero
+    Up,     // away from zero
+    HalfUp, // to nearest, ties away from zero
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecimalError {
+    Invalid(String),
+    Overflow,
+    PrecisionLoss { scale: u32, decimals: u32 },
+}
+
+impl fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecimalError::Invalid(s) => write!(f, "Invalid decimal: {}", s),
+            DecimalError::Overflow => write!(f, "Decimal overflow"),
+            DecimalError::PrecisionLoss { scale, decimals } => write!(
+                f,
+                "Amount has {} decimal places but only {} are supported",
+                scale, decimals
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecimalError {}
+
+fn pow10(exp: u32) -> Result<i128, DecimalError> {
+    10i128.checked_pow(exp).ok_or(DecimalError::Overflow)
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal { mantissa: 0, scale: 0 };
+
+    pub fn new(mantissa: i128, scale: u32) -> Result<Self, DecimalError> {
+        if scale > MAX_SCALE {
+            return Err(DecimalError::Overflow);
+        }
+        Ok(Decimal { mantissa, scale })
+    }
+
+    /// Decimal value of an atomic amount, e.g. 1500000000 with 9 decimals -> 1.5
+    pub fn from_atomic(atomic: i128, decimals: u32) -> Self {
+        Decimal { mantissa: atomic, scale: decimals.min(MAX_SCALE) }.normalize()
+    }
+
+    /// Decimal value of an atomic amount shown with as many places as the market's
+    /// `step` (tick or lot size, in atomic units) has, e.g. 50000000 with 6 decimals and
+    /// step 10000 -> 50.00. Never drops digits of an amount that is off the step.
+    pub fn from_atomic_at_step(atomic: i128, decimals: u32, step: i64) -> Self {
+        let mut places = decimals;
+        let mut step = step;
+        while step > 0 && step % 10 == 0 && places > 0 {
+            step /= 10;
+            places -= 1;
+        }
+        Decimal::from_atomic(atomic, decimals).with_min_scale(places)
+    }
+
+    /// Atomic amount for a token with `decimals` places, e.g. 1.5 with 9 decimals -> 1500000000
+    pub fn to_atomic(&self, decimals: u32, mode: RoundingMode) -> Result<i128, DecimalError> {
+        if decimals >= self.scale {
+            return self.mantissa.checked_mul(pow10(decimals - self.scale)?).ok_or(DecimalError::Overflow);
+        }
+
+        let divisor = pow10(self.scale - decimals)?;
+        let quotient = self.mantissa / divisor;
+        let remainder = self.mantissa % divisor;
+        if remainder == 0 {
+            return Ok(quotient);
+        }
+
+        let away = if self.mantissa < 0 { -1 } else { 1 };
+        match mode {
+            RoundingMode::Exact => Err(DecimalError::PrecisionLoss { scale: self.normalize().scale, decimals }),
+            RoundingMode::Down => Ok(quotient),
+            RoundingMode::Up => Ok(quotient + away),
+            RoundingMode::HalfUp => {
+                if remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+                    Ok(quotient + away)
+                } else {
+                    Ok(quotient)
+                }
+            }
+        }
+    }
+
+    /// Same value without trailing fractional zeros
+    pub fn normalize(&self) -> Self {
+        let mut d = *self;
+        while d.scale > 0 && d.mantissa % 10 == 0 {
+            d.mantissa /= 10;
+            d.scale -= 1;
+        }
+        d
+    }
+
+    /// Same value padded with trailing zeros to at least `scale` places, e.g. 1.5 -> 1.50.
+    /// Never drops digits; returns the value unchanged if padding would overflow.
+    pub fn with_min_scale(&self, scale: u32) -> Self {
+        let scale = scale.min(MAX_SCALE);
+        if scale <= self.scale {
+            return *self;
+        }
+        match pow10(scale - self.scale).ok().and_then(|p| self.mantissa.checked_mul(p)) {
+            Some(mantissa) => Decimal { mantissa, scale },
+            None => *self,
+        }
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn is_sign_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    pub fn is_sign_positive(&self) -> bool {
+        self.mantissa > 0
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.normalize(), other.normalize());
+        a.mantissa == b.mantissa && a.scale == b.scale
+    }
+}
+
+impl Eq for Decimal {}
+
+impl FromStr for Decimal {
+    type Err = DecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DecimalError::Invalid(s.to_string());
+        let trimmed = s.trim();
+
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        // Plain notation plus an optional exponent, which JSON numbers may use
+        let (number, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((number, exp)) => (number, exp.parse::<i32>().map_err(|_| invalid())?),
+            None => (unsigned, 0),
+        };
+        let (int_part, frac_part) = number.split_once('.').unwrap_or((number, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(invalid());
+        }
+        // Anything beyond this cannot be held in an i128 anyway
+        if exponent.unsigned_abs() > MAX_SCALE || frac_part.len() > MAX_SCALE as usize {
+            return Err(DecimalError::Overflow);
+        }
+        if !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let mut mantissa: i128 = 0;
+        for c in int_part.chars().chain(frac_part.chars()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add((c as u8 - b'0') as i128))
+                .ok_or(DecimalError::Overflow)?;
+        }
+
+        let mut scale = frac_part.len() as i64 - exponent as i64;
+        if scale < 0 {
+            mantissa = mantissa.checked_mul(pow10((-scale) as u32)?).ok_or(DecimalError::Overflow)?;
+            scale = 0;
+        }
+        let mut decimal = Decimal { mantissa, scale: scale as u32 };
+        if decimal.scale > MAX_SCALE {
+            decimal = decimal.normalize();
+            if decimal.scale > MAX_SCALE {
+                return Err(DecimalError::Overflow);
+            }
+        }
+        if negative {
+            decimal.mantissa = -decimal.mantissa;
+        }
+        Ok(decimal)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (int_part, frac_part) = padded.split_at(padded.len() - scale);
+        write!(f, "{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct DecimalVisitor;
+
+impl<'de> Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal string or integer")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Decimal, E> {
+        Ok(Decimal { mantissa: v as i128, scale: 0 })
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Decimal, E> {
+        Ok(Decimal { mantissa: v as i128, scale: 0 })
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for s in ["0", "1", "-1", "1.5", "0.000000000000000001", "-12345.6789", "170141183460469231731.687303715884105727"] {
+            assert_eq!(dec(s).to_string(), s);
+        }
+        assert_eq!(dec("+1.50").to_string(), "1.50");
+        assert_eq!(dec(".5").to_string(), "0.5");
+        assert_eq!(dec("5.").to_string(), "5");
+    }
+
+    #[test]
+    fn parse_exponent() {
+        assert_eq!(dec("1.5e3"), dec("1500"));
+        assert_eq!(dec("15E-1"), dec("1.5"));
+        assert_eq!(dec("0e-38"), Decimal::ZERO);
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        for s in ["", "-", ".", "1.2.3", "1,5", "abc", "1e", "NaN", "1.5x"] {
+            assert!(matches!(s.parse::<Decimal>(), Err(DecimalError::Invalid(_))), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_caps_exponent_and_scale() {
+        assert_eq!("0e-2147483647".parse::<Decimal>(), Err(DecimalError::Overflow));
+        assert_eq!("1e39".parse::<Decimal>(), Err(DecimalError::Overflow));
+        assert_eq!(format!("0.{}", "0".repeat(39)).parse::<Decimal>(), Err(DecimalError::Overflow));
+        assert_eq!("1e200".parse::<Decimal>(), Err(DecimalError::Overflow));
+    }
+
+    #[test]
+    fn equality_ignores_trailing_zeros() {
+        assert_eq!(dec("1.50"), dec("1.5"));
+        assert_eq!(dec("-0.0"), Decimal::ZERO);
+        assert_ne!(dec("1.5"), dec("1.05"));
+    }
+
+    #[test]
+    fn atomic_round_trip() {
+        assert_eq!(Decimal::from_atomic(1_500_000_000, 9), dec("1.5"));
+        assert_eq!(dec("1.5").to_atomic(9, RoundingMode::Exact), Ok(1_500_000_000));
+        let wei = 123_456_789_012_345_678_901_234_567i128;
+        assert_eq!(Decimal::from_atomic(wei, 18).to_atomic(18, RoundingMode::Exact), Ok(wei));
+        assert_eq!(Decimal::from_atomic_at_step(50_000_000, 6, 10_000).to_string(), "50.00");
+    }
+
+    #[test]
+    fn to_atomic_rejects_precision_loss() {
+        assert_eq!(
+            dec("1.2345").to_atomic(2, RoundingMode::Exact),
+            Err(DecimalError::PrecisionLoss { scale: 4, decimals: 2 })
+        );
+        // Trailing zeros are not lost precision
+        assert_eq!(dec("1.2300").to_atomic(2, RoundingMode::Exact), Ok(123));
+    }
+
+    #[test]
+    fn to_atomic_rounding_modes() {
+        assert_eq!(dec("1.231").to_atomic(2, RoundingMode::Down), Ok(123));
+        assert_eq!(dec("1.231").to_atomic(2, RoundingMode::Up), Ok(124));
+        assert_eq!(dec("1.235").to_atomic(2, RoundingMode::HalfUp), Ok(124));
+        assert_eq!(dec("1.234").to_atomic(2, RoundingMode::HalfUp), Ok(123));
+        assert_eq!(dec("-1.231").to_atomic(2, RoundingMode::Down), Ok(-123));
+        assert_eq!(dec("-1.231").to_atomic(2, RoundingMode::Up), Ok(-124));
+    }
+
+    #[test]
+    fn to_atomic_overflow() {
+        assert_eq!(dec("100000000000000000000").to_atomic(18, RoundingMode::Exact), Ok(10i128.pow(38)));
+        assert_eq!(dec("1000000000000000000000").to_atomic(18, RoundingMode::Exact), Err(DecimalError::Overflow));
+    }
+
+    #[test]
+    fn serde_uses_strings() {
+        assert_eq!(serde_json::to_string(&dec("1.50")).unwrap(), "\"1.50\"");
+        assert_eq!(serde_json::from_str::<Decimal>("\"0.000000000000000001\"").unwrap(), dec("0.000000000000000001"));
+        assert_eq!(serde_json::from_str::<Decimal>("42").unwrap(), dec("42"));
+        assert_eq!(serde_json::from_str::<Decimal>("-7").unwrap(), dec("-7"));
+    }
+
+    #[test]
+    fn serde_rejects_floats() {
+        assert!(serde_json::from_str::<Decimal>("1.5").is_err());
+        assert!(serde_json::from_str::<Decimal>("0.123456789012345678").is_err());
+        assert!(serde_json::from_str::<Decimal>("1e3").is_err());
+    }
+}
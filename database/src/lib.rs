@@ -1,5 +1,9 @@
 pub mod schema;
 pub mod model;
+pub mod decimal;
+pub mod amount;
+pub mod candle;
+pub mod auth;
 
 use diesel::prelude::*;
 use dotenvy::dotenv;
@@ -22,6 +26,12 @@ pub use model::{
     DecimalWithdrawRequest,
     TransactionResponse,
 };
+pub use decimal::{Decimal, DecimalError, RoundingMode};
+pub use candle::{Candle, CandleInterval};
+pub use bigdecimal::BigDecimal;
+
+/// System user that collects settlement rounding dust (see the `create_dust_account` migration)
+pub const DUST_ACCOUNT_ID: uuid::Uuid = uuid::Uuid::from_u128(0xd057);
 
 pub fn establish_connection() -> PgConnection {
     dotenv().ok();
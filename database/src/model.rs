@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::NaiveDateTime;
+use crate::decimal::Decimal;
+use bigdecimal::BigDecimal;
 
 // User model for inserting
 #[derive(diesel::Insertable)]
@@ -144,10 +146,11 @@ pub struct NewOrder {
     pub market_id: Uuid,
     pub order_type: String, // Will be converted from OrderType enum
     pub order_kind: String, // Will be converted from OrderKind enum
-    pub price: Option<i64>, // NULL for market orders
-    pub quantity: i64,
-    pub filled_quantity: Option<i64>, // Optional since it has a default
+    pub price: Option<BigDecimal>, // NULL for market orders
+    pub quantity: BigDecimal,
+    pub filled_quantity: Option<BigDecimal>, // Optional since it has a default
     pub status: Option<String>, // Optional since it has a default
+    pub client_order_id: Option<String>, // Unique per user when set
 }
 
 #[derive(diesel::Queryable, diesel::Selectable, Serialize)]
@@ -159,12 +162,13 @@ pub struct Order {
     pub market_id: Uuid,
     pub order_type: String,
     pub order_kind: String,
-    pub price: Option<i64>,
-    pub quantity: i64,
-    pub filled_quantity: i64,
+    pub price: Option<BigDecimal>,
+    pub quantity: BigDecimal,
+    pub filled_quantity: BigDecimal,
     pub status: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub client_order_id: Option<String>,
 }
 
 // Trade models
@@ -172,13 +176,17 @@ pub struct Order {
 #[diesel(table_name = crate::schema::trades)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct NewTrade {
+    pub id: Uuid,
     pub market_id: Uuid,
     pub buyer_order_id: Uuid,
     pub seller_order_id: Uuid,
     pub buyer_user_id: Uuid,
     pub seller_user_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub taker_side: Option<String>, // "BUY" or "SELL"
+    pub executed_at: NaiveDateTime, // engine match time
+    pub event_seq: Option<i64>,     // engine event sequence; orders trades within the same millisecond
 }
 
 #[derive(diesel::Queryable, diesel::Selectable, Serialize)]
@@ -191,9 +199,12 @@ pub struct Trade {
     pub seller_order_id: Uuid,
     pub buyer_user_id: Uuid,
     pub seller_user_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
     pub created_at: NaiveDateTime,
+    pub taker_side: Option<String>, // "BUY" or "SELL"; null for trades from before it was recorded
+    pub executed_at: NaiveDateTime,
+    pub event_seq: Option<i64>,     // null for trades from before it was recorded
 }
 
 // Response models for API (with joined data)
@@ -204,12 +215,13 @@ pub struct OrderResponse {
     pub market: Market,
     pub order_type: String,
     pub order_kind: String,
-    pub price: Option<i64>,
-    pub quantity: i64,
-    pub filled_quantity: i64,
+    pub price: Option<BigDecimal>,
+    pub quantity: BigDecimal,
+    pub filled_quantity: BigDecimal,
     pub status: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub client_order_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -218,8 +230,8 @@ pub struct TradeResponse {
     pub market: Market,
     pub buyer_order_id: Uuid,
     pub seller_order_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
     pub created_at: NaiveDateTime,
 }
 
@@ -231,8 +243,8 @@ impl NewOrder {
         market_id: Uuid,
         order_type: OrderType,
         order_kind: OrderKind,
-        price: Option<i64>,
-        quantity: i64,
+        price: Option<BigDecimal>,
+        quantity: BigDecimal,
     ) -> Self {
         Self {
             id,
@@ -244,6 +256,7 @@ impl NewOrder {
             quantity,
             filled_quantity: None, // Will use default (0)
             status: None, // Will use default (PENDING)
+            client_order_id: None,
         }
     }
 }
@@ -283,8 +296,8 @@ impl Order {
 pub struct NewBalance {
     pub user_id: Uuid,
     pub token_id: Uuid,
-    pub amount: Option<i64>, // Optional since it has a default of 0
-    pub locked_amount: Option<i64>, // Optional since it has a default of 0
+    pub amount: Option<BigDecimal>, // Optional since it has a default of 0
+    pub locked_amount: Option<BigDecimal>, // Optional since it has a default of 0
 }
 
 #[derive(diesel::Queryable, diesel::Selectable, Serialize)]
@@ -294,8 +307,8 @@ pub struct Balance {
     pub id: Uuid,
     pub user_id: Uuid,
     pub token_id: Uuid,
-    pub amount: i64,
-    pub locked_amount: i64,
+    pub amount: BigDecimal,
+    pub locked_amount: BigDecimal,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -304,9 +317,9 @@ pub struct Balance {
 #[derive(Serialize)]
 pub struct BalanceResponse {
     pub token: Token,
-    pub amount: i64,
-    pub locked_amount: i64,
-    pub available_amount: i64, // amount - locked_amount
+    pub amount: BigDecimal,
+    pub locked_amount: BigDecimal,
+    pub available_amount: BigDecimal, // amount - locked_amount
 }
 
 #[derive(Serialize)]
@@ -318,45 +331,46 @@ pub struct UserBalancesResponse {
 #[derive(Deserialize, Debug)]
 pub struct DecimalDepositRequest {
     pub token_id: Uuid,
-    pub amount: f64,
+    pub amount: Decimal,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct DecimalWithdrawRequest {
     pub token_id: Uuid,
-    pub amount: f64, 
+    pub amount: Decimal,
 }
 
 #[derive(Serialize)]
 pub struct TransactionResponse {
     pub success: bool,
     pub message: String,
-    pub new_balance: Option<i64>,
+    #[serde(default, with = "crate::amount::option_string")]
+    pub new_balance: Option<i128>,
 }
 
 // Helper implementations
 impl NewBalance {
-    pub fn new(user_id: Uuid, token_id: Uuid, amount: i64) -> Self {
+    pub fn new(user_id: Uuid, token_id: Uuid, amount: BigDecimal) -> Self {
         Self {
             user_id,
             token_id,
             amount: Some(amount),
-            locked_amount: Some(0),
+            locked_amount: Some(BigDecimal::from(0)),
         }
     }
 }
 
 impl Balance {
-    pub fn available_amount(&self) -> i64 {
-        self.amount - self.locked_amount
+    pub fn available_amount(&self) -> BigDecimal {
+        &self.amount - &self.locked_amount
     }
     
-    pub fn can_withdraw(&self, amount: i64) -> bool {
-        self.available_amount() >= amount
+    pub fn can_withdraw(&self, amount: &BigDecimal) -> bool {
+        &self.available_amount() >= amount
     }
     
-    pub fn can_lock(&self, amount: i64) -> bool {
-        self.available_amount() >= amount
+    pub fn can_lock(&self, amount: &BigDecimal) -> bool {
+        &self.available_amount() >= amount
     }
 }
 
@@ -364,9 +378,9 @@ impl From<(Balance, Token)> for BalanceResponse {
     fn from((balance, token): (Balance, Token)) -> Self {
         Self {
             token,
+            available_amount: balance.available_amount(),
             amount: balance.amount,
             locked_amount: balance.locked_amount,
-            available_amount: balance.available_amount(),
         }
     }
 }
\ No newline at end of file
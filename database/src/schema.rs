@@ -5,13 +5,29 @@ diesel::table! {
         id -> Uuid,
         user_id -> Uuid,
         token_id -> Uuid,
-        amount -> Int8,
-        locked_amount -> Int8,
+        amount -> Numeric,
+        locked_amount -> Numeric,
         created_at -> Timestamp,
         updated_at -> Timestamp,
     }
 }
 
+diesel::table! {
+    candles (market_id, interval, open_time) {
+        market_id -> Uuid,
+        #[max_length = 3]
+        interval -> Varchar,
+        open_time -> Timestamp,
+        open -> Numeric,
+        high -> Numeric,
+        low -> Numeric,
+        close -> Numeric,
+        volume -> Numeric,
+        trade_count -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     markets (id) {
         id -> Uuid,
@@ -35,13 +51,15 @@ diesel::table! {
         order_type -> Varchar,
         #[max_length = 10]
         order_kind -> Varchar,
-        price -> Nullable<Int8>,
-        quantity -> Int8,
-        filled_quantity -> Int8,
+        price -> Nullable<Numeric>,
+        quantity -> Numeric,
+        filled_quantity -> Numeric,
         #[max_length = 20]
         status -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        #[max_length = 64]
+        client_order_id -> Nullable<Varchar>,
     }
 }
 
@@ -64,11 +82,15 @@ diesel::table! {
         market_id -> Uuid,
         buyer_order_id -> Uuid,
         seller_order_id -> Uuid,
-        price -> Int8,
-        quantity -> Int8,
+        price -> Numeric,
+        quantity -> Numeric,
         created_at -> Timestamp,
         buyer_user_id -> Uuid,
         seller_user_id -> Uuid,
+        #[max_length = 10]
+        taker_side -> Nullable<Varchar>,
+        executed_at -> Timestamp,
+        event_seq -> Nullable<Int8>,
     }
 }
 
@@ -84,12 +106,14 @@ diesel::table! {
 
 diesel::joinable!(balances -> tokens (token_id));
 diesel::joinable!(balances -> users (user_id));
+diesel::joinable!(candles -> markets (market_id));
 diesel::joinable!(orders -> markets (market_id));
 diesel::joinable!(orders -> users (user_id));
 diesel::joinable!(trades -> markets (market_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     balances,
+    candles,
     markets,
     orders,
     tokens,
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Numeric, Timestamp, Uuid as SqlUuid, Varchar};
+use serde::Serialize;
+use uuid::Uuid;
+
+use database::amount::{from_numeric, to_numeric};
+use database::schema::{markets, tokens};
+use database::{Candle, CandleInterval, Decimal, DecimalError};
+
+/// What is needed to render a market's atomic prices and quantities as decimals
+#[derive(Debug, Clone, Copy)]
+pub struct MarketDecimals {
+    pub price_decimals: u32,    // quote token decimals
+    pub quantity_decimals: u32, // base token decimals
+    pub tick_size: i64,
+}
+
+pub fn load_market_decimals(conn: &mut PgConnection) -> QueryResult<HashMap<Uuid, MarketDecimals>> {
+    let (base, quote) = diesel::alias!(tokens as base, tokens as quote);
+    let rows: Vec<(Uuid, i64, i32, i32)> = markets::table
+        .inner_join(base.on(base.field(tokens::id).eq(markets::base_currency_id)))
+        .inner_join(quote.on(quote.field(tokens::id).eq(markets::quote_currency_id)))
+        .select((markets::id, markets::tick_size, base.field(tokens::decimals), quote.field(tokens::decimals)))
+        .load(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(market_id, tick_size, base_decimals, quote_decimals)| {
+            (market_id, MarketDecimals {
+                price_decimals: quote_decimals.max(0) as u32,
+                quantity_decimals: base_decimals.max(0) as u32,
+                tick_size,
+            })
+        })
+        .collect())
+}
+
+/// Fold one executed trade into the bar of every interval; returns the updated bars
+pub fn apply_trade(
+    conn: &mut PgConnection,
+    market_id: Uuid,
+    price: i128,
+    quantity: i128,
+    timestamp_ms: i64,
+) -> QueryResult<Vec<Candle>> {
+    let mut updated = Vec::with_capacity(CandleInterval::ALL.len());
+    for interval in CandleInterval::ALL {
+        let Some(open_time) = chrono::DateTime::from_timestamp_millis(interval.open_time(timestamp_ms)) else {
+            continue;
+        };
+        let candle = diesel::sql_query(
+            "INSERT INTO candles (market_id, interval, open_time, open, high, low, close, volume, trade_count)
+             VALUES ($1, $2, $3, $4, $4, $4, $4, $5, 1)
+             ON CONFLICT (market_id, interval, open_time) DO UPDATE SET
+                 high = GREATEST(candles.high, EXCLUDED.high),
+                 low = LEAST(candles.low, EXCLUDED.low),
+                 close = EXCLUDED.close,
+                 volume = candles.volume + EXCLUDED.volume,
+                 trade_count = candles.trade_count + 1,
+                 updated_at = NOW()
+             RETURNING *",
+        )
+        .bind::<SqlUuid, _>(market_id)
+        .bind::<Varchar, _>(interval.as_str())
+        .bind::<Timestamp, _>(open_time.naive_utc())
+        .bind::<Numeric, _>(to_numeric(price))
+        .bind::<Numeric, _>(to_numeric(quantity))
+        .get_result::<Candle>(conn)?;
+        updated.push(candle);
+    }
+    Ok(updated)
+}
+
+/// Rebuild every interval's bars from the `trades` table. Trades are bucketed and ordered by
+/// engine match time like `apply_trade`; existing bars are overwritten, so this is safe to run repeatedly.
+pub fn backfill(conn: &mut PgConnection) -> QueryResult<usize> {
+    let mut rows = 0;
+    for interval in CandleInterval::ALL {
+        rows += diesel::sql_query(
+            "INSERT INTO candles (market_id, interval, open_time, open, high, low, close, volume, trade_count)
+             SELECT market_id, $1, bucket,
+                    (array_agg(price ORDER BY executed_at, event_seq NULLS FIRST, id))[1],
+                    MAX(price), MIN(price),
+                    (array_agg(price ORDER BY executed_at DESC, event_seq DESC NULLS LAST, id DESC))[1],
+                    SUM(quantity), COUNT(*)::INTEGER
+             FROM (
+                 SELECT *, to_timestamp(floor(extract(epoch FROM executed_at) / $2) * $2) AT TIME ZONE 'UTC' AS bucket
+                 FROM trades
+             ) bucketed
+             GROUP BY market_id, bucket
+             ON CONFLICT (market_id, interval, open_time) DO UPDATE SET
+                 open = EXCLUDED.open,
+                 high = EXCLUDED.high,
+                 low = EXCLUDED.low,
+                 close = EXCLUDED.close,
+                 volume = EXCLUDED.volume,
+                 trade_count = EXCLUDED.trade_count,
+                 updated_at = NOW()",
+        )
+        .bind::<Varchar, _>(interval.as_str())
+        .bind::<BigInt, _>(interval.seconds())
+        .execute(conn)?;
+    }
+    Ok(rows)
+}
+
+/// In-progress bar published on `candles:<market_id>`
+#[derive(Debug, Serialize)]
+pub struct CandleUpdate {
+    pub market_id: Uuid,
+    pub interval: String,
+    pub open_time: i64, // ms since epoch
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trade_count: i32,
+    pub price_decimals: u32,
+    pub quantity_decimals: u32,
+}
+
+impl CandleUpdate {
+    pub fn new(candle: &Candle, decimals: MarketDecimals) -> Result<Self, DecimalError> {
+        let price = |value| -> Result<Decimal, DecimalError> {
+            Ok(Decimal::from_atomic_at_step(from_numeric(value)?, decimals.price_decimals, decimals.tick_size))
+        };
+        Ok(CandleUpdate {
+            market_id: candle.market_id,
+            interval: candle.interval.clone(),
+            open_time: candle.open_time.and_utc().timestamp_millis(),
+            open: price(&candle.open)?,
+            high: price(&candle.high)?,
+            low: price(&candle.low)?,
+            close: price(&candle.close)?,
+            volume: Decimal::from_atomic(from_numeric(&candle.volume)?, decimals.quantity_decimals),
+            trade_count: candle.trade_count,
+            price_decimals: decimals.price_decimals,
+            quantity_decimals: decimals.quantity_decimals,
+        })
+    }
+}
@@ -2,9 +2,12 @@ use redis::{Client, Value};
 use serde::{Deserialize, Serialize};
 use diesel::prelude::*;
 use database::{establish_connection, schema, NewOrder, NewTrade, Order, Trade, Balance};
+use database::amount::to_numeric;
 use uuid::Uuid;
 use std::collections::HashMap;
 
+mod candles;
+
 // This enum MUST match exactly what the engine sends
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type", content = "data")]
@@ -15,8 +18,8 @@ enum DBUpdateEvent {
     BalanceUpdated { 
         user_id: Uuid, 
         token_id: Uuid, 
-        available: i64, 
-        locked: i64 
+        available: i128, 
+        locked: i128 
     },
 }
 
@@ -28,11 +31,16 @@ struct EngineOrder {
     pub market_id: Uuid,
     pub order_type: EngineOrderType,
     pub order_kind: EngineOrderKind,
-    pub price: Option<i64>,
-    pub quantity: i64,
-    pub filled_quantity: i64,
+    #[serde(default, with = "database::amount::option_string")]
+    pub price: Option<i128>,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
+    #[serde(with = "database::amount::string")]
+    pub filled_quantity: i128,
     pub status: EngineOrderStatus,
     pub created_at: i64,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -43,9 +51,13 @@ struct EngineTrade {
     pub seller_order_id: Uuid,
     pub buyer_user_id: Uuid,
     pub seller_user_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    #[serde(with = "database::amount::string")]
+    pub price: i128,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
     pub timestamp: i64,
+    #[serde(default)]
+    pub taker_side: Option<EngineOrderType>,
 }
 
 // These enums match exactly what the engine sends
@@ -58,9 +70,57 @@ enum EngineOrderKind { Market, Limit }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 enum EngineOrderStatus { Pending, PartiallyFilled, Filled, Cancelled }
 
+/// Sequence stamp the engine puts on every `db_update_queue` entry
+#[derive(Debug, Clone, Copy, Default)]
+struct EventSeq {
+    seq: u64,
+    prev_seq: u64,
+}
+
+/// A `seq` this far below the last applied one cannot be a redelivery; the engine's counter was reset
+const RESET_DISTANCE: u64 = 100_000;
+
+/// Tracks the last applied engine sequence so redelivered entries are skipped and gaps are reported
+#[derive(Debug, Default, Clone, Copy)]
+struct SequenceTracker {
+    last_seq: u64,
+}
+
+impl SequenceTracker {
+    /// Returns false when the event was already applied
+    fn accept(&mut self, stream_id: &str, stamp: EventSeq) -> bool {
+        // Entries written before the engine stamped events carry no sequence
+        if stamp.seq == 0 {
+            return true;
+        }
+        if stamp.seq <= self.last_seq {
+            // An engine that lost `engine:event_seq` starts over with no previous event
+            if stamp.prev_seq == 0 || self.last_seq - stamp.seq >= RESET_DISTANCE {
+                tracing::warn!(
+                    "🔄 Engine sequence reset at {}: seq {} after {}, resuming from it",
+                    stream_id, stamp.seq, self.last_seq
+                );
+                self.last_seq = stamp.seq;
+                return true;
+            }
+            tracing::warn!("⏭️ Skipping duplicate event {} (seq {} <= {})", stream_id, stamp.seq, self.last_seq);
+            return false;
+        }
+        if self.last_seq != 0 && stamp.prev_seq != 0 && stamp.prev_seq != self.last_seq {
+            tracing::error!(
+                "⚠️ Sequence gap before {}: expected prev_seq {}, got {} (seq {})",
+                stream_id, self.last_seq, stamp.prev_seq, stamp.seq
+            );
+        }
+        self.last_seq = stamp.seq;
+        true
+    }
+}
+
 #[derive(Debug, Clone)]
 struct PendingUpdate {
     stream_id: String,
+    seq: u64,
     event: DBUpdateEvent,
 }
 
@@ -75,6 +135,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut conn = redis_client.get_async_connection().await?;
     let mut db_conn = establish_connection();
     
+    if std::env::args().any(|arg| arg == "--backfill-candles") {
+        let rows = candles::backfill(&mut db_conn)?;
+        tracing::info!("🕯️ Backfilled {} candles from trades", rows);
+    }
+    let mut market_decimals = candles::load_market_decimals(&mut db_conn)?;
+    
     let consumer_group = "db_updater_group";
     let consumer_name = "db_updater_1";
     
@@ -88,7 +154,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .query_async(&mut conn)
         .await;
     
-    tracing::info!("📥 Listening for database updates...");
+    let last_seq: Option<u64> = redis::cmd("GET")
+        .arg("db_updater:last_seq")
+        .query_async(&mut conn)
+        .await
+        .unwrap_or(None);
+    let mut sequencer = SequenceTracker { last_seq: last_seq.unwrap_or(0) };
+    
+    tracing::info!("📥 Listening for database updates (last seq {})...", sequencer.last_seq);
     
     loop {
         // Read from queue with consumer group
@@ -110,17 +183,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         if let Some(updates) = parse_db_updates(results) {
             tracing::info!("📥 Received {} updates", updates.len());
             
+            // Drop redelivered events, but still acknowledge them. The tracker only
+            // moves forward once the batch is committed.
+            let mut batch_sequencer = sequencer;
+            let mut duplicate_stream_ids = Vec::new();
+            let updates: Vec<(String, u64, DBUpdateEvent)> = updates
+                .into_iter()
+                .filter_map(|(stream_id, stamp, event)| {
+                    if batch_sequencer.accept(&stream_id, stamp) {
+                        Some((stream_id, stamp.seq, event))
+                    } else {
+                        duplicate_stream_ids.push(stream_id);
+                        None
+                    }
+                })
+                .collect();
+            
             // Group and order updates
             let ordered_updates = order_updates_by_dependencies(updates);
             println!("ordered_updates: {:?}", ordered_updates);
             
             // Process in dependency order within a transaction
-            db_conn.transaction::<_, Box<dyn std::error::Error>, _>(|tx_conn| {
+            let (_processed, updated_candles) = db_conn.transaction::<_, Box<dyn std::error::Error>, _>(|tx_conn| {
                 let mut processed_stream_ids = Vec::new();
+                let mut updated_candles = Vec::new();
                 
                 // Process orders first (they are dependencies for trades)
                 for update in &ordered_updates.orders {
-                    match process_db_update(update.event.clone(), tx_conn) {
+                    match process_db_update(update.event.clone(), update.seq, tx_conn) {
                         Ok(_) => {
                             processed_stream_ids.push(update.stream_id.clone());
                             tracing::info!("✅ Processed order update: {}", update.stream_id);
@@ -134,8 +224,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 
                 // Then process trades (they depend on orders)
                 for update in &ordered_updates.trades {
-                    match process_db_update(update.event.clone(), tx_conn) {
+                    match process_db_update(update.event.clone(), update.seq, tx_conn) {
                         Ok(_) => {
+                            if let DBUpdateEvent::TradeExecuted(trade) = &update.event {
+                                updated_candles.extend(candles::apply_trade(
+                                    tx_conn, trade.market_id, trade.price, trade.quantity, trade.timestamp,
+                                )?);
+                            }
                             processed_stream_ids.push(update.stream_id.clone());
                             tracing::info!("✅ Processed trade update: {}", update.stream_id);
                         }
@@ -148,7 +243,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 
                 // Finally process balances (they can be processed independently)
                 for update in &ordered_updates.balances {
-                    match process_db_update(update.event.clone(), tx_conn) {
+                    match process_db_update(update.event.clone(), update.seq, tx_conn) {
                         Ok(_) => {
                             processed_stream_ids.push(update.stream_id.clone());
                             tracing::info!("✅ Processed balance update: {}", update.stream_id);
@@ -160,11 +255,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
                 
-                Ok(processed_stream_ids)
+                Ok((processed_stream_ids, updated_candles))
             })?;
             
+            sequencer = batch_sequencer;
+            let _: () = redis::cmd("SET")
+                .arg("db_updater:last_seq")
+                .arg(sequencer.last_seq)
+                .query_async(&mut conn)
+                .await?;
+            
+            // Push the latest state of every touched bar once the batch is committed
+            let mut latest: HashMap<(Uuid, String, chrono::NaiveDateTime), database::Candle> = HashMap::new();
+            for candle in updated_candles {
+                latest.insert((candle.market_id, candle.interval.clone(), candle.open_time), candle);
+            }
+            for candle in latest.into_values() {
+                if !market_decimals.contains_key(&candle.market_id) {
+                    market_decimals = candles::load_market_decimals(&mut db_conn)?;
+                }
+                let Some(&decimals) = market_decimals.get(&candle.market_id) else { continue };
+                // The batch is already committed; a bar that cannot be rendered is reported, not published
+                let update = match candles::CandleUpdate::new(&candle, decimals) {
+                    Ok(update) => update,
+                    Err(e) => {
+                        tracing::error!("❌ Bad candle {} {} {}: {}", candle.market_id, candle.interval, candle.open_time, e);
+                        continue;
+                    }
+                };
+                let payload = serde_json::to_string(&update)?;
+                let _: () = redis::cmd("PUBLISH")
+                    .arg(format!("candles:{}", candle.market_id))
+                    .arg(payload)
+                    .query_async(&mut conn)
+                    .await?;
+            }
+            
             // Acknowledge all successfully processed messages
-            for stream_id in &ordered_updates.all_stream_ids {
+            for stream_id in ordered_updates.all_stream_ids.iter().chain(&duplicate_stream_ids) {
                 let _: () = redis::cmd("XACK")
                     .arg("db_update_queue")
                     .arg(consumer_group)
@@ -191,24 +319,24 @@ struct OrderedUpdates {
 }
 
 /// Order updates by dependencies: orders first, then trades, then balances
-fn order_updates_by_dependencies(updates: Vec<(String, DBUpdateEvent)>) -> OrderedUpdates {
+fn order_updates_by_dependencies(updates: Vec<(String, u64, DBUpdateEvent)>) -> OrderedUpdates {
     let mut orders = Vec::new();
     let mut trades = Vec::new();
     let mut balances = Vec::new();
     let mut all_stream_ids = Vec::new();
     
-    for (stream_id, event) in updates {
+    for (stream_id, seq, event) in updates {
         all_stream_ids.push(stream_id.clone());
         
         match &event {
             DBUpdateEvent::OrderCreated(_) | DBUpdateEvent::OrderUpdated(_) => {
-                orders.push(PendingUpdate { stream_id, event });
+                orders.push(PendingUpdate { stream_id, seq, event });
             }
             DBUpdateEvent::TradeExecuted(_) => {
-                trades.push(PendingUpdate { stream_id, event });
+                trades.push(PendingUpdate { stream_id, seq, event });
             }
             DBUpdateEvent::BalanceUpdated { .. } => {
-                balances.push(PendingUpdate { stream_id, event });
+                balances.push(PendingUpdate { stream_id, seq, event });
             }
         }
     }
@@ -225,7 +353,7 @@ fn order_updates_by_dependencies(updates: Vec<(String, DBUpdateEvent)>) -> Order
 }
 
 /// Parse Redis stream response into database update events
-fn parse_db_updates(results: Value) -> Option<Vec<(String, DBUpdateEvent)>> {
+fn parse_db_updates(results: Value) -> Option<Vec<(String, EventSeq, DBUpdateEvent)>> {
     match results {
         Value::Bulk(streams) => {
             let mut updates = Vec::new();
@@ -245,8 +373,8 @@ fn parse_db_updates(results: Value) -> Option<Vec<(String, DBUpdateEvent)>> {
                                         
                                         // Extract fields
                                         if let Value::Bulk(fields) = &msg_data[1] {
-                                            if let Some(update) = parse_message_fields(fields) {
-                                                updates.push((stream_id, update));
+                                            if let Some((stamp, update)) = parse_message_fields(fields) {
+                                                updates.push((stream_id, stamp, update));
                                             } else {
                                                 tracing::warn!("Failed to parse message fields for {}", stream_id);
                                             }
@@ -269,7 +397,7 @@ fn parse_db_updates(results: Value) -> Option<Vec<(String, DBUpdateEvent)>> {
 }
 
 /// Parse message fields into database update event
-fn parse_message_fields(fields: &[Value]) -> Option<DBUpdateEvent> {
+fn parse_message_fields(fields: &[Value]) -> Option<(EventSeq, DBUpdateEvent)> {
     let mut field_map = std::collections::HashMap::new();
     
     // Parse key-value pairs
@@ -291,10 +419,15 @@ fn parse_message_fields(fields: &[Value]) -> Option<DBUpdateEvent> {
     let event_type = field_map.get("type")?;
     let data_json = field_map.get("data")?;
     
-    tracing::debug!("Parsing event type: {}", event_type);
+    let stamp = EventSeq {
+        seq: field_map.get("seq").and_then(|s| s.parse().ok()).unwrap_or(0),
+        prev_seq: field_map.get("prev_seq").and_then(|s| s.parse().ok()).unwrap_or(0),
+    };
+    
+    tracing::debug!("Parsing event type: {} (seq {})", event_type, stamp.seq);
     
     // Parse based on event type
-    match event_type.as_str() {
+    let event = match event_type.as_str() {
         "order_created" => {
             match serde_json::from_str::<EngineOrder>(data_json) {
                 Ok(order) => Some(DBUpdateEvent::OrderCreated(order)),
@@ -327,8 +460,10 @@ fn parse_message_fields(fields: &[Value]) -> Option<DBUpdateEvent> {
             struct BalanceUpdateData {
                 user_id: Uuid,
                 token_id: Uuid,
-                available: i64,
-                locked: i64,
+                #[serde(with = "database::amount::string")]
+                available: i128,
+                #[serde(with = "database::amount::string")]
+                locked: i128,
             }
             
             match serde_json::from_str::<BalanceUpdateData>(data_json) {
@@ -348,12 +483,15 @@ fn parse_message_fields(fields: &[Value]) -> Option<DBUpdateEvent> {
             tracing::warn!("Unknown event type: {}", event_type);
             None
         }
-    }
+    }?;
+    
+    Some((stamp, event))
 }
 
-/// Process database update event
+/// Process database update event; `seq` is the engine's stamp on it (0 when unstamped)
 fn process_db_update(
     update: DBUpdateEvent, 
+    seq: u64,
     db_conn: &mut PgConnection
 ) -> Result<(), Box<dyn std::error::Error>> {
     
@@ -375,22 +513,22 @@ fn process_db_update(
                     EngineOrderKind::Market => "MARKET".to_string(),
                     EngineOrderKind::Limit => "LIMIT".to_string(),
                 },
-                price: order_data.price,
-                quantity: order_data.quantity,
-                filled_quantity: Some(order_data.filled_quantity),
+                price: order_data.price.map(to_numeric),
+                quantity: to_numeric(order_data.quantity),
+                filled_quantity: Some(to_numeric(order_data.filled_quantity)),
                 status: Some(match order_data.status {
                     EngineOrderStatus::Pending => "PENDING".to_string(),
                     EngineOrderStatus::PartiallyFilled => "PARTIALLY_FILLED".to_string(),
                     EngineOrderStatus::Filled => "FILLED".to_string(),
                     EngineOrderStatus::Cancelled => "CANCELLED".to_string(),
                 }),
+                client_order_id: order_data.client_order_id.clone(),
             };
             
-            // Use INSERT ON CONFLICT for idempotency
+            // Use INSERT ON CONFLICT for idempotency (id or per-user client_order_id)
             diesel::insert_into(orders::table)
                 .values(&new_order)
-                .on_conflict(orders::id)
-                .do_nothing()
+                .on_conflict_do_nothing()
                 .execute(db_conn)?;
                 
             tracing::debug!("✅ Order {} created successfully", order_data.id);
@@ -409,7 +547,7 @@ fn process_db_update(
             // First try to update existing order
             let updated_rows = diesel::update(orders::table.find(order_data.id))
                 .set((
-                    orders::filled_quantity.eq(order_data.filled_quantity),
+                    orders::filled_quantity.eq(to_numeric(order_data.filled_quantity)),
                     orders::status.eq(status_str),
                     orders::updated_at.eq(diesel::dsl::now),
                 ))
@@ -431,16 +569,16 @@ fn process_db_update(
                         EngineOrderKind::Market => "MARKET".to_string(),
                         EngineOrderKind::Limit => "LIMIT".to_string(),
                     },
-                    price: order_data.price,
-                    quantity: order_data.quantity,
-                    filled_quantity: Some(order_data.filled_quantity),
+                    price: order_data.price.map(to_numeric),
+                    quantity: to_numeric(order_data.quantity),
+                    filled_quantity: Some(to_numeric(order_data.filled_quantity)),
                     status: Some(status_str.to_string()),
+                    client_order_id: order_data.client_order_id.clone(),
                 };
                 
                 diesel::insert_into(orders::table)
                     .values(&new_order)
-                    .on_conflict(orders::id)
-                    .do_nothing()
+                    .on_conflict_do_nothing()
                     .execute(db_conn)?;
             }
                 
@@ -450,14 +588,24 @@ fn process_db_update(
         DBUpdateEvent::TradeExecuted(trade_data) => {
             tracing::info!("💾 Creating trade {} in database", trade_data.id);
             
+            let Some(executed_at) = chrono::DateTime::from_timestamp_millis(trade_data.timestamp) else {
+                return Err(format!("Trade {} has an invalid timestamp {}", trade_data.id, trade_data.timestamp).into());
+            };
             let new_trade = NewTrade {
+                id: trade_data.id,
                 market_id: trade_data.market_id,
                 buyer_order_id: trade_data.buyer_order_id,
                 seller_order_id: trade_data.seller_order_id,
                 buyer_user_id: trade_data.buyer_user_id,
                 seller_user_id: trade_data.seller_user_id,
-                price: trade_data.price,
-                quantity: trade_data.quantity,
+                price: to_numeric(trade_data.price),
+                quantity: to_numeric(trade_data.quantity),
+                taker_side: trade_data.taker_side.as_ref().map(|side| match side {
+                    EngineOrderType::Buy => "BUY".to_string(),
+                    EngineOrderType::Sell => "SELL".to_string(),
+                }),
+                executed_at: executed_at.naive_utc(),
+                event_seq: (seq != 0).then_some(seq as i64),
             };
             
             // Use INSERT ON CONFLICT for idempotency
@@ -480,8 +628,8 @@ fn process_db_update(
                     .filter(balances::token_id.eq(token_id))
             )
             .set((
-                balances::amount.eq(available),
-                balances::locked_amount.eq(locked),
+                balances::amount.eq(to_numeric(available)),
+                balances::locked_amount.eq(to_numeric(locked)),
                 balances::updated_at.eq(diesel::dsl::now),
             ))
             .execute(db_conn)?;
@@ -493,8 +641,8 @@ fn process_db_update(
                 let new_balance = NewBalance {
                     user_id,
                     token_id,
-                    amount: Some(available),
-                    locked_amount: Some(locked),
+                    amount: Some(to_numeric(available)),
+                    locked_amount: Some(to_numeric(locked)),
                 };
                 
                 diesel::insert_into(balances::table)
@@ -502,8 +650,8 @@ fn process_db_update(
                     .on_conflict((balances::user_id, balances::token_id))
                     .do_update()
                     .set((
-                        balances::amount.eq(available),
-                        balances::locked_amount.eq(locked),
+                        balances::amount.eq(to_numeric(available)),
+                        balances::locked_amount.eq(to_numeric(locked)),
                         balances::updated_at.eq(diesel::dsl::now),
                     ))
                     .execute(db_conn)?;
@@ -516,4 +664,61 @@ fn process_db_update(
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(seq: u64, prev_seq: u64) -> EventSeq {
+        EventSeq { seq, prev_seq }
+    }
+
+    #[test]
+    fn duplicates_are_dropped() {
+        let mut tracker = SequenceTracker::default();
+        assert!(tracker.accept("1-0", stamp(1, 0)));
+        assert!(tracker.accept("2-0", stamp(2, 1)));
+        assert!(tracker.accept("3-0", stamp(3, 2)));
+        assert!(!tracker.accept("3-0", stamp(3, 2)));
+        assert!(!tracker.accept("2-0", stamp(2, 1)));
+        assert_eq!(tracker.last_seq, 3);
+    }
+
+    #[test]
+    fn gaps_are_applied() {
+        let mut tracker = SequenceTracker { last_seq: 3 };
+        assert!(tracker.accept("9-0", stamp(9, 8)));
+        assert_eq!(tracker.last_seq, 9);
+        // The missing events are not applied when they turn up late
+        assert!(!tracker.accept("5-0", stamp(5, 4)));
+        assert_eq!(tracker.last_seq, 9);
+    }
+
+    #[test]
+    fn unstamped_entries_pass_through() {
+        let mut tracker = SequenceTracker { last_seq: 7 };
+        assert!(tracker.accept("1-0", EventSeq::default()));
+        assert!(tracker.accept("1-0", EventSeq::default()));
+        assert_eq!(tracker.last_seq, 7);
+    }
+
+    #[test]
+    fn restarted_counter_resets() {
+        let mut tracker = SequenceTracker { last_seq: 500 };
+        assert!(tracker.accept("1-0", stamp(1, 0)));
+        assert_eq!(tracker.last_seq, 1);
+        assert!(tracker.accept("2-0", stamp(2, 1)));
+    }
+
+    #[test]
+    fn far_behind_seq_resets() {
+        let last_seq = RESET_DISTANCE + 50;
+        let mut tracker = SequenceTracker { last_seq };
+        assert!(!tracker.accept("1-0", stamp(51, 50)), "just inside the distance is a redelivery");
+        assert!(tracker.accept("2-0", stamp(50, 49)));
+        assert_eq!(tracker.last_seq, 50);
+        assert!(tracker.accept("3-0", stamp(51, 50)));
+        assert_eq!(tracker.last_seq, 51);
+    }
+}
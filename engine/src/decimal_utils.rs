@@ -1,6 +1,8 @@
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
-use crate::trading_engine::MarketInfo;
+use crate::trading_engine::{MarketInfo, Order, OrderKind, OrderStatus, OrderType, TokenBalance, TokenInfo, Trade};
+use crate::redis_manager::EventMeta;
+use database::Decimal;
 
 #[derive(Debug)]
 pub enum ConversionError {
@@ -23,101 +25,108 @@ impl std::fmt::Display for ConversionError {
 
 /// Convert atomic units back to decimal for a specific token using decimals directly
 /// Example: 1500000000 lamports (with 9 decimals) -> 1.5 SOL
-pub fn from_atomic_units_with_decimals(atomic_amount: i64, decimals: i32) -> f64 {
-    let divisor = 10_f64.powi(decimals);
-    atomic_amount as f64 / divisor
+pub fn from_atomic_units_with_decimals(atomic_amount: i128, decimals: i32) -> Decimal {
+    Decimal::from_atomic(atomic_amount, decimals.max(0) as u32)
 }
 
-/// Convert atomic price back to decimal for a market using MarketInfo
-/// Example: atomic price in USDC -> 50.0 USDC per SOL
-pub fn price_from_atomic_units(atomic_price: i64, market_info: &MarketInfo) -> f64 {
+/// Convert atomic price back to decimal for a market using MarketInfo, rendered at tick precision
+/// Example: 50000000 with tick 10000 and 6 USDC decimals -> "50.00"
+pub fn price_from_atomic_units(atomic_price: i128, market_info: &MarketInfo) -> Decimal {
     // Price is in quote token units
-    from_atomic_units_with_decimals(atomic_price, market_info.quote_currency.decimals)
+    Decimal::from_atomic_at_step(atomic_price, market_info.quote_currency.decimals.max(0) as u32, market_info.tick_size)
 }
 
 /// Convert atomic quantity back to decimal for a market using MarketInfo
 /// Example: atomic quantity in SOL -> 1.0 SOL
-pub fn quantity_from_atomic_units(atomic_quantity: i64, market_info: &MarketInfo) -> f64 {
+pub fn quantity_from_atomic_units(atomic_quantity: i128, market_info: &MarketInfo) -> Decimal {
     // Quantity is in base token units
     from_atomic_units_with_decimals(atomic_quantity, market_info.base_currency.decimals)
 }
 
-/// Format price to tick size precision
-pub fn format_price_to_tick_precision(price: f64, tick_size: i64, quote_decimals: i32) -> f64 {
-    let tick_size_decimal = tick_size as f64 / 10_f64.powi(quote_decimals);
-    let decimal_places = (-tick_size_decimal.log10().floor()) as usize;
-    let multiplier = 10_f64.powi(decimal_places as i32);
-    (price * multiplier).round() / multiplier
-}
-
-/// Format quantity to base currency precision based on min_order_size
-pub fn format_quantity_to_precision(quantity: f64, min_order_size: i64, base_decimals: i32) -> f64 {
-    // Calculate how many decimal places the min_order_size represents
-    let min_order_decimal = (min_order_size as f64) / 10_f64.powi(base_decimals);
-    
-    // Get the number of decimal places needed
-    let decimal_places = if min_order_decimal < 1.0 {
-        (-min_order_decimal.log10().floor()) as i32
-    } else {
-        0
-    };
-    
-    // Format to that precision
-    let multiplier = 10_f64.powi(decimal_places);
-    (quantity * multiplier).round() / multiplier
+/// Token decimals sent with every feed payload so subscribers can recover atomic units
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FeedDecimals {
+    pub price_decimals: u32,    // quote token decimals
+    pub quantity_decimals: u32, // base token decimals
+}
+
+impl FeedDecimals {
+    pub fn of(market_info: &MarketInfo) -> Self {
+        FeedDecimals {
+            price_decimals: market_info.quote_currency.decimals.max(0) as u32,
+            quantity_decimals: market_info.base_currency.decimals.max(0) as u32,
+        }
+    }
 }
 
-/// Enhanced DepthUpdate struct with both decimal and atomic values
+/// Depth delta published on `depth:<market_id>`: only the levels that changed,
+/// with their new total quantity ("0" removes the level). `seq` increases by
+/// exactly one per delta, so a client applying deltas to a `DepthSnapshot` can
+/// detect a gap and refetch the snapshot.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnhancedDepthUpdate {
     pub market_id: Uuid,
     pub seq: u64,
     pub ts: i64,
-    pub bids: Vec<(f64, f64)>,        // Decimal values (price, quantity)
-    pub asks: Vec<(f64, f64)>,        // Decimal values (price, quantity)
-    pub bids_atomic: Vec<(i64, i64)>, // Atomic values for debugging
-    pub asks_atomic: Vec<(i64, i64)>, // Atomic values for debugging
+    pub bids: Vec<(Decimal, Decimal)>, // (price, new quantity)
+    pub asks: Vec<(Decimal, Decimal)>, // (price, new quantity)
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
+    #[serde(flatten)]
+    pub meta: EventMeta,
+}
+
+/// Full book as of delta `seq`, stored under `snapshot:depth:<market_id>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub market_id: Uuid,
+    pub seq: u64,
+    pub ts: i64,
+    pub bids: Vec<(Decimal, Decimal)>, // best (highest) first
+    pub asks: Vec<(Decimal, Decimal)>, // best (lowest) first
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
 }
 
-/// Enhanced MarketTicker struct with both decimal and atomic values
+/// Market ticker with exact decimal strings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnhancedMarketTicker {
     pub market_id: Uuid,
-    pub last_price: f64,              // Decimal price (e.g., 50.0 USDC)
-    pub last_price_atomic: i64,       // Atomic price for debugging
-    pub volume_24h: f64,              // Decimal volume (e.g., 10.5 SOL)
-    pub volume_24h_atomic: i64,       // Atomic volume for debugging
-    pub high_24h: f64,                // Decimal high
-    pub high_24h_atomic: i64,         // Atomic high for debugging
-    pub low_24h: f64,                 // Decimal low
-    pub low_24h_atomic: i64,          // Atomic low for debugging
-    pub change_24h: f64,
+    pub last_price: Decimal,          // Decimal price (e.g., "50.00" USDC)
+    pub open_24h: Decimal,            // first trade price in the window
+    pub volume_24h: Decimal,          // Base volume (e.g., "10.5" SOL)
+    pub quote_volume_24h: Decimal,    // Quote volume (e.g., "525.25" USDC)
+    pub high_24h: Decimal,
+    pub low_24h: Decimal,
+    pub trade_count_24h: u64,
+    pub change_24h: Decimal,          // percent
     pub timestamp: i64,
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
+    #[serde(flatten)]
+    pub meta: EventMeta,
 }
 
 /// Convert atomic MarketTicker to enhanced version with decimal values
-pub fn convert_ticker_to_decimal(atomic_ticker: &crate::trading_engine::MarketTicker, market_info: &MarketInfo) -> EnhancedMarketTicker {
-    let raw_price = price_from_atomic_units(atomic_ticker.last_price, market_info);
-    let raw_volume = quantity_from_atomic_units(atomic_ticker.volume_24h, market_info);
-    let raw_high = price_from_atomic_units(atomic_ticker.high_24h, market_info);
-    let raw_low = price_from_atomic_units(atomic_ticker.low_24h, market_info);
-
+pub fn convert_ticker_to_decimal(atomic_ticker: &crate::trading_engine::MarketTicker, market_info: &MarketInfo, meta: EventMeta) -> EnhancedMarketTicker {
     EnhancedMarketTicker {
         market_id: atomic_ticker.market_id,
-        last_price: format_price_to_tick_precision(raw_price, market_info.tick_size, market_info.quote_currency.decimals),
-        last_price_atomic: atomic_ticker.last_price,
-        volume_24h: format_quantity_to_precision(raw_volume, market_info.min_order_size, market_info.base_currency.decimals),
-        volume_24h_atomic: atomic_ticker.volume_24h,
-        high_24h: format_price_to_tick_precision(raw_high, market_info.tick_size, market_info.quote_currency.decimals),
-        high_24h_atomic: atomic_ticker.high_24h,
-        low_24h: format_price_to_tick_precision(raw_low, market_info.tick_size, market_info.quote_currency.decimals),
-        low_24h_atomic: atomic_ticker.low_24h,
+        last_price: price_from_atomic_units(atomic_ticker.last_price, market_info),
+        open_24h: price_from_atomic_units(atomic_ticker.open_24h, market_info),
+        volume_24h: quantity_from_atomic_units(atomic_ticker.volume_24h, market_info),
+        quote_volume_24h: from_atomic_units_with_decimals(atomic_ticker.quote_volume_24h, market_info.quote_currency.decimals),
+        high_24h: price_from_atomic_units(atomic_ticker.high_24h, market_info),
+        low_24h: price_from_atomic_units(atomic_ticker.low_24h, market_info),
+        trade_count_24h: atomic_ticker.trade_count_24h,
         change_24h: atomic_ticker.change_24h,
         timestamp: atomic_ticker.timestamp,
+        decimals: FeedDecimals::of(market_info),
+        meta,
     }
 }
 
-/// Enhanced Trade struct with both decimal and atomic values
+/// Trade with exact decimal strings, as published on the public `trades:<market_id>` feed.
+/// Client order ids are private and only go out on `orders:`/`fills:<user_id>`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnhancedTrade {
     pub id: Uuid,
@@ -126,17 +135,18 @@ pub struct EnhancedTrade {
     pub seller_order_id: Uuid,
     pub buyer_user_id: Uuid,
     pub seller_user_id: Uuid,
-    pub price: f64,              // Decimal price (e.g., 4021.0 USDC)
-    pub price_atomic: i64,       // Atomic price for debugging
-    pub quantity: f64,           // Decimal quantity (e.g., 0.001 ETH)
-    pub quantity_atomic: i64,    // Atomic quantity for debugging
+    pub price: Decimal,          // Decimal price (e.g., "4021.00" USDC)
+    pub quantity: Decimal,       // Decimal quantity (e.g., "0.001" ETH)
     pub timestamp: i64,
+    pub taker_side: OrderType,
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
+    #[serde(flatten)]
+    pub meta: EventMeta,
 }
 
 /// Convert atomic Trade to enhanced version with decimal values
-pub fn convert_trade_to_decimal(atomic_trade: &crate::trading_engine::Trade, market_info: &MarketInfo) -> EnhancedTrade {
-    let raw_price = price_from_atomic_units(atomic_trade.price, market_info);
-    let raw_quantity = quantity_from_atomic_units(atomic_trade.quantity, market_info);
+pub fn convert_trade_to_decimal(atomic_trade: &crate::trading_engine::Trade, market_info: &MarketInfo, meta: EventMeta) -> EnhancedTrade {
     EnhancedTrade {
         id: atomic_trade.id,
         market_id: atomic_trade.market_id,
@@ -144,10 +154,153 @@ pub fn convert_trade_to_decimal(atomic_trade: &crate::trading_engine::Trade, mar
         seller_order_id: atomic_trade.seller_order_id,
         buyer_user_id: atomic_trade.buyer_user_id,
         seller_user_id: atomic_trade.seller_user_id,
-        price: format_price_to_tick_precision(raw_price, market_info.tick_size, market_info.quote_currency.decimals),
-        price_atomic: atomic_trade.price,
-        quantity: format_quantity_to_precision(raw_quantity, market_info.min_order_size, market_info.base_currency.decimals),
-        quantity_atomic: atomic_trade.quantity,
+        price: price_from_atomic_units(atomic_trade.price, market_info),
+        quantity: quantity_from_atomic_units(atomic_trade.quantity, market_info),
         timestamp: atomic_trade.timestamp,
+        taker_side: atomic_trade.taker_side.clone(),
+        decimals: FeedDecimals::of(market_info),
+        meta,
+    }
+}
+/// Order-by-order book event; never carries user ids
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum L3Event {
+    /// Order started resting on the book with `size` remaining
+    Add { order_id: Uuid, side: L3Side, price: Decimal, size: Decimal },
+    /// Resting order's remaining size changed to `size`
+    Modify { order_id: Uuid, side: L3Side, price: Decimal, size: Decimal },
+    /// Order left the book
+    Remove { order_id: Uuid, side: L3Side, price: Decimal, reason: L3RemoveReason },
+    /// Resting order traded `size` against an incoming order
+    Execute { order_id: Uuid, side: L3Side, price: Decimal, size: Decimal, trade_id: Uuid },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum L3Side { Buy, Sell }
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum L3RemoveReason { Filled, Cancelled }
+
+/// L3 event published on `l3:<market_id>`; `seq` increases by exactly one per event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct L3Update {
+    pub market_id: Uuid,
+    pub seq: u64,
+    pub ts: i64,
+    #[serde(flatten)]
+    pub event: L3Event,
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
+    #[serde(flatten)]
+    pub meta: EventMeta,
+}
+
+/// Whether an order's side was resting (maker) or incoming (taker) in a fill
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liquidity { Maker, Taker }
+
+/// Order state change published on the owner's private `orders:<user_id>` channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderUpdate {
+    pub order_id: Uuid,
+    pub client_order_id: Option<String>,
+    pub market_id: Uuid,
+    pub side: OrderType,
+    pub kind: OrderKind,
+    pub status: OrderStatus,
+    pub price: Option<Decimal>, // None for market orders
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub ts: i64,
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
+    #[serde(flatten)]
+    pub meta: EventMeta,
+}
+
+pub fn convert_order_update(order: &Order, market_info: &MarketInfo, meta: EventMeta) -> OrderUpdate {
+    OrderUpdate {
+        order_id: order.id,
+        client_order_id: order.client_order_id.clone(),
+        market_id: order.market_id,
+        side: order.order_type.clone(),
+        kind: order.order_kind.clone(),
+        status: order.status.clone(),
+        price: order.price.map(|price| price_from_atomic_units(price, market_info)),
+        quantity: quantity_from_atomic_units(order.quantity, market_info),
+        filled_quantity: quantity_from_atomic_units(order.filled_quantity, market_info),
+        ts: chrono::Utc::now().timestamp_millis(),
+        decimals: FeedDecimals::of(market_info),
+        meta,
+    }
+}
+
+/// One side of a trade, published on that user's private `fills:<user_id>` channel.
+/// `quote_amount` is what the buyer paid or the seller received after rounding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FillUpdate {
+    pub trade_id: Uuid,
+    pub order_id: Uuid,
+    pub client_order_id: Option<String>,
+    pub market_id: Uuid,
+    pub side: OrderType,
+    pub liquidity: Liquidity,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub quote_amount: Decimal,
+    pub ts: i64,
+    #[serde(flatten)]
+    pub decimals: FeedDecimals,
+    #[serde(flatten)]
+    pub meta: EventMeta,
+}
+
+pub fn convert_fill(trade: &Trade, side: OrderType, liquidity: Liquidity, market_info: &MarketInfo, meta: EventMeta) -> FillUpdate {
+    let (order_id, client_order_id, quote_amount) = match side {
+        OrderType::Buy => (trade.buyer_order_id, trade.buyer_client_order_id.clone(), trade.buyer_cost),
+        OrderType::Sell => (trade.seller_order_id, trade.seller_client_order_id.clone(), trade.seller_proceeds),
+    };
+    FillUpdate {
+        trade_id: trade.id,
+        order_id,
+        client_order_id,
+        market_id: trade.market_id,
+        side,
+        liquidity,
+        price: price_from_atomic_units(trade.price, market_info),
+        quantity: quantity_from_atomic_units(trade.quantity, market_info),
+        quote_amount: from_atomic_units_with_decimals(quote_amount, market_info.quote_currency.decimals),
+        ts: trade.timestamp,
+        decimals: FeedDecimals::of(market_info),
+        meta,
+    }
+}
+
+/// New balance of one token, published on the owner's private `balances:<user_id>` channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceUpdate {
+    pub token_id: Uuid,
+    pub symbol: String,
+    pub available: Decimal,
+    pub locked: Decimal,
+    pub decimals: u32, // token decimals
+    pub ts: i64,
+    #[serde(flatten)]
+    pub meta: EventMeta,
+}
+
+pub fn convert_balance_update(token: &TokenInfo, balance: &TokenBalance, meta: EventMeta) -> BalanceUpdate {
+    BalanceUpdate {
+        token_id: token.id,
+        symbol: token.symbol.clone(),
+        available: from_atomic_units_with_decimals(balance.available, token.decimals),
+        locked: from_atomic_units_with_decimals(balance.locked, token.decimals),
+        decimals: token.decimals.max(0) as u32,
+        ts: chrono::Utc::now().timestamp_millis(),
+        meta,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::trading_engine::{MarketInfo, OrderBook, OrderType, UserBalance};
+
+/// What the engine does with the invariant checker, set with `ENGINE_INVARIANT_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantMode {
+    Off,  // never run
+    Log,  // run after every command and report violations
+    Halt, // report violations and stop accepting orders and withdrawals
+}
+
+impl InvariantMode {
+    pub fn from_env() -> Self {
+        match std::env::var("ENGINE_INVARIANT_MODE").unwrap_or_default().to_lowercase().as_str() {
+            "log" => InvariantMode::Log,
+            "halt" => InvariantMode::Halt,
+            _ => InvariantMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    /// Sum of available + locked over all users differs from deposits - withdrawals
+    Supply {
+        token_id: Uuid,
+        #[serde(with = "database::amount::string")]
+        expected: i128,
+        #[serde(with = "database::amount::string")]
+        actual: i128,
+    },
+    /// Locked amount differs from what the user's resting orders reserve
+    Reservation {
+        user_id: Uuid,
+        token_id: Uuid,
+        #[serde(with = "database::amount::string")]
+        reserved: i128,
+        #[serde(with = "database::amount::string")]
+        locked: i128,
+    },
+    NegativeBalance {
+        user_id: Uuid,
+        token_id: Uuid,
+        #[serde(with = "database::amount::string")]
+        available: i128,
+        #[serde(with = "database::amount::string")]
+        locked: i128,
+    },
+}
+
+/// Report published on `engine:invariants`
+#[derive(Debug, Clone, Serialize)]
+pub struct InvariantReport {
+    pub input_id: Option<String>,
+    pub halted: bool,
+    pub violations: Vec<Violation>,
+    pub timestamp: i64,
+}
+
+/// Check every balance-conservation invariant against the in-memory state.
+/// `net_deposits` is deposits minus withdrawals per token since the balances were loaded.
+pub fn check(
+    balances: &HashMap<Uuid, UserBalance>,
+    orderbooks: &HashMap<Uuid, OrderBook>,
+    markets: &HashMap<Uuid, MarketInfo>,
+    net_deposits: &HashMap<Uuid, i128>,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    // Supply per token and negative balances
+    let mut supply: HashMap<Uuid, i128> = HashMap::new();
+    for user_balance in balances.values() {
+        for (&token_id, tb) in &user_balance.token_balances {
+            let total = supply.entry(token_id).or_insert(0);
+            *total = total.saturating_add(tb.available).saturating_add(tb.locked);
+            if tb.available < 0 || tb.locked < 0 {
+                violations.push(Violation::NegativeBalance {
+                    user_id: user_balance.user_id,
+                    token_id,
+                    available: tb.available,
+                    locked: tb.locked,
+                });
+            }
+        }
+    }
+    let tokens: HashSet<Uuid> = supply.keys().chain(net_deposits.keys()).copied().collect();
+    for token_id in tokens {
+        let expected = net_deposits.get(&token_id).copied().unwrap_or(0);
+        let actual = supply.get(&token_id).copied().unwrap_or(0);
+        if expected != actual {
+            violations.push(Violation::Supply { token_id, expected, actual });
+        }
+    }
+
+    // Resting orders hold exactly their `reserved` amount: quote for buys, base for sells
+    let mut reserved: HashMap<(Uuid, Uuid), i128> = HashMap::new();
+    for orderbook in orderbooks.values() {
+        let Some(market) = markets.get(&orderbook.market_id) else { continue };
+        for order in orderbook.bids.values().chain(orderbook.asks.values()).flatten() {
+            let token_id = match order.order_type {
+                OrderType::Buy => market.quote_currency.id,
+                OrderType::Sell => market.base_currency.id,
+            };
+            let total = reserved.entry((order.user_id, token_id)).or_insert(0);
+            *total = total.saturating_add(order.reserved);
+        }
+    }
+    for user_balance in balances.values() {
+        for (&token_id, tb) in &user_balance.token_balances {
+            let expected = reserved.remove(&(user_balance.user_id, token_id)).unwrap_or(0);
+            if expected != tb.locked {
+                violations.push(Violation::Reservation {
+                    user_id: user_balance.user_id,
+                    token_id,
+                    reserved: expected,
+                    locked: tb.locked,
+                });
+            }
+        }
+    }
+    // Reservations of users without any balance entry
+    for ((user_id, token_id), amount) in reserved {
+        if amount != 0 {
+            violations.push(Violation::Reservation { user_id, token_id, reserved: amount, locked: 0 });
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, VecDeque};
+    use primitive_types::U256;
+
+    use super::*;
+    use crate::trading_engine::{notional, Order, OrderKind, OrderStatus, TokenBalance, TokenInfo};
+
+    const ETH: i128 = 1_000_000_000_000_000_000;
+    const USDC: i128 = 1_000_000;
+
+    struct State {
+        balances: HashMap<Uuid, UserBalance>,
+        orderbooks: HashMap<Uuid, OrderBook>,
+        markets: HashMap<Uuid, MarketInfo>,
+        net_deposits: HashMap<Uuid, i128>,
+        market: MarketInfo,
+        buyer: Uuid,
+        seller: Uuid,
+    }
+
+    impl State {
+        fn check(&self) -> Vec<Violation> {
+            check(&self.balances, &self.orderbooks, &self.markets, &self.net_deposits)
+        }
+
+        fn balance(&mut self, user_id: Uuid, token_id: Uuid) -> &mut TokenBalance {
+            self.balances.get_mut(&user_id).unwrap().token_balances.get_mut(&token_id).unwrap()
+        }
+    }
+
+    fn token(symbol: &str, decimals: i32) -> TokenInfo {
+        TokenInfo { id: Uuid::new_v4(), symbol: symbol.to_string(), name: symbol.to_string(), decimals, is_active: true }
+    }
+
+    fn resting(user_id: Uuid, market_id: Uuid, order_type: OrderType, price: i128, quantity: i128) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id,
+            market_id,
+            order_type,
+            order_kind: OrderKind::Limit,
+            price: Some(price),
+            quantity,
+            filled_quantity: 0,
+            status: OrderStatus::Pending,
+            created_at: 0,
+            client_order_id: None,
+            reserved: 0,
+            filled_notional: U256::zero(),
+        }
+    }
+
+    /// A buyer with 1.5 ETH bid at 2000 (0.5 filled at 1990) and a seller with 2 ETH
+    /// offered at 2100 (0.25 filled); locks match what the orders still reserve
+    fn state() -> State {
+        let market = MarketInfo {
+            id: Uuid::new_v4(),
+            symbol: "ETH-USDC".to_string(),
+            base_currency: token("ETH", 18),
+            quote_currency: token("USDC", 6),
+            min_order_size: 1,
+            tick_size: 1,
+            is_active: true,
+            created_at: chrono::NaiveDateTime::default(),
+        };
+        let (base, quote) = (market.base_currency.id, market.quote_currency.id);
+        let (buyer, seller) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut bid = resting(buyer, market.id, OrderType::Buy, 2000 * USDC, 3 * ETH / 2);
+        bid.filled_quantity = ETH / 2;
+        bid.filled_notional = notional(1990 * USDC, ETH / 2).unwrap();
+        bid.reserved = 3000 * USDC - 995 * USDC;
+        bid.status = OrderStatus::PartiallyFilled;
+        let mut ask = resting(seller, market.id, OrderType::Sell, 2100 * USDC, 2 * ETH);
+        ask.filled_quantity = ETH / 4;
+        ask.reserved = 7 * ETH / 4;
+        ask.status = OrderStatus::PartiallyFilled;
+
+        let book = OrderBook {
+            market_id: market.id,
+            bids: BTreeMap::from([(2000 * USDC, VecDeque::from([bid.clone()]))]),
+            asks: BTreeMap::from([(2100 * USDC, VecDeque::from([ask.clone()]))]),
+            last_updated: 0,
+        };
+        let balances = HashMap::from([
+            (buyer, UserBalance { user_id: buyer, token_balances: HashMap::from([
+                (quote, TokenBalance { available: 5000 * USDC, locked: bid.reserved }),
+                (base, TokenBalance { available: ETH / 2, locked: 0 }),
+            ]) }),
+            (seller, UserBalance { user_id: seller, token_balances: HashMap::from([
+                (quote, TokenBalance { available: 995 * USDC, locked: 0 }),
+                (base, TokenBalance { available: ETH, locked: ask.reserved }),
+            ]) }),
+        ]);
+        let net_deposits = HashMap::from([
+            (quote, 5000 * USDC + bid.reserved + 995 * USDC),
+            (base, ETH / 2 + ETH + ask.reserved),
+        ]);
+        State {
+            balances,
+            orderbooks: HashMap::from([(market.id, book)]),
+            markets: HashMap::from([(market.id, market.clone())]),
+            net_deposits,
+            market,
+            buyer,
+            seller,
+        }
+    }
+
+    #[test]
+    fn consistent_state_passes() {
+        assert!(state().check().is_empty());
+    }
+
+    #[test]
+    fn supply_violation() {
+        let mut state = state();
+        let quote = state.market.quote_currency.id;
+        // Quote appears out of nowhere: the user's total grows, deposits do not
+        state.balance(state.seller, quote).available += 1;
+        let violations = state.check();
+        assert_eq!(violations.len(), 1);
+        assert!(matches!(
+            violations[0],
+            Violation::Supply { token_id, expected, actual } if token_id == quote && actual == expected + 1
+        ));
+    }
+
+    #[test]
+    fn reservation_violation() {
+        let mut state = state();
+        let base = state.market.base_currency.id;
+        // Move base from available to locked: supply is unchanged, the lock no longer matches the ask
+        let balance = state.balance(state.seller, base);
+        balance.available -= 1;
+        balance.locked += 1;
+        let violations = state.check();
+        assert_eq!(violations.len(), 1);
+        assert!(matches!(
+            violations[0],
+            Violation::Reservation { user_id, token_id, reserved, locked }
+                if user_id == state.seller && token_id == base && reserved == 7 * ETH / 4 && locked == reserved + 1
+        ));
+    }
+
+    #[test]
+    fn negative_balance_violation() {
+        let mut state = state();
+        let base = state.market.base_currency.id;
+        state.balance(state.buyer, base).available = -1;
+        state.net_deposits.insert(base, ETH + 7 * ETH / 4 - 1);
+        let violations = state.check();
+        assert_eq!(violations.len(), 1);
+        assert!(matches!(
+            violations[0],
+            Violation::NegativeBalance { user_id, available: -1, locked: 0, .. } if user_id == state.buyer
+        ));
+    }
+
+    #[test]
+    fn restored_books_back_restored_locks() {
+        // After a restart the books come from a snapshot; reservations are rebuilt, not trusted
+        let mut state = state();
+        let market_id = state.market.id;
+        let json = serde_json::to_string(&state.orderbooks[&market_id]).unwrap();
+        let mut restored: OrderBook = serde_json::from_str(&json).unwrap();
+        for order in restored.bids.values_mut().chain(restored.asks.values_mut()).flatten() {
+            order.reserved = 0;
+        }
+        restored.rebuild_reservations(&state.market);
+        state.orderbooks.insert(market_id, restored);
+        assert!(state.check().is_empty());
+    }
+
+    #[test]
+    fn restored_lock_without_its_order_is_reported() {
+        // A lock restored without the order book that backs it is no longer skipped
+        let mut state = state();
+        state.orderbooks.clear();
+        let violations = state.check();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().all(|v| matches!(v, Violation::Reservation { reserved: 0, .. })));
+    }
+}
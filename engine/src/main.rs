@@ -1,6 +1,8 @@
 mod redis_manager;
 mod trading_engine;
 mod decimal_utils;
+mod invariants;
+mod ticker;
 
 use redis_manager::{EngineRedisManager, EngineMessage, EngineResponse};
 use trading_engine::TradingEngine;
@@ -31,18 +33,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("✅ Markets loaded successfully");
     }
 
+    trading_engine.load_event_seq().await;
+    trading_engine.reset_depth_snapshots().await;
+
+    if let Err(e) = trading_engine.load_client_order_ids().await {
+        error!("Failed to load client order ids: {}", e);
+    } else {
+        info!("✅ Client order ids loaded successfully");
+    }
+
+    if let Err(e) = trading_engine.load_ticker_snapshots().await {
+        error!("Failed to load ticker snapshots: {}", e);
+    }
+
     if let Err(e) = trading_engine.load_balance_snapshots().await {
         error!("Failed to load balance snapshots: {}", e);
     } else {
         info!("✅ Balance snapshots loaded successfully");
     }
 
+    if let Err(e) = trading_engine.load_orderbook_snapshots().await {
+        error!("Failed to load order book snapshots: {}", e);
+    }
+
     let consumer_group = "matching_engine_group";
     let consumer_name = "engine_1";
+
+    // Recover messages that were delivered but never acknowledged before a crash
+    match redis_manager.claim_pending_messages(consumer_group, consumer_name, 100).await {
+        Ok(pending) => {
+            if !pending.is_empty() {
+                info!("♻️ Recovering {} unacknowledged messages", pending.len());
+            }
+            for (stream_id, message) in pending {
+                handle_message(&mut trading_engine, &redis_manager, consumer_group, stream_id, message).await;
+            }
+        }
+        Err(e) => {
+            error!("Failed to claim pending messages: {}", e);
+        }
+    }
     
     info!("🔄 Starting order processing loop...");
     
+    let mut last_ticker_roll = std::time::Instant::now();
     loop {
+        // 24h windows move on even when nothing trades
+        if last_ticker_roll.elapsed() >= Duration::from_secs(60) {
+            trading_engine.roll_tickers().await;
+            last_ticker_roll = std::time::Instant::now();
+        }
+
         match redis_manager.consume_messages(consumer_group, consumer_name, 10).await {
             Ok(messages) => {
                 if !messages.is_empty() {
@@ -50,33 +91,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     
                     // Process each message
                     for (stream_id, message) in messages {
-                        let response = match message {
-                            EngineMessage::Order(order_request) => {
-                                info!("🔄 Processing order: {}", order_request.request_id);
-                                let order_response = trading_engine.process_order(order_request).await;
-                                EngineResponse::Order(order_response)
-                            }
-                            EngineMessage::Balance(balance_request) => {
-                                info!("💰 Processing balance: {}", balance_request.request_id);
-                                let balance_response = trading_engine.process_balance_request(balance_request).await;
-                                EngineResponse::Balance(balance_response)
-                            }
-                            EngineMessage::CancelOrder(cancel_order_request) => {
-                                info!("🔄 Processing cancel order: {}", cancel_order_request.request_id);
-                                let cancel_order_response = trading_engine.process_cancel_order(cancel_order_request).await;
-                                EngineResponse::Order(cancel_order_response)
-                            }
-                        };
-
-                        // Send unified response
-                        if let Err(e) = redis_manager.send_unified_response(response).await {
-                            error!("Failed to send response: {}", e);
-                        }
-
-                        // Acknowledge message
-                        if let Err(e) = redis_manager.ack_message(consumer_group, &stream_id).await {
-                            error!("Failed to acknowledge message: {}", e);
-                        }
+                        handle_message(&mut trading_engine, &redis_manager, consumer_group, stream_id, message).await;
                     }
                 } else {
                     // No messages, sleep briefly
@@ -89,4 +104,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Apply a single message, respond and acknowledge it. Requests that were already
+/// applied (e.g. redelivered after a crash) are answered from the recorded response.
+async fn handle_message(
+    trading_engine: &mut TradingEngine,
+    redis_manager: &EngineRedisManager,
+    consumer_group: &str,
+    stream_id: String,
+    message: EngineMessage,
+) {
+    trading_engine.begin_input(&stream_id);
+
+    let previous = match redis_manager.get_processed_response(message.request_id()).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            error!("Failed to check processed requests: {}", e);
+            None
+        }
+    };
+
+    let read_only = message.is_read_only();
+    let response = match previous {
+        Some(response) => {
+            info!("⏭️ Request {} already processed, resending response", message.request_id());
+            response
+        }
+        None => {
+            let response = match message {
+                EngineMessage::Order(order_request) => {
+                    info!("🔄 Processing order: {}", order_request.request_id);
+                    let order_response = trading_engine.process_order(order_request).await;
+                    EngineResponse::Order(order_response)
+                }
+                EngineMessage::Balance(balance_request) => {
+                    info!("💰 Processing balance: {}", balance_request.request_id);
+                    let balance_response = trading_engine.process_balance_request(balance_request).await;
+                    EngineResponse::Balance(balance_response)
+                }
+                EngineMessage::CancelOrder(cancel_order_request) => {
+                    info!("🔄 Processing cancel order: {}", cancel_order_request.request_id);
+                    let cancel_order_response = trading_engine.process_cancel_order(cancel_order_request).await;
+                    EngineResponse::Order(cancel_order_response)
+                }
+                EngineMessage::OpenOrders(open_orders_request) => {
+                    EngineResponse::OpenOrders(trading_engine.open_orders(open_orders_request))
+                }
+                EngineMessage::Resume(resume_request) => {
+                    info!("▶️ Processing resume: {}", resume_request.request_id);
+                    EngineResponse::Resume(trading_engine.resume(resume_request))
+                }
+            };
+            if !read_only {
+                trading_engine.check_invariants().await;
+                if let Err(e) = redis_manager.record_processed_response(&response).await {
+                    error!("Failed to record processed request: {}", e);
+                }
+            }
+            response
+        }
+    };
+
+    // Send unified response
+    let meta = trading_engine.next_response_meta();
+    if let Err(e) = redis_manager.send_unified_response(response, meta).await {
+        error!("Failed to send response: {}", e);
+    }
+    trading_engine.end_input().await;
+
+    // Acknowledge message
+    if let Err(e) = redis_manager.ack_message(consumer_group, &stream_id).await {
+        error!("Failed to acknowledge message: {}", e);
+    }
+}
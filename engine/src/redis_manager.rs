@@ -9,6 +9,8 @@ pub enum EngineMessage {
     Order(OrderRequest),
     Balance(BalanceRequest),
     CancelOrder(CancelOrderRequest),
+    OpenOrders(OpenOrdersRequest),
+    Resume(ResumeRequest),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,13 +18,69 @@ pub enum EngineMessage {
 pub enum EngineResponse {
     Order(OrderResponse),
     Balance(BalanceResponse),
+    OpenOrders(OpenOrdersResponse),
+    Resume(ResumeResponse),
+}
+
+/// Ordering stamp carried by every engine output event. `event_seq` is one global
+/// counter across all outputs; `prev_event_seq` is the stamp of the previous event
+/// on the same channel (0 when unknown) so a consumer of a single channel can spot
+/// gaps; `input_id` is the `engine_processing_queue` entry that caused the event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventMeta {
+    #[serde(default)]
+    pub event_seq: u64,
+    #[serde(default)]
+    pub prev_event_seq: u64,
+    #[serde(default)]
+    pub input_id: Option<String>,
+}
+
+/// Response as published to `engine_response:<request_id>`, stamped with its event meta
+#[derive(Debug, Clone, Serialize)]
+struct StampedResponse<'a> {
+    #[serde(flatten)]
+    response: &'a EngineResponse,
+    #[serde(flatten)]
+    meta: EventMeta,
+}
+
+impl EngineMessage {
+    pub fn request_id(&self) -> &str {
+        match self {
+            EngineMessage::Order(req) => &req.request_id,
+            EngineMessage::Balance(req) => &req.request_id,
+            EngineMessage::CancelOrder(req) => &req.request_id,
+            EngineMessage::OpenOrders(req) => &req.request_id,
+            EngineMessage::Resume(req) => &req.request_id,
+        }
+    }
+
+    /// Queries that change no state; their responses are not recorded for redelivery
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, EngineMessage::OpenOrders(_))
+    }
+}
+
+impl EngineResponse {
+    pub fn request_id(&self) -> &str {
+        match self {
+            EngineResponse::Order(resp) => &resp.request_id,
+            EngineResponse::Balance(resp) => &resp.request_id,
+            EngineResponse::OpenOrders(resp) => &resp.request_id,
+            EngineResponse::Resume(resp) => &resp.request_id,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CancelOrderRequest {
     pub request_id: String,
     pub user_id: Uuid,
-    pub order_id: Uuid,
+    #[serde(default)]
+    pub order_id: Option<Uuid>,
+    #[serde(default)]
+    pub client_order_id: Option<String>, // Used when order_id is not given
     pub market_id: Uuid,
     pub timestamp: i64,
 }
@@ -35,8 +93,12 @@ pub struct OrderRequest {
     pub market_id: Uuid,
     pub order_type: String, // Buy/Sell
     pub order_kind: String, // Market/Limit
-    pub price: Option<i64>,
-    pub quantity: i64,
+    #[serde(default, with = "database::amount::option_string")]
+    pub price: Option<i128>,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     pub timestamp: i64,
 }
 
@@ -46,18 +108,25 @@ pub struct OrderResponse {
     pub success: bool,
     pub status: String, // "Filled", "PartiallyFilled", "Pending", "Rejected"
     pub order_id: Option<Uuid>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     pub message: String,
-    pub filled_quantity: Option<i64>,
-    pub remaining_quantity: Option<i64>,
-    pub average_price: Option<i64>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub filled_quantity: Option<i128>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub remaining_quantity: Option<i128>,
+    #[serde(default, with = "database::amount::option_string")]
+    pub average_price: Option<i128>,
     pub trades: Option<Vec<TradeInfo>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeInfo {
     pub trade_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    #[serde(with = "database::amount::string")]
+    pub price: i128,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
     pub timestamp: i64,
 }
 
@@ -68,7 +137,8 @@ pub struct BalanceRequest {
     pub user_id: Uuid,
     pub token_id: Uuid,
     pub operation: BalanceOperation,
-    pub amount: i64,
+    #[serde(with = "database::amount::string")]
+    pub amount: i128,
     pub timestamp: i64,
 }
 
@@ -79,20 +149,56 @@ pub enum BalanceOperation {
     GetBalances,
 }
 
+/// A user's resting orders, straight from the order books
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenOrdersRequest {
+    pub request_id: String,
+    pub user_id: Uuid,
+    #[serde(default)]
+    pub market_id: Option<Uuid>, // all markets when not given
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenOrdersResponse {
+    pub request_id: String,
+    pub success: bool,
+    pub message: String,
+    pub orders: Vec<crate::trading_engine::Order>, // newest first
+}
+
+/// Admin command clearing an invariant halt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeRequest {
+    pub request_id: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeResponse {
+    pub request_id: String,
+    pub success: bool,
+    pub message: String,
+    pub was_halted: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BalanceResponse {
     pub request_id: String,
     pub success: bool,
     pub message: String,
-    pub new_balance: i64,
+    #[serde(with = "database::amount::string")]
+    pub new_balance: i128,
     pub balances: Option<Vec<UserTokenBalance>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserTokenBalance {
     pub token_id: Uuid,
-    pub available: i64,
-    pub locked: i64,
+    #[serde(with = "database::amount::string")]
+    pub available: i128,
+    #[serde(with = "database::amount::string")]
+    pub locked: i128,
 }
 
 // Redis manager
@@ -120,14 +226,7 @@ impl EngineRedisManager {
         let mut conn = self.connection_manager.clone();
         
         // Create consumer group if it doesn't exist
-        let _: Result<String, _> = redis::cmd("XGROUP")
-            .arg("CREATE")
-            .arg("engine_processing_queue") // Unified queue name
-            .arg(consumer_group)
-            .arg("0")
-            .arg("MKSTREAM")
-            .query_async(&mut conn)
-            .await;
+        self.ensure_consumer_group(consumer_group).await;
         
         // Read from stream with consumer group
         let results: redis::Value = redis::cmd("XREADGROUP")
@@ -145,50 +244,130 @@ impl EngineRedisManager {
             .await?;
         
         let mut messages = Vec::new();
+        let mut skipped = Vec::new();
         
         // Parse Redis stream response
         if let redis::Value::Bulk(streams) = results {
             if let Some(redis::Value::Bulk(stream_data)) = streams.get(0) {
                 if let Some(redis::Value::Bulk(stream_messages)) = stream_data.get(1) {
-                    for message in stream_messages {
-                        if let redis::Value::Bulk(msg_parts) = message {
-                            if let (Some(redis::Value::Data(stream_id)), Some(redis::Value::Bulk(fields))) = 
-                                (msg_parts.get(0), msg_parts.get(1)) {
-                                
-                                let stream_id_str = String::from_utf8_lossy(stream_id);
-                                let mut data = None;
-                                let mut message_type = None;
-                                
-                                // Parse field-value pairs
-                                for chunk in fields.chunks(2) {
-                                    if let (Some(redis::Value::Data(key)), Some(redis::Value::Data(value))) = 
-                                        (chunk.get(0), chunk.get(1)) {
-                                        let key_str = String::from_utf8_lossy(key);
-                                        let value_str = String::from_utf8_lossy(value);
-                                        
-                                        match key_str.as_ref() {
-                                            "data" => data = Some(value_str.to_string()),
-                                            "message_type" => message_type = Some(value_str.to_string()),
-                                            _ => {}
-                                        }
-                                    }
-                                }
-                                
-                                if let Some(data_str) = data {
-                                    if let Ok(engine_message) = serde_json::from_str::<EngineMessage>(&data_str) {
-                                        messages.push((stream_id_str.to_string(), engine_message));
-                                    }
-                                }
-                            }
-                        }
-                    }
+                    (messages, skipped) = parse_stream_entries(stream_messages);
                 }
             }
         }
+        self.ack_skipped(consumer_group, &skipped).await;
         
         Ok(messages)
     }
 
+    /// Claim every entry that was delivered to the group but never acknowledged,
+    /// e.g. because the engine crashed mid-batch. XAUTOCLAIM with a zero idle time
+    /// moves them all to this consumer; call it once before the main loop.
+    pub async fn claim_pending_messages(
+        &self,
+        consumer_group: &str,
+        consumer_name: &str,
+        batch_size: usize,
+    ) -> Result<Vec<(String, EngineMessage)>, redis::RedisError> {
+        let mut conn = self.connection_manager.clone();
+        self.ensure_consumer_group(consumer_group).await;
+
+        let mut messages = Vec::new();
+        let mut cursor = "0-0".to_string();
+
+        loop {
+            let results: redis::Value = redis::cmd("XAUTOCLAIM")
+                .arg("engine_processing_queue")
+                .arg(consumer_group)
+                .arg(consumer_name)
+                .arg(0) // min idle time: claim regardless of age
+                .arg(&cursor)
+                .arg("COUNT")
+                .arg(batch_size)
+                .query_async(&mut conn)
+                .await?;
+
+            // Reply: [next_cursor, [entries...], [deleted ids...]]
+            let mut next_cursor = None;
+            if let redis::Value::Bulk(parts) = results {
+                if let Some(redis::Value::Data(next)) = parts.first() {
+                    next_cursor = Some(String::from_utf8_lossy(next).to_string());
+                }
+                if let Some(redis::Value::Bulk(entries)) = parts.get(1) {
+                    let (parsed, skipped) = parse_stream_entries(entries);
+                    messages.extend(parsed);
+                    self.ack_skipped(consumer_group, &skipped).await;
+                }
+            }
+
+            match next_cursor {
+                Some(next) if next != "0-0" => cursor = next,
+                _ => break,
+            }
+        }
+
+        tracing::info!("♻️ Claimed {} pending messages for {}", messages.len(), consumer_name);
+        Ok(messages)
+    }
+
+    /// Acknowledge entries that can never be processed so they leave the pending list
+    /// instead of being re-claimed on every start
+    async fn ack_skipped(&self, consumer_group: &str, stream_ids: &[String]) {
+        if stream_ids.is_empty() {
+            return;
+        }
+        let mut conn = self.connection_manager.clone();
+        let acked: Result<i64, _> = redis::cmd("XACK")
+            .arg("engine_processing_queue")
+            .arg(consumer_group)
+            .arg(stream_ids)
+            .query_async(&mut conn)
+            .await;
+        if let Err(e) = acked {
+            tracing::error!("❌ Failed to ack {} skipped messages: {}", stream_ids.len(), e);
+        }
+    }
+
+    async fn ensure_consumer_group(&self, consumer_group: &str) {
+        let mut conn = self.connection_manager.clone();
+        let _: Result<String, _> = redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg("engine_processing_queue") // Unified queue name
+            .arg(consumer_group)
+            .arg("0")
+            .arg("MKSTREAM")
+            .query_async(&mut conn)
+            .await;
+    }
+
+    /// Response recorded for a request that was already applied, if any
+    pub async fn get_processed_response(
+        &self,
+        request_id: &str,
+    ) -> Result<Option<EngineResponse>, redis::RedisError> {
+        let mut conn = self.connection_manager.clone();
+        let cached: Option<String> = conn.get(format!("engine_processed:{}", request_id)).await?;
+        Ok(cached.and_then(|json| serde_json::from_str(&json).ok()))
+    }
+
+    /// Remember that a request has been applied so a redelivery is answered
+    /// from this record instead of being processed twice
+    pub async fn record_processed_response(
+        &self,
+        response: &EngineResponse,
+    ) -> Result<(), redis::RedisError> {
+        let mut conn = self.connection_manager.clone();
+        let response_json = serde_json::to_string(response).unwrap();
+
+        let _: () = redis::cmd("SET")
+            .arg(format!("engine_processed:{}", response.request_id()))
+            .arg(response_json)
+            .arg("EX")
+            .arg(86400) // 24 hours
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
     /// Consume orders from the processing queue
     // pub async fn consume_orders(
     //     &self,
@@ -269,16 +448,14 @@ impl EngineRedisManager {
     pub async fn send_unified_response(
         &self,
         response: EngineResponse,
+        meta: EventMeta,
     ) -> Result<(), redis::RedisError> {
         let mut conn = self.connection_manager.clone();
         
-        let request_id = match &response {
-            EngineResponse::Order(resp) => resp.request_id.clone(),
-            EngineResponse::Balance(resp) => resp.request_id.clone(),
-        };
+        let request_id = response.request_id().to_string();
         
         let response_channel = format!("engine_response:{}", request_id);
-        let response_json = serde_json::to_string(&response).unwrap();
+        let response_json = serde_json::to_string(&StampedResponse { response: &response, meta }).unwrap();
         
         let _: () = conn.publish(&response_channel, response_json).await?;
         tracing::info!("📤 Sent unified response for request {}", request_id);
@@ -334,4 +511,46 @@ impl EngineRedisManager {
         
     //     Ok(())
     // }
-}
\ No newline at end of file
+}
+
+/// Parse the `[id, [field, value, ...]]` entries of a stream reply into engine messages.
+/// Entries without a parseable `data` field are logged and returned by id so the caller can ack them.
+fn parse_stream_entries(entries: &[redis::Value]) -> (Vec<(String, EngineMessage)>, Vec<String>) {
+    let mut messages = Vec::new();
+    let mut skipped = Vec::new();
+
+    for message in entries {
+        if let redis::Value::Bulk(msg_parts) = message {
+            if let (Some(redis::Value::Data(stream_id)), Some(redis::Value::Bulk(fields))) =
+                (msg_parts.first(), msg_parts.get(1)) {
+
+                let stream_id_str = String::from_utf8_lossy(stream_id);
+                let mut data = None;
+
+                // Parse field-value pairs
+                for chunk in fields.chunks(2) {
+                    if let (Some(redis::Value::Data(key)), Some(redis::Value::Data(value))) =
+                        (chunk.first(), chunk.get(1)) {
+                        if key.as_slice() == b"data" {
+                            data = Some(String::from_utf8_lossy(value).to_string());
+                        }
+                    }
+                }
+
+                match data.map(|data_str| serde_json::from_str::<EngineMessage>(&data_str)) {
+                    Some(Ok(engine_message)) => messages.push((stream_id_str.to_string(), engine_message)),
+                    Some(Err(e)) => {
+                        tracing::warn!("Skipping unparseable message {}: {}", stream_id_str, e);
+                        skipped.push(stream_id_str.to_string());
+                    }
+                    None => {
+                        tracing::warn!("Skipping message {} without data", stream_id_str);
+                        skipped.push(stream_id_str.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    (messages, skipped)
+}
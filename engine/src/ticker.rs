@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use database::Decimal;
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::trading_engine::MarketTicker;
+
+const BUCKET_MS: i64 = 60 * 1000;
+const WINDOW_MS: i64 = 24 * 60 * 60 * 1000;
+/// Decimal places of `change_24h`
+const CHANGE_SCALE: u32 = 4;
+
+/// Trades of one minute
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TickerBucket {
+    start: i64,
+    #[serde(with = "database::amount::string")]
+    open: i128,
+    #[serde(with = "database::amount::string")]
+    high: i128,
+    #[serde(with = "database::amount::string")]
+    low: i128,
+    #[serde(with = "database::amount::string")]
+    base_volume: i128,
+    #[serde(with = "database::amount::string")]
+    quote_volume: i128,
+    trade_count: u64,
+}
+
+/// Rolling 24h statistics of one market, kept as one-minute buckets.
+/// Persisted under `snapshot:ticker:<market_id>` so a restart keeps the window.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RollingTicker {
+    buckets: VecDeque<TickerBucket>,
+    #[serde(with = "database::amount::string")]
+    last_price: i128,
+    last_trade_at: i64,
+}
+
+impl RollingTicker {
+    pub fn record(&mut self, price: i128, base_volume: i128, quote_volume: i128, timestamp: i64) {
+        let start = timestamp.div_euclid(BUCKET_MS) * BUCKET_MS;
+        match self.buckets.back_mut() {
+            Some(bucket) if bucket.start == start => {
+                bucket.high = bucket.high.max(price);
+                bucket.low = bucket.low.min(price);
+                bucket.base_volume = bucket.base_volume.saturating_add(base_volume);
+                bucket.quote_volume = bucket.quote_volume.saturating_add(quote_volume);
+                bucket.trade_count += 1;
+            }
+            _ => self.buckets.push_back(TickerBucket {
+                start,
+                open: price,
+                high: price,
+                low: price,
+                base_volume,
+                quote_volume,
+                trade_count: 1,
+            }),
+        }
+        if timestamp >= self.last_trade_at {
+            self.last_price = price;
+            self.last_trade_at = timestamp;
+        }
+        self.roll(timestamp);
+    }
+
+    /// Drop buckets that ended more than 24h before `now`
+    pub fn roll(&mut self, now: i64) {
+        while self.buckets.front().is_some_and(|bucket| bucket.start + BUCKET_MS <= now - WINDOW_MS) {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Statistics over the window; a market without trades in the last 24h shows
+    /// its last price with zero volume and change
+    pub fn stats(&self, market_id: Uuid, now: i64) -> MarketTicker {
+        let in_window = || self.buckets.iter().filter(|bucket| bucket.start + BUCKET_MS > now - WINDOW_MS);
+        let open_24h = in_window().next().map(|bucket| bucket.open).unwrap_or(self.last_price);
+        let change_24h = percent_change(open_24h, self.last_price);
+
+        MarketTicker {
+            market_id,
+            last_price: self.last_price,
+            open_24h,
+            high_24h: in_window().map(|bucket| bucket.high).max().unwrap_or(self.last_price),
+            low_24h: in_window().map(|bucket| bucket.low).min().unwrap_or(self.last_price),
+            volume_24h: in_window().fold(0i128, |total, bucket| total.saturating_add(bucket.base_volume)),
+            quote_volume_24h: in_window().fold(0i128, |total, bucket| total.saturating_add(bucket.quote_volume)),
+            trade_count_24h: in_window().map(|bucket| bucket.trade_count).sum(),
+            change_24h,
+            timestamp: now,
+        }
+    }
+}
+
+/// Percent change from `open` to `last`, rounded half away from zero to `CHANGE_SCALE` places
+fn percent_change(open: i128, last: i128) -> Decimal {
+    if open <= 0 {
+        return Decimal::from_atomic(0, 0);
+    }
+    let diff = last.saturating_sub(open);
+    let open = U256::from(open as u128);
+    let scaled = U256::from(diff.unsigned_abs()) * U256::exp10(2 + CHANGE_SCALE as usize);
+    let magnitude = (scaled + open / 2) / open;
+    let magnitude = if magnitude > U256::from(i128::MAX as u128) { i128::MAX } else { magnitude.as_u128() as i128 };
+    Decimal::from_atomic(if diff < 0 { -magnitude } else { magnitude }, CHANGE_SCALE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = BUCKET_MS;
+    const DAY: i64 = WINDOW_MS;
+
+    #[test]
+    fn trades_of_one_minute_share_a_bucket() {
+        let mut ticker = RollingTicker::default();
+        ticker.record(100, 1, 100, 0);
+        ticker.record(120, 2, 240, 30_000);
+        ticker.record(90, 1, 90, MINUTE - 1);
+        ticker.record(110, 1, 110, MINUTE);
+        assert_eq!(ticker.buckets.len(), 2);
+
+        let stats = ticker.stats(Uuid::nil(), MINUTE);
+        assert_eq!((stats.open_24h, stats.high_24h, stats.low_24h, stats.last_price), (100, 120, 90, 110));
+        assert_eq!((stats.volume_24h, stats.quote_volume_24h, stats.trade_count_24h), (5, 540, 4));
+        assert_eq!(stats.change_24h.to_string(), "10");
+    }
+
+    #[test]
+    fn window_edge() {
+        let mut ticker = RollingTicker::default();
+        ticker.record(100, 1, 100, 0);
+        ticker.record(150, 1, 150, 10 * MINUTE);
+
+        // The first bucket counts until it ended a full day ago
+        let stats = ticker.stats(Uuid::nil(), DAY + MINUTE - 1);
+        assert_eq!((stats.open_24h, stats.trade_count_24h), (100, 2));
+        assert_eq!(stats.change_24h.to_string(), "50");
+
+        let stats = ticker.stats(Uuid::nil(), DAY + MINUTE);
+        assert_eq!((stats.open_24h, stats.low_24h, stats.trade_count_24h), (150, 150, 1));
+        assert!(stats.change_24h.is_zero());
+    }
+
+    #[test]
+    fn expired_buckets_roll_off() {
+        let mut ticker = RollingTicker::default();
+        ticker.record(100, 1, 100, 0);
+        ticker.record(200, 1, 200, 5 * MINUTE);
+
+        ticker.roll(DAY + MINUTE);
+        assert_eq!(ticker.buckets.len(), 1);
+        ticker.roll(DAY + 6 * MINUTE);
+        assert!(ticker.buckets.is_empty());
+
+        // Nothing traded in the window: the last price with zero volume and change
+        let stats = ticker.stats(Uuid::nil(), DAY + 6 * MINUTE);
+        assert_eq!((stats.last_price, stats.open_24h, stats.high_24h, stats.low_24h), (200, 200, 200, 200));
+        assert_eq!((stats.volume_24h, stats.trade_count_24h), (0, 0));
+        assert!(stats.change_24h.is_zero());
+    }
+
+    #[test]
+    fn open_24h_survives_a_restart() {
+        let mut ticker = RollingTicker::default();
+        ticker.record(100, 1, 100, 0);
+        ticker.record(125, 1, 125, 2 * MINUTE);
+
+        // What `load_ticker_snapshots` does with `snapshot:ticker:<market_id>`
+        let json = serde_json::to_string(&ticker).unwrap();
+        let mut restored: RollingTicker = serde_json::from_str(&json).unwrap();
+        restored.roll(3 * MINUTE);
+        let stats = restored.stats(Uuid::nil(), 3 * MINUTE);
+        assert_eq!((stats.open_24h, stats.last_price), (100, 125));
+        assert_eq!(stats.change_24h.to_string(), "25");
+
+        // Restarted after the first bucket left the window
+        let mut restored: RollingTicker = serde_json::from_str(&json).unwrap();
+        restored.roll(DAY + MINUTE);
+        let stats = restored.stats(Uuid::nil(), DAY + MINUTE);
+        assert_eq!((stats.open_24h, stats.trade_count_24h), (125, 1));
+    }
+
+    #[test]
+    fn change_is_exact() {
+        assert_eq!(percent_change(300, 400).to_string(), "33.3333");
+        assert_eq!(percent_change(300, 200).to_string(), "-33.3333");
+        assert_eq!(percent_change(3, 5).to_string(), "66.6667");
+        assert_eq!(percent_change(3, 1).to_string(), "-66.6667");
+        assert!(percent_change(0, 5).is_zero());
+        assert_eq!(percent_change(1, i128::MAX), Decimal::from_atomic(i128::MAX, CHANGE_SCALE));
+        // Serialized as a string like every other amount on the feed
+        assert_eq!(serde_json::to_value(percent_change(8, 9)).unwrap(), "12.5");
+    }
+}
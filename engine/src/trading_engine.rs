@@ -3,14 +3,18 @@ use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 use tokio::time::{interval, Duration};
 use redis::{aio::ConnectionManager, AsyncCommands, Commands};
-use primitive_types::U256;
 use chrono::Utc;
 use diesel::prelude::*;
-use database::{establish_connection, Market, Token, schema::{markets, tokens}};
+use primitive_types::U256;
+use database::{establish_connection, Decimal, Market, RoundingMode, Token, DUST_ACCOUNT_ID, schema::{markets, orders, tokens}};
+use crate::redis_manager::EventMeta;
+use crate::invariants::{self, InvariantMode, InvariantReport};
+use crate::ticker::RollingTicker;
 use crate::decimal_utils::{
     price_from_atomic_units, quantity_from_atomic_units,
-    EnhancedDepthUpdate, EnhancedMarketTicker, convert_ticker_to_decimal,
-    convert_trade_to_decimal, format_price_to_tick_precision, format_quantity_to_precision
+    DepthSnapshot, EnhancedDepthUpdate, EnhancedMarketTicker, FeedDecimals,
+    L3Event, L3RemoveReason, L3Side, L3Update, Liquidity, convert_balance_update,
+    convert_fill, convert_order_update, convert_ticker_to_decimal, convert_trade_to_decimal,
 };
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
@@ -19,11 +23,37 @@ pub struct Order {
     pub market_id: Uuid,
     pub order_type: OrderType, // Buy/Sell
     pub order_kind: OrderKind, // Market/Limit
-    pub price: Option<i64>,
-    pub quantity: i64,
-    pub filled_quantity: i64,
+    #[serde(default, with = "database::amount::option_string")]
+    pub price: Option<i128>,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
+    #[serde(with = "database::amount::string")]
+    pub filled_quantity: i128,
     pub status: OrderStatus,
     pub created_at: i64,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// Amount still locked for this order: quote for buys, base for sells
+    #[serde(default, with = "database::amount::string")]
+    pub reserved: i128,
+    /// Sum of price * quantity over all fills, not yet scaled down by the base decimals
+    #[serde(default, with = "u256_string")]
+    pub filled_notional: U256,
+}
+
+/// `U256` as a decimal string, like the i128 amounts
+mod u256_string {
+    use primitive_types::U256;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        U256::from_dec_str(&s).map_err(|e| de::Error::custom(format!("invalid amount {s:?}: {e:?}")))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,33 +73,58 @@ pub struct Trade {
     pub seller_order_id: Uuid,
     pub buyer_user_id: Uuid,
     pub seller_user_id: Uuid,
-    pub price: i64,
-    pub quantity: i64,
+    #[serde(with = "database::amount::string")]
+    pub price: i128,
+    #[serde(with = "database::amount::string")]
+    pub quantity: i128,
     pub timestamp: i64,
+    #[serde(default)]
+    pub buyer_client_order_id: Option<String>,
+    #[serde(default)]
+    pub seller_client_order_id: Option<String>,
+    /// Side of the incoming order that took liquidity
+    pub taker_side: OrderType,
+    /// Quote the buyer pays (rounded up); the difference to `seller_proceeds` goes to the dust account
+    #[serde(default, with = "database::amount::string")]
+    pub buyer_cost: i128,
+    /// Quote the seller receives (rounded down)
+    #[serde(default, with = "database::amount::string")]
+    pub seller_proceeds: i128,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reservation {
     pub token_id: Uuid,
-    pub amount: i64
+    #[serde(with = "database::amount::string")]
+    pub amount: i128
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderBook {
     pub market_id: Uuid,
-    pub bids: BTreeMap<i64, VecDeque<Order>>, // Price -> Orders (descending)
-    pub asks: BTreeMap<i64, VecDeque<Order>>, // Price -> Orders (ascending)
+    pub bids: BTreeMap<i128, VecDeque<Order>>, // Price -> Orders (descending)
+    pub asks: BTreeMap<i128, VecDeque<Order>>, // Price -> Orders (ascending)
     pub last_updated: i64,
 }
 
+/// Rolling 24h statistics, computed by `RollingTicker`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketTicker {
     pub market_id: Uuid,
-    pub last_price: i64,
-    pub volume_24h: i64,
-    pub high_24h: i64,
-    pub low_24h: i64,
-    pub change_24h: f64,
+    #[serde(with = "database::amount::string")]
+    pub last_price: i128,
+    #[serde(with = "database::amount::string")]
+    pub open_24h: i128,
+    #[serde(with = "database::amount::string")]
+    pub volume_24h: i128, // base
+    #[serde(with = "database::amount::string")]
+    pub quote_volume_24h: i128,
+    #[serde(with = "database::amount::string")]
+    pub high_24h: i128,
+    #[serde(with = "database::amount::string")]
+    pub low_24h: i128,
+    pub trade_count_24h: u64,
+    pub change_24h: Decimal, // percent
     pub timestamp: i64,
 }
 
@@ -81,8 +136,21 @@ pub struct UserBalance {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenBalance {
-    pub available: i64,
-    pub locked: i64,
+    #[serde(with = "database::amount::string")]
+    pub available: i128,
+    #[serde(with = "database::amount::string")]
+    pub locked: i128,
+}
+
+/// `balance_updated` entry for the db-updater; amounts are atomic-unit strings
+#[derive(Debug, Clone, Serialize)]
+struct BalanceRecord {
+    user_id: Uuid,
+    token_id: Uuid,
+    #[serde(with = "database::amount::string")]
+    available: i128,
+    #[serde(with = "database::amount::string")]
+    locked: i128,
 }
 
 // Events for DB-Updater queue
@@ -91,7 +159,7 @@ pub enum DBUpdateEvent {
     OrderCreated(Order),
     OrderUpdated(Order),
     TradeExecuted(Trade),
-    BalanceUpdated { user_id: Uuid, token_id: Uuid, available: i64, locked: i64 },
+    BalanceUpdated { user_id: Uuid, token_id: Uuid, available: i128, locked: i128 },
 }
 
 // Events for WebSocket PubSub
@@ -104,8 +172,8 @@ pub enum MarketEvent {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderBookSnapshot {
-    pub bids: Vec<(i64, i64)>, // (price, total_quantity)
-    pub asks: Vec<(i64, i64)>,
+    pub bids: Vec<(i128, i128)>, // (price, total_quantity)
+    pub asks: Vec<(i128, i128)>,
     pub timestamp: i64,
 }
 
@@ -131,16 +199,325 @@ pub struct TokenInfo {
     pub is_active: bool,
 }
 
-// Update the DepthUpdate struct to include both decimal and atomic values
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DepthUpdate {
-    pub market_id: Uuid,
-    pub seq: u64,
-    pub ts: i64,
-    pub bids: Vec<(f64, f64)>,        // Decimal values (price, quantity)
-    pub asks: Vec<(f64, f64)>,        // Decimal values (price, quantity)
-    pub bids_atomic: Vec<(i64, i64)>, // Atomic values for debugging
-    pub asks_atomic: Vec<(i64, i64)>, // Atomic values for debugging
+/// Price times quantity of two atomic amounts. Needs 256 bits: at 18 decimals on both
+/// sides a price of 3000 times one whole base token is already 3e39.
+pub fn notional(price: i128, quantity: i128) -> Result<U256, String> {
+    if price < 0 || quantity < 0 {
+        return Err("Price and quantity must be non-negative".to_string());
+    }
+    U256::from(price as u128)
+        .checked_mul(U256::from(quantity as u128))
+        .ok_or_else(|| "Order value overflows".to_string())
+}
+
+/// Quote amount of a notional (price * quantity in atomic units) for a market whose base
+/// token has `decimals` places. The rounding policy is explicit: buyers' costs and
+/// reservations round up, sellers' proceeds round down.
+pub fn quote_amount(notional: U256, decimals: i32, mode: RoundingMode) -> Result<i128, String> {
+    if !(0..=18).contains(&decimals) {
+        return Err(format!("Decimals {} exceeds maximum supported (18)", decimals));
+    }
+    let divisor = U256::exp10(decimals as usize);
+    let (quotient, remainder) = notional.div_mod(divisor);
+    let rounded = match mode {
+        RoundingMode::Exact if !remainder.is_zero() => return Err(format!("Notional {} is not a whole quote amount", notional)),
+        RoundingMode::Up if !remainder.is_zero() => quotient + 1,
+        RoundingMode::HalfUp if remainder * 2 >= divisor => quotient + 1,
+        _ => quotient,
+    };
+    if rounded > U256::from(i128::MAX as u128) {
+        return Err("Order value overflows".to_string());
+    }
+    Ok(rounded.as_u128() as i128)
+}
+
+/// Record a fill on `order` and return the quote it pays or receives for it.
+/// Amounts are cumulative per order (`rounded(total) - rounded(previous total)`), so
+/// an order never pays more in total than `quote_amount(price * quantity)` it reserved.
+/// On overflow the order is left untouched.
+fn settle_fill(order: &mut Order, notional: U256, decimals: i32, mode: RoundingMode) -> Result<i128, String> {
+    let filled = order.filled_notional.checked_add(notional)
+        .ok_or_else(|| "Filled value overflows".to_string())?;
+    let amount = quote_amount(filled, decimals, mode)? - quote_amount(order.filled_notional, decimals, mode)?;
+    order.filled_notional = filled;
+    Ok(amount)
+}
+
+/// Settle one trade between `buyer` and `seller`: the buyer pays the cumulative cost
+/// rounded up, the seller receives it rounded down. Returns (buyer cost, seller proceeds);
+/// on error neither order is changed.
+fn settle_trade(buyer: &mut Order, seller: &mut Order, price: i128, quantity: i128, decimals: i32) -> Result<(i128, i128), String> {
+    let notional = notional(price, quantity)?;
+    let buyer_filled = buyer.filled_notional;
+    let buyer_cost = settle_fill(buyer, notional, decimals, RoundingMode::Up)?;
+    match settle_fill(seller, notional, decimals, RoundingMode::Down) {
+        Ok(seller_proceeds) => Ok((buyer_cost, seller_proceeds)),
+        Err(e) => {
+            buyer.filled_notional = buyer_filled;
+            Err(e)
+        }
+    }
+}
+
+/// What a resting order still has locked: the base it has yet to sell, or for a buy the quote
+/// reserved at placement less what its fills paid so far (both rounded up, as in `settle_fill`)
+fn outstanding_reservation(order: &Order, market: &MarketInfo) -> Result<i128, String> {
+    match order.order_type {
+        OrderType::Sell => Ok(order.quantity - order.filled_quantity),
+        OrderType::Buy => {
+            let price = order.price.ok_or_else(|| "Resting buy without a price".to_string())?;
+            let decimals = market.base_currency.decimals;
+            Ok(quote_amount(notional(price, order.quantity)?, decimals, RoundingMode::Up)?
+                - quote_amount(order.filled_notional, decimals, RoundingMode::Up)?)
+        }
+    }
+}
+
+fn l3_side(order_type: &OrderType) -> L3Side {
+    match order_type {
+        OrderType::Buy => L3Side::Buy,
+        OrderType::Sell => L3Side::Sell,
+    }
+}
+
+impl OrderBook {
+    /// Match a limit order against the opposite side and rest whatever is left of it.
+    /// Resting orders keep their original quantity; what can still trade is `quantity - filled_quantity`.
+    fn match_limit_order(&mut self, order: &mut Order, market_info: &MarketInfo) -> (Vec<Trade>, Vec<Order>) {
+        let mut trades = Vec::new();
+        let mut matched_orders = Vec::new();
+        let order_price = order.price.expect("Limit order must have a price");
+        let mut remaining_quantity = order.quantity;
+
+        // First, try to match against existing orders
+        let opposite_side = match order.order_type {
+            OrderType::Buy => &mut self.asks,   // Buy orders match against asks
+            OrderType::Sell => &mut self.bids, // Sell orders match against bids
+        };
+        println!("Opposite side: {:?}", opposite_side);
+        let mut prices_to_remove = Vec::new();
+
+        // Get prices that can match with this limit order
+        let matching_prices: Vec<i128> = match order.order_type {
+            OrderType::Buy => {
+                // Buy limit order matches with asks at or below the limit price
+                opposite_side.keys()
+                    .filter(|&&ask_price| ask_price <= order_price)
+                    .cloned()
+                    .collect()
+            }
+            OrderType::Sell => {
+                // Sell limit order matches with bids at or above the limit price
+                opposite_side.keys()
+                    .filter(|&&bid_price| bid_price >= order_price)
+                    .rev() // Start with highest bids
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        // Execute matches
+        let mut rejected = false;
+        for price in matching_prices {
+            if remaining_quantity == 0 || rejected { break; }
+
+            if let Some(order_queue) = opposite_side.get_mut(&price) {
+                while let Some(mut matching_order) = order_queue.pop_front() {
+                    if remaining_quantity == 0 { break; }
+
+                    let available_quantity = matching_order.quantity - matching_order.filled_quantity;
+                    let trade_quantity = remaining_quantity.min(available_quantity);
+
+                    // Create trade at the maker's price (price improvement for taker)
+                    // Buyer pays the cumulative cost rounded up, seller receives it rounded down
+                    let (buyer, seller) = match order.order_type {
+                        OrderType::Buy => (&mut *order, &mut matching_order),
+                        OrderType::Sell => (&mut matching_order, &mut *order),
+                    };
+                    let (buyer_cost, seller_proceeds) = match settle_trade(buyer, seller, price, trade_quantity, market_info.base_currency.decimals) {
+                        Ok(amounts) => amounts,
+                        Err(e) => {
+                            // Leave the maker where it was and stop matching this order
+                            tracing::error!("❌ Rejecting fill of order {} against {}: {}", order.id, matching_order.id, e);
+                            order_queue.push_front(matching_order);
+                            rejected = true;
+                            break;
+                        }
+                    };
+                    buyer.reserved -= buyer_cost;
+                    seller.reserved -= trade_quantity;
+
+                    let trade = Trade {
+                        id: Uuid::new_v4(),
+                        market_id: order.market_id,
+                        buyer_order_id: if matches!(order.order_type, OrderType::Buy) { 
+                            order.id 
+                        } else { 
+                            matching_order.id 
+                        },
+                        seller_order_id: if matches!(order.order_type, OrderType::Sell) { 
+                            order.id 
+                        } else { 
+                            matching_order.id 
+                        },
+                        buyer_user_id: if matches!(order.order_type, OrderType::Buy) { 
+                            order.user_id 
+                        } else { 
+                            matching_order.user_id 
+                        },
+                        seller_user_id: if matches!(order.order_type, OrderType::Sell) { 
+                            order.user_id 
+                        } else { 
+                            matching_order.user_id 
+                        },
+                        price,
+                        quantity: trade_quantity, // This IS the quantity that was traded!
+                        timestamp: Utc::now().timestamp_millis(),
+                        buyer_client_order_id: if matches!(order.order_type, OrderType::Buy) {
+                            order.client_order_id.clone()
+                        } else {
+                            matching_order.client_order_id.clone()
+                        },
+                        seller_client_order_id: if matches!(order.order_type, OrderType::Sell) {
+                            order.client_order_id.clone()
+                        } else {
+                            matching_order.client_order_id.clone()
+                        },
+                        taker_side: order.order_type.clone(),
+                        buyer_cost,
+                        seller_proceeds,
+                    };
+
+                    trades.push(trade.clone());
+
+                    // Update orders
+                    order.filled_quantity += trade_quantity;
+                    matching_order.filled_quantity += trade_quantity;
+                    remaining_quantity -= trade_quantity;
+
+                    tracing::info!("Limit order trade: {} {} @ {} in {}", 
+                        trade_quantity, 
+                        market_info.base_currency.symbol, 
+                        price, 
+                        market_info.symbol
+                    );
+
+                    // Update matching order status
+                    if matching_order.filled_quantity >= matching_order.quantity {
+                        matching_order.status = OrderStatus::Filled;
+                        matched_orders.push(matching_order.clone());
+                    } else {
+                        matching_order.status = OrderStatus::PartiallyFilled;
+                        matched_orders.push(matching_order.clone());
+                        order_queue.push_front(matching_order);
+                        break;
+                    }
+                }
+
+                if order_queue.is_empty() {
+                    prices_to_remove.push(price);
+                }
+            }
+        }
+
+        // Clean up empty price levels
+        for price in prices_to_remove {
+            opposite_side.remove(&price);
+        }
+
+        // Add remaining quantity to the orderbook if not fully filled; after a rejected
+        // fill the order would cross the book, so its remainder is cancelled instead
+        if remaining_quantity > 0 && !rejected {
+            let same_side = match order.order_type {
+                OrderType::Buy => &mut self.bids,
+                OrderType::Sell => &mut self.asks,
+            };
+
+            // Rest the order itself: it keeps its quantity and fills so far, like a partially filled maker
+            let mut resting_order = order.clone();
+            resting_order.status = if order.filled_quantity > 0 {
+                OrderStatus::PartiallyFilled
+            } else {
+                OrderStatus::Pending
+            };
+
+            let price_level = same_side.entry(order_price).or_insert_with(VecDeque::new);
+            price_level.push_back(resting_order);
+
+            tracing::info!("📋 Added {} {} to {} orderbook at price {} in {}", 
+                remaining_quantity, 
+                market_info.base_currency.symbol,
+                if matches!(order.order_type, OrderType::Buy) { "bid" } else { "ask" },
+                order_price,
+                market_info.symbol
+            );
+        }
+
+        // Update order status
+        order.status = if order.filled_quantity >= order.quantity {
+            OrderStatus::Filled
+        } else if rejected {
+            OrderStatus::Cancelled
+        } else if order.filled_quantity > 0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Pending
+        };
+
+        (trades, matched_orders)
+    }
+
+    /// Recompute what every resting order still has locked from its price, size and fills,
+    /// instead of trusting the `reserved` of a snapshot. Orders that cannot be priced keep it.
+    pub fn rebuild_reservations(&mut self, market: &MarketInfo) {
+        for order in self.bids.values_mut().chain(self.asks.values_mut()).flatten() {
+            match outstanding_reservation(order, market) {
+                Ok(reserved) => order.reserved = reserved,
+                Err(e) => tracing::error!("❌ Cannot rebuild the reservation of order {}: {}", order.id, e),
+            }
+        }
+    }
+
+    /// Resting orders of a user on both sides
+    fn orders_of(&self, user_id: Uuid) -> impl Iterator<Item = &Order> {
+        self.bids.values().chain(self.asks.values()).flatten().filter(move |order| order.user_id == user_id)
+    }
+}
+
+/// Aggregated quantity per price level, as last published on the depth feed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DepthLevels {
+    pub bids: BTreeMap<i128, i128>,
+    pub asks: BTreeMap<i128, i128>,
+}
+
+/// Levels of `new` that differ from `old`, with quantity 0 for removed ones
+fn diff_levels(old: &BTreeMap<i128, i128>, new: &BTreeMap<i128, i128>) -> Vec<(i128, i128)> {
+    let removed = old.keys().filter(|price| !new.contains_key(price)).map(|&price| (price, 0));
+    let changed = new.iter().filter(|(price, qty)| old.get(price) != Some(qty)).map(|(&price, &qty)| (price, qty));
+    let mut levels: Vec<(i128, i128)> = removed.chain(changed).collect();
+    levels.sort_unstable();
+    levels
+}
+
+/// Hands out the global event sequence and remembers the last stamp per output channel
+#[derive(Debug, Default)]
+pub struct EventSequencer {
+    event_seq: u64,
+    channel_seq: HashMap<String, u64>,
+    input_id: Option<String>,
+}
+
+impl EventSequencer {
+    fn next(&mut self, channel: &str) -> EventMeta {
+        self.event_seq += 1;
+        let prev_event_seq = self.channel_seq.insert(channel.to_string(), self.event_seq).unwrap_or(0);
+        EventMeta {
+            event_seq: self.event_seq,
+            prev_event_seq,
+            input_id: self.input_id.clone(),
+        }
+    }
 }
 
 pub struct TradingEngine {
@@ -148,9 +525,18 @@ pub struct TradingEngine {
     orderbooks: HashMap<Uuid, OrderBook>,
     balances: HashMap<Uuid, UserBalance>,
     tickers: HashMap<Uuid, MarketTicker>,
+    rolling_tickers: HashMap<Uuid, RollingTicker>,
     markets: HashMap<Uuid, MarketInfo>,
     depth_seq: HashMap<Uuid, u64>,
-    ticker_seq: HashMap<Uuid, u64>,
+    l3_seq: HashMap<Uuid, u64>,
+    published_depth: HashMap<Uuid, DepthLevels>,
+    client_order_ids: HashMap<(Uuid, String), Uuid>, // (user_id, client_order_id) -> order_id
+    sequencer: EventSequencer,
+
+    // INVARIANTS: Deposits minus withdrawals per token, and whether trading is halted
+    net_deposits: HashMap<Uuid, i128>,
+    invariant_mode: InvariantMode,
+    halted: bool,
     
     // REDIS: Communication layer
     redis_manager: ConnectionManager,
@@ -169,9 +555,16 @@ impl TradingEngine {
             orderbooks: HashMap::new(),
             balances: HashMap::new(),
             tickers: HashMap::new(),
+            rolling_tickers: HashMap::new(),
             markets: HashMap::new(),
             depth_seq: HashMap::new(),
-            ticker_seq: HashMap::new(),
+            l3_seq: HashMap::new(),
+            published_depth: HashMap::new(),
+            client_order_ids: HashMap::new(),
+            sequencer: EventSequencer::default(),
+            net_deposits: HashMap::new(),
+            invariant_mode: InvariantMode::from_env(),
+            halted: false,
 
             redis_manager,
             operations_since_snapshot: 0,
@@ -189,8 +582,31 @@ impl TradingEngine {
             order_request.price
         );
         
+        if self.halted {
+            tracing::warn!("❌ Trading halted, rejecting order {}", order_request.request_id);
+            return crate::redis_manager::OrderResponse {
+                request_id: order_request.request_id, success: false, status: "REJECTED".to_string(),
+                order_id: None, client_order_id: order_request.client_order_id,
+                message: "Trading is halted".to_string(),
+                filled_quantity: None, remaining_quantity: None, average_price: None, trades: None
+            };
+        }
+
+        // Client order ids are unique per user: reject a retry and point at the existing order
+        if let Some(client_order_id) = &order_request.client_order_id {
+            if let Some(&existing_id) = self.client_order_ids.get(&(order_request.user_id, client_order_id.clone())) {
+                tracing::warn!("❌ Duplicate client_order_id {} for user {}", client_order_id, order_request.user_id);
+                return crate::redis_manager::OrderResponse {
+                    request_id: order_request.request_id, success: false, status: "REJECTED".to_string(),
+                    order_id: Some(existing_id), client_order_id: order_request.client_order_id.clone(),
+                    message: format!("Duplicate client_order_id: order {} already exists", existing_id),
+                    filled_quantity: None, remaining_quantity: None, average_price: None, trades: None
+                };
+            }
+        }
+
         // 1. Convert request to internal order
-        let order = self.create_order_from_request(order_request.clone());
+        let mut order = self.create_order_from_request(order_request.clone());
         println!("Order: {:?}", order);
         
         // 2) Validate + lock funds BEFORE persisting created
@@ -200,7 +616,7 @@ impl TradingEngine {
                 tracing::warn!("❌ Market not found");
                 return crate::redis_manager::OrderResponse {
                     request_id: order_request.request_id, success: false, status: "REJECTED".to_string(),
-                    order_id: None, message: "Market not found".to_string(),
+                    order_id: None, client_order_id: order_request.client_order_id, message: "Market not found".to_string(),
                     filled_quantity: None, remaining_quantity: None, average_price: None, trades: None
                 };
             }
@@ -216,6 +632,7 @@ impl TradingEngine {
                     success: false,
                     status: "REJECTED".to_string(),
                     order_id: None,
+                    client_order_id: order_request.client_order_id,
                     message: msg,
                     filled_quantity: None,
                     remaining_quantity: None,
@@ -224,49 +641,44 @@ impl TradingEngine {
                 };
             }
         };
+        order.reserved = reservation.amount;
+
+        if let Some(client_order_id) = &order.client_order_id {
+            self.client_order_ids.insert((order.user_id, client_order_id.clone()), order.id);
+        }
 
         self.queue_order_created(&order).await;
         // 3. Execute matching in memory
-        let (updated_order, matched_orders, trades) = self.match_order(order).await;
+        let (mut updated_order, matched_orders, trades) = self.match_order(order).await;
         println!("Trades: {:?}, Orders: {:?}", trades, updated_order);
         
-        if matches!(updated_order.order_kind, OrderKind::Market) {
-            match updated_order.order_type {
-                OrderType::Buy => {
-                    let market = self.markets.get(&updated_order.market_id).unwrap();
-                    let mut executed_cost = 0i64;
-                    
-                    // Calculate executed cost using safe_multiply_divide for each trade
-                    for trade in &trades {
-                        match self.safe_multiply_divide(trade.price, trade.quantity, market.base_currency.decimals) {
-                            Ok(cost) => executed_cost += cost,
-                            Err(e) => {
-                                tracing::error!("Error calculating trade cost for refund: {}", e);
-                                // Continue with other trades, don't fail the entire order
-                            }
-                        }
-                    }
-                    if reservation.token_id == self.markets.get(&updated_order.market_id).unwrap().quote_currency.id {
-                        let refund = reservation.amount.saturating_sub(executed_cost);
-                        if refund > 0 {
-                            self.unlock(updated_order.user_id, reservation.token_id, refund).await;
-                        }
-                    }
-                }
-                OrderType::Sell => {
-                    let remaining = updated_order.quantity - updated_order.filled_quantity;
-                    if remaining > 0 && reservation.token_id == self.markets.get(&updated_order.market_id).unwrap().base_currency.id {
-                        self.unlock(updated_order.user_id, reservation.token_id, remaining).await;
-                    }
-                }
+        // Release what is still locked for orders that will not rest on the book:
+        // unfilled market orders, and buys filled below their limit price
+        let finished = matches!(updated_order.order_kind, OrderKind::Market)
+            || matches!(updated_order.status, OrderStatus::Cancelled)
+            || updated_order.filled_quantity >= updated_order.quantity;
+        if finished && updated_order.reserved > 0 {
+            self.unlock(updated_order.user_id, reservation.token_id, updated_order.reserved).await;
+            updated_order.reserved = 0;
+        }
+        for maker in &matched_orders {
+            if matches!(maker.status, OrderStatus::Filled) && maker.reserved > 0 {
+                let token_id = match maker.order_type {
+                    OrderType::Buy => market.quote_currency.id,
+                    OrderType::Sell => market.base_currency.id,
+                };
+                self.unlock(maker.user_id, token_id, maker.reserved).await;
             }
         }
 
         let market_id = updated_order.market_id;
+        self.publish_order_updates(std::iter::once(&updated_order).chain(&matched_orders)).await;
+        self.publish_fills(&updated_order, &trades).await;
         println!("Publishing depth for market {}", market_id);
+        let l3_events = self.l3_events_for_match(&updated_order, &matched_orders, &trades);
+        self.publish_l3(market_id, l3_events).await;
         self.publish_depth(market_id).await;
         if !trades.is_empty() {
-            self.publish_depth(market_id).await;
             self.publish_trades(market_id, &trades).await;   // new trades
             self.publish_ticker(market_id).await;            // last price from trades
         }
@@ -288,6 +700,7 @@ impl TradingEngine {
             success: true,
             status: if updated_order.filled_quantity == updated_order.quantity { "FILLED" } else { "PARTIALLY_FILLED" }.to_string(),
             order_id: Some(updated_order.id),
+            client_order_id: updated_order.client_order_id.clone(),
             message: "Order processed successfully".to_string(),
             filled_quantity: Some(updated_order.filled_quantity),
             remaining_quantity: Some(updated_order.quantity - updated_order.filled_quantity),
@@ -359,96 +772,177 @@ impl TradingEngine {
         (order, matched_orders, trades)
     }
     // KEEP THIS FUNCTION
-    fn build_depth(&self, market_id: Uuid, top_n: usize) -> Option<(Vec<(i64,i64)>, Vec<(i64,i64)>)> {
+    fn aggregate_depth(&self, market_id: Uuid) -> Option<DepthLevels> {
         let ob = self.orderbooks.get(&market_id)?;
-        
-        let bids = ob.bids.iter().rev().take(top_n).filter_map(|(&price, orders)| {
-            let mut total_quantity = 0i64;
-            for order in orders {
-                let remaining = order.quantity - order.filled_quantity;
-                match total_quantity.checked_add(remaining) {
-                    Some(new_total) => total_quantity = new_total,
-                    None => {
-                        tracing::warn!("Quantity overflow in depth for market {} at price {}", market_id, price);
-                        return None;
-                    }
-                }
-            }
-            Some((price, total_quantity))
-        }).collect();
-        
-        let asks = ob.asks.iter().take(top_n).filter_map(|(&price, orders)| {
-            let mut total_quantity = 0i64;
-            for order in orders {
-                let remaining = order.quantity - order.filled_quantity;
-                match total_quantity.checked_add(remaining) {
-                    Some(new_total) => total_quantity = new_total,
-                    None => {
-                        tracing::warn!("Quantity overflow in depth for market {} at price {}", market_id, price);
-                        return None;
+        let aggregate = |side: &BTreeMap<i128, VecDeque<Order>>| -> BTreeMap<i128, i128> {
+            side.iter().filter_map(|(&price, orders)| {
+                let mut total_quantity = 0i128;
+                for order in orders {
+                    let remaining = order.quantity - order.filled_quantity;
+                    match total_quantity.checked_add(remaining) {
+                        Some(new_total) => total_quantity = new_total,
+                        None => {
+                            tracing::warn!("Quantity overflow in depth for market {} at price {}", market_id, price);
+                            return None;
+                        }
                     }
                 }
-            }
-            Some((price, total_quantity))
-        }).collect();
+                (total_quantity > 0).then_some((price, total_quantity))
+            }).collect()
+        };
         
-        Some((bids, asks))
+        Some(DepthLevels { bids: aggregate(&ob.bids), asks: aggregate(&ob.asks) })
     }
 
-    // Update the publish_depth function
-async fn publish_depth(&mut self, market_id: Uuid) {
-    println!("Publishing depth for market {}", market_id);
-    if let Some((bids_atomic, asks_atomic)) = self.build_depth(market_id, 50) { // top 50
-        if let Some(market_info) = self.markets.get(&market_id) {
-            // Convert to decimal format
-            let bids_decimal: Vec<(f64, f64)> = bids_atomic.iter().map(|&(price, quantity)| {
-                let raw_price = price_from_atomic_units(price, market_info);
-                let raw_quantity = quantity_from_atomic_units(quantity, market_info);
-                let formatted_price = format_price_to_tick_precision(raw_price, market_info.tick_size, market_info.quote_currency.decimals);
-                let formatted_quantity = format_quantity_to_precision(raw_quantity, market_info.min_order_size, market_info.base_currency.decimals);
-                (formatted_price, formatted_quantity)
-            }).collect();
-
-            let asks_decimal: Vec<(f64, f64)> = asks_atomic.iter().map(|&(price, quantity)| {
-                let raw_price = price_from_atomic_units(price, market_info);
-                let raw_quantity = quantity_from_atomic_units(quantity, market_info);
-                let formatted_price = format_price_to_tick_precision(raw_price, market_info.tick_size, market_info.quote_currency.decimals);
-                let formatted_quantity = format_quantity_to_precision(raw_quantity, market_info.min_order_size, market_info.base_currency.decimals);
-                (formatted_price, formatted_quantity)
-            }).collect();
-
-            let seq = self.depth_seq.entry(market_id).and_modify(|s| *s += 1).or_insert(1);
-            let enhanced_update = EnhancedDepthUpdate {
+    /// Publish the levels that changed since the last delta and store the full
+    /// book under `snapshot:depth:<market_id>`, tagged with the same `seq`
+    async fn publish_depth(&mut self, market_id: Uuid) {
+        let Some(market_info) = self.markets.get(&market_id) else { return };
+        let levels = self.aggregate_depth(market_id).unwrap_or_default();
+        let published = self.published_depth.get(&market_id).cloned().unwrap_or_default();
+        if levels == published {
+            return;
+        }
+
+        let to_decimal = |levels: Vec<(i128, i128)>| -> Vec<(Decimal, Decimal)> {
+            levels.into_iter().map(|(price, quantity)| {
+                (price_from_atomic_units(price, market_info), quantity_from_atomic_units(quantity, market_info))
+            }).collect()
+        };
+
+        let seq = *self.depth_seq.entry(market_id).and_modify(|s| *s += 1).or_insert(1);
+        let ts = chrono::Utc::now().timestamp_millis();
+        let channel = format!("depth:{}", market_id);
+        let delta = EnhancedDepthUpdate {
+            market_id,
+            seq,
+            ts,
+            bids: to_decimal(diff_levels(&published.bids, &levels.bids).into_iter().rev().collect()),
+            asks: to_decimal(diff_levels(&published.asks, &levels.asks)),
+            decimals: FeedDecimals::of(market_info),
+            meta: self.sequencer.next(&channel),
+        };
+        let snapshot = DepthSnapshot {
+            market_id,
+            seq,
+            ts,
+            bids: to_decimal(levels.bids.iter().rev().map(|(&p, &q)| (p, q)).collect()),
+            asks: to_decimal(levels.asks.iter().map(|(&p, &q)| (p, q)).collect()),
+            decimals: FeedDecimals::of(market_info),
+        };
+
+        let mut conn = self.redis_manager.clone();
+        // Snapshot first, so a client that sees delta `seq` can always fetch a snapshot >= `seq`
+        let _: Result<(), _> = conn.set(
+            format!("snapshot:depth:{}", market_id),
+            serde_json::to_string(&snapshot).unwrap()
+        ).await;
+        let _: Result<(), _> = conn.publish(
+            channel,
+            serde_json::to_string(&delta).unwrap()
+        ).await;
+        self.published_depth.insert(market_id, levels);
+    }
+
+    /// Books are rebuilt from scratch on startup: store an empty snapshot per market
+    /// so stale snapshots from a previous run are not served
+    pub async fn reset_depth_snapshots(&mut self) {
+        let mut conn = self.redis_manager.clone();
+        for market_info in self.markets.values() {
+            let snapshot = DepthSnapshot {
+                market_id: market_info.id,
+                seq: 0,
+                ts: chrono::Utc::now().timestamp_millis(),
+                bids: Vec::new(),
+                asks: Vec::new(),
+                decimals: FeedDecimals::of(market_info),
+            };
+            let _: Result<(), _> = conn.set(
+                format!("snapshot:depth:{}", market_info.id),
+                serde_json::to_string(&snapshot).unwrap()
+            ).await;
+        }
+    }
+
+    /// L3 events caused by one incoming order: executions against resting orders, their
+    /// new size or removal, and the incoming order's remainder if it rests on the book
+    fn l3_events_for_match(&self, taker: &Order, matched_orders: &[Order], trades: &[Trade]) -> Vec<L3Event> {
+        let Some(market_info) = self.markets.get(&taker.market_id) else { return Vec::new() };
+        let maker_side = match taker.order_type {
+            OrderType::Buy => L3Side::Sell,
+            OrderType::Sell => L3Side::Buy,
+        };
+        let mut events = Vec::new();
+
+        for trade in trades {
+            let order_id = match taker.order_type {
+                OrderType::Buy => trade.seller_order_id,
+                OrderType::Sell => trade.buyer_order_id,
+            };
+            events.push(L3Event::Execute {
+                order_id,
+                side: maker_side,
+                price: price_from_atomic_units(trade.price, market_info),
+                size: quantity_from_atomic_units(trade.quantity, market_info),
+                trade_id: trade.id,
+            });
+        }
+        for maker in matched_orders {
+            let price = price_from_atomic_units(maker.price.unwrap_or(0), market_info);
+            let remaining = maker.quantity - maker.filled_quantity;
+            events.push(if remaining > 0 {
+                L3Event::Modify { order_id: maker.id, side: maker_side, price, size: quantity_from_atomic_units(remaining, market_info) }
+            } else {
+                L3Event::Remove { order_id: maker.id, side: maker_side, price, reason: L3RemoveReason::Filled }
+            });
+        }
+
+        let remaining = taker.quantity - taker.filled_quantity;
+        if let (OrderKind::Limit, Some(price), true) = (&taker.order_kind, taker.price, remaining > 0) {
+            events.push(L3Event::Add {
+                order_id: taker.id,
+                side: l3_side(&taker.order_type),
+                price: price_from_atomic_units(price, market_info),
+                size: quantity_from_atomic_units(remaining, market_info),
+            });
+        }
+        events
+    }
+
+    async fn publish_l3(&mut self, market_id: Uuid, events: Vec<L3Event>) {
+        let Some(market_info) = self.markets.get(&market_id) else { return };
+        let decimals = FeedDecimals::of(market_info);
+        let channel = format!("l3:{}", market_id);
+        let mut conn = self.redis_manager.clone();
+        for event in events {
+            let seq = *self.l3_seq.entry(market_id).and_modify(|s| *s += 1).or_insert(1);
+            let update = L3Update {
                 market_id,
-                seq: *seq,
+                seq,
                 ts: chrono::Utc::now().timestamp_millis(),
-                bids: bids_decimal,
-                asks: asks_decimal,
-                bids_atomic,
-                asks_atomic,
+                event,
+                decimals,
+                meta: self.sequencer.next(&channel),
             };
-            
-            let mut conn = self.redis_manager.clone();
             let _: Result<(), _> = conn.publish(
-                format!("depth:{}", market_id),
-                serde_json::to_string(&enhanced_update).unwrap()
+                &channel,
+                serde_json::to_string(&update).unwrap()
             ).await;
         }
     }
-}
 
     // Update the publish_ticker function
 async fn publish_ticker(&mut self, market_id: Uuid) {
     if let Some(atomic_ticker) = self.tickers.get(&market_id) {
         if let Some(market_info) = self.markets.get(&market_id) {
-            let enhanced_ticker = convert_ticker_to_decimal(atomic_ticker, market_info);
-            
-            let _seq = self.ticker_seq.entry(market_id).and_modify(|s| *s += 1).or_insert(1);
+            let channel = format!("ticker:{}", market_id);
+            let enhanced_ticker = convert_ticker_to_decimal(atomic_ticker, market_info, self.sequencer.next(&channel));
+
             let mut conn = self.redis_manager.clone();
-            let _: Result<(), _> = conn.publish(
-                format!("ticker:{}", market_id),
-                serde_json::to_string(&enhanced_ticker).unwrap()
-            ).await;
+            let json = serde_json::to_string(&enhanced_ticker).unwrap();
+            // Latest published ticker, for REST reads
+            let _: Result<(), _> = conn.set(format!("latest:ticker:{}", market_id), &json).await;
+            let _: Result<(), _> = conn.publish(channel, json).await;
         }
     }
 }
@@ -458,17 +952,62 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
         if trades.is_empty() { return; }
         if let Some(market_info) = self.markets.get(&market_id) {
             let mut conn = self.redis_manager.clone();
+            let channel = format!("trades:{}", market_id);
             for trade in trades {
                 // Convert trade to decimal format
-                let enhanced_trade = convert_trade_to_decimal(trade, market_info);
+                let enhanced_trade = convert_trade_to_decimal(trade, market_info, self.sequencer.next(&channel));
                 
                 let _: Result<(), _> = conn.publish(
-                    format!("trades:{}", market_id),
+                    &channel,
                     serde_json::to_string(&enhanced_trade).unwrap()
                 ).await;
             }
         }
     }
+    /// Current state of each order on its owner's private `orders:<user_id>` channel
+    async fn publish_order_updates(&mut self, orders: impl Iterator<Item = &Order>) {
+        let mut conn = self.redis_manager.clone();
+        for order in orders {
+            let Some(market_info) = self.markets.get(&order.market_id) else { continue };
+            let channel = format!("orders:{}", order.user_id);
+            let update = convert_order_update(order, market_info, self.sequencer.next(&channel));
+            let _: Result<(), _> = conn.publish(
+                &channel,
+                serde_json::to_string(&update).unwrap()
+            ).await;
+        }
+    }
+
+    /// Both sides of every trade on the private `fills:<user_id>` channels
+    async fn publish_fills(&mut self, taker: &Order, trades: &[Trade]) {
+        let Some(market_info) = self.markets.get(&taker.market_id) else { return };
+        let mut conn = self.redis_manager.clone();
+        for trade in trades {
+            let (buyer, seller) = if trade.buyer_order_id == taker.id {
+                (Liquidity::Taker, Liquidity::Maker)
+            } else {
+                (Liquidity::Maker, Liquidity::Taker)
+            };
+            for (side, user_id, liquidity) in [
+                (OrderType::Buy, trade.buyer_user_id, buyer),
+                (OrderType::Sell, trade.seller_user_id, seller),
+            ] {
+                let channel = format!("fills:{}", user_id);
+                let fill = convert_fill(trade, side, liquidity, market_info, self.sequencer.next(&channel));
+                let _: Result<(), _> = conn.publish(
+                    &channel,
+                    serde_json::to_string(&fill).unwrap()
+                ).await;
+            }
+        }
+    }
+
+    fn token_info(&self, token_id: Uuid) -> Option<&TokenInfo> {
+        self.markets.values()
+            .flat_map(|market| [&market.base_currency, &market.quote_currency])
+            .find(|token| token.id == token_id)
+    }
+
     // KEEP THIS FUNCTION
     async fn execute_market_order(&mut self, order: &mut Order, market_info: &MarketInfo) -> (Vec<Trade>, Vec<Order>) {
         let mut trades = Vec::new();
@@ -492,12 +1031,13 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
         let mut prices_to_remove = Vec::new();
 
         // BTreeMap is naturally sorted, but we need different iteration order
-        let price_levels: Vec<i64> = match order.order_type {
+        let price_levels: Vec<i128> = match order.order_type {
             OrderType::Buy => opposite_side.keys().cloned().collect(), // Ascending (best asks first)
             OrderType::Sell => opposite_side.keys().rev().cloned().collect(), // Descending (best bids first)
         };
+        let mut rejected = false;
         for price in price_levels {
-            if remaining_quantity == 0 { break; }
+            if remaining_quantity == 0 || rejected { break; }
     
             if let Some(order_queue) = opposite_side.get_mut(&price) {
                 while let Some(mut matching_order) = order_queue.pop_front() {
@@ -508,6 +1048,24 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                     println!("{:?}", trade_quantity);
     
                     // Create trade
+                    // Buyer pays the cumulative cost rounded up, seller receives it rounded down
+                    let (buyer, seller) = match order.order_type {
+                        OrderType::Buy => (&mut *order, &mut matching_order),
+                        OrderType::Sell => (&mut matching_order, &mut *order),
+                    };
+                    let (buyer_cost, seller_proceeds) = match settle_trade(buyer, seller, price, trade_quantity, market_info.base_currency.decimals) {
+                        Ok(amounts) => amounts,
+                        Err(e) => {
+                            // Leave the maker where it was and stop matching this order
+                            tracing::error!("❌ Rejecting fill of order {} against {}: {}", order.id, matching_order.id, e);
+                            order_queue.push_front(matching_order);
+                            rejected = true;
+                            break;
+                        }
+                    };
+                    buyer.reserved -= buyer_cost;
+                    seller.reserved -= trade_quantity;
+
                     let trade = Trade {
                         id: Uuid::new_v4(),
                         market_id: order.market_id,
@@ -534,6 +1092,19 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                         price,
                         quantity: trade_quantity, // This IS the quantity that was traded!
                         timestamp: Utc::now().timestamp_millis(),
+                        buyer_client_order_id: if matches!(order.order_type, OrderType::Buy) {
+                            order.client_order_id.clone()
+                        } else {
+                            matching_order.client_order_id.clone()
+                        },
+                        seller_client_order_id: if matches!(order.order_type, OrderType::Sell) {
+                            order.client_order_id.clone()
+                        } else {
+                            matching_order.client_order_id.clone()
+                        },
+                        taker_side: order.order_type.clone(),
+                        buyer_cost,
+                        seller_proceeds,
                     };
     
                     trades.push(trade.clone());
@@ -555,192 +1126,49 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                         matching_order.status = OrderStatus::Filled;
                         matched_orders.push(matching_order.clone());
                         // Don't put it back in the queue - it's fully filled
-                    } else {
-                        matching_order.status = OrderStatus::PartiallyFilled;
-                        matched_orders.push(matching_order.clone());
-                        order_queue.push_front(matching_order); // Put back partially filled order
-                        break; // This price level still has liquidity
-                    }
-                }
-    
-                if order_queue.is_empty() {
-                    prices_to_remove.push(price);
-                }
-            }
-        }
-
-        // Clean up empty price levels
-        for price in prices_to_remove {
-            opposite_side.remove(&price);
-        }
-        // Update order status
-        order.status = if order.filled_quantity >= order.quantity {
-            OrderStatus::Filled
-        } else if order.filled_quantity > 0 {
-            OrderStatus::PartiallyFilled
-        } else {
-            OrderStatus::Cancelled // Market order couldn't be filled
-        };
-
-        (trades, matched_orders)
-    }
-
-    // KEEP THIS FUNCTION
-    async fn execute_limit_order(&mut self, order: &mut Order, market_info: &MarketInfo) -> (Vec<Trade>, Vec<Order>) {
-        println!("Executing limit order: {:?}", order);
-        let mut trades = Vec::new();
-        let mut matched_orders = Vec::new();
-        let order_price = order.price.expect("Limit order must have a price");
-        let mut remaining_quantity = order.quantity;
-
-        // Get the orderbook
-        let orderbook = match self.orderbooks.get_mut(&order.market_id) {
-            Some(ob) => ob,
-            None => return (trades, matched_orders), // Should not happen, but safe fallback
-        };
-        println!("Orderbook: {:?}", orderbook);
-
-        // First, try to match against existing orders
-        let opposite_side = match order.order_type {
-            OrderType::Buy => &mut orderbook.asks,   // Buy orders match against asks
-            OrderType::Sell => &mut orderbook.bids, // Sell orders match against bids
-        };
-        println!("Opposite side: {:?}", opposite_side);
-        let mut prices_to_remove = Vec::new();
-
-        // Get prices that can match with this limit order
-        let matching_prices: Vec<i64> = match order.order_type {
-            OrderType::Buy => {
-                // Buy limit order matches with asks at or below the limit price
-                opposite_side.keys()
-                    .filter(|&&ask_price| ask_price <= order_price)
-                    .cloned()
-                    .collect()
-            }
-            OrderType::Sell => {
-                // Sell limit order matches with bids at or above the limit price
-                opposite_side.keys()
-                    .filter(|&&bid_price| bid_price >= order_price)
-                    .rev() // Start with highest bids
-                    .cloned()
-                    .collect()
-            }
-        };
-
-        // Execute matches
-        for price in matching_prices {
-            if remaining_quantity == 0 { break; }
-
-            if let Some(order_queue) = opposite_side.get_mut(&price) {
-                while let Some(mut matching_order) = order_queue.pop_front() {
-                    if remaining_quantity == 0 { break; }
-
-                    let available_quantity = matching_order.quantity - matching_order.filled_quantity;
-                    let trade_quantity = remaining_quantity.min(available_quantity);
-
-                    // Create trade at the maker's price (price improvement for taker)
-                    let trade = Trade {
-                        id: Uuid::new_v4(),
-                        market_id: order.market_id,
-                        buyer_order_id: if matches!(order.order_type, OrderType::Buy) { 
-                            order.id 
-                        } else { 
-                            matching_order.id 
-                        },
-                        seller_order_id: if matches!(order.order_type, OrderType::Sell) { 
-                            order.id 
-                        } else { 
-                            matching_order.id 
-                        },
-                        buyer_user_id: if matches!(order.order_type, OrderType::Buy) { 
-                            order.user_id 
-                        } else { 
-                            matching_order.user_id 
-                        },
-                        seller_user_id: if matches!(order.order_type, OrderType::Sell) { 
-                            order.user_id 
-                        } else { 
-                            matching_order.user_id 
-                        },
-                        price,
-                        quantity: trade_quantity, // This IS the quantity that was traded!
-                        timestamp: Utc::now().timestamp_millis(),
-                    };
-
-                    trades.push(trade.clone());
-
-                    // Update orders
-                    order.filled_quantity += trade_quantity;
-                    matching_order.filled_quantity += trade_quantity;
-                    remaining_quantity -= trade_quantity;
-
-                    tracing::info!("Limit order trade: {} {} @ {} in {}", 
-                        trade_quantity, 
-                        market_info.base_currency.symbol, 
-                        price, 
-                        market_info.symbol
-                    );
-
-                    // Update matching order status
-                    if matching_order.filled_quantity >= matching_order.quantity {
-                        matching_order.status = OrderStatus::Filled;
-                        matched_orders.push(matching_order.clone());
-                    } else {
-                        matching_order.status = OrderStatus::PartiallyFilled;
-                        matched_orders.push(matching_order.clone());
-                        order_queue.push_front(matching_order);
-                        break;
-                    }
-                }
-
-                if order_queue.is_empty() {
-                    prices_to_remove.push(price);
-                }
-            }
-        }
-
-        // Clean up empty price levels
-        for price in prices_to_remove {
-            opposite_side.remove(&price);
-        }
-
-        // Add remaining quantity to the orderbook if not fully filled
-        if remaining_quantity > 0 {
-            let same_side = match order.order_type {
-                OrderType::Buy => &mut orderbook.bids,
-                OrderType::Sell => &mut orderbook.asks,
-            };
-
-            // Create a new order for the remaining quantity
-            let mut remaining_order = order.clone();
-            remaining_order.quantity = remaining_quantity;
-            remaining_order.filled_quantity = 0;
-            remaining_order.status = OrderStatus::Pending;
-
-            let price_level = same_side.entry(order_price).or_insert_with(VecDeque::new);
-            price_level.push_back(remaining_order);
-
-            tracing::info!("📋 Added {} {} to {} orderbook at price {} in {}", 
-                remaining_quantity, 
-                market_info.base_currency.symbol,
-                if matches!(order.order_type, OrderType::Buy) { "bid" } else { "ask" },
-                order_price,
-                market_info.symbol
-            );
+                    } else {
+                        matching_order.status = OrderStatus::PartiallyFilled;
+                        matched_orders.push(matching_order.clone());
+                        order_queue.push_front(matching_order); // Put back partially filled order
+                        break; // This price level still has liquidity
+                    }
+                }
+    
+                if order_queue.is_empty() {
+                    prices_to_remove.push(price);
+                }
+            }
         }
 
+        // Clean up empty price levels
+        for price in prices_to_remove {
+            opposite_side.remove(&price);
+        }
         // Update order status
         order.status = if order.filled_quantity >= order.quantity {
             OrderStatus::Filled
         } else if order.filled_quantity > 0 {
             OrderStatus::PartiallyFilled
         } else {
-            OrderStatus::Pending
+            OrderStatus::Cancelled // Market order couldn't be filled
         };
 
         (trades, matched_orders)
     }
-    
+
+    // KEEP THIS FUNCTION
+    async fn execute_limit_order(&mut self, order: &mut Order, market_info: &MarketInfo) -> (Vec<Trade>, Vec<Order>) {
+        println!("Executing limit order: {:?}", order);
+
+        // Get the orderbook
+        let orderbook = match self.orderbooks.get_mut(&order.market_id) {
+            Some(ob) => ob,
+            None => return (Vec::new(), Vec::new()), // Should not happen, but safe fallback
+        };
+        println!("Orderbook: {:?}", orderbook);
+        orderbook.match_limit_order(order, market_info)
+    }
+
     // KEEP THIS FUNCTION
     async fn update_balances_from_trades(&mut self, trades: &[Trade], market_info: &MarketInfo) {
         for trade in trades {
@@ -750,32 +1178,19 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             let quote_id = market_info.quote_currency.id;
             let base_id  = market_info.base_currency.id;
 
-             // FIXED: Correct calculation for trade cost
-            let cost = self.safe_multiply_divide(trade.price, trade.quantity, market_info.base_currency.decimals).unwrap();
-            // Buyer: spend quote (locked if resting limit or immediate if market), credit base
-            let buyer_qoute_locked_now = self.balances.get(&buyer_id)
-                .and_then(|ub| ub.token_balances.get(&quote_id))
-                .map(|tb| tb.locked)
-                .unwrap_or(0);
-
-            let buyer_use_locked = buyer_qoute_locked_now.min(cost);
-
-            self.update_user_balance(buyer_id, quote_id, 0, -buyer_use_locked).await;
+            // Buyer: spend locked quote (cost rounded up), credit base
+            self.update_user_balance(buyer_id, quote_id, 0, -trade.buyer_cost).await;
             self.update_user_balance(buyer_id, base_id, trade.quantity, 0).await;
 
-            // Seller: spend base (locked if resting limit), credit quote
-
-            let seller_base_locked_now = self.balances
-                .get(&seller_id)
-                .and_then(|ub| ub.token_balances.get(&base_id))
-                .map(|tb| tb.locked)
-                .unwrap_or(0);
-
-            let seller_use_locked = seller_base_locked_now.min(trade.quantity);
+            // Seller: spend locked base, credit quote (proceeds rounded down)
+            self.update_user_balance(seller_id, base_id, 0, -trade.quantity).await;
+            self.update_user_balance(seller_id, quote_id, trade.seller_proceeds, 0).await;
 
-            self.update_user_balance(seller_id, base_id, 0, -seller_use_locked).await;
-            self.update_user_balance(seller_id, quote_id, cost, 0).await;
-            
+            // Rounding difference; can be negative for one fill but never in total
+            let dust = trade.buyer_cost - trade.seller_proceeds;
+            if dust != 0 {
+                self.update_user_balance(DUST_ACCOUNT_ID, quote_id, dust, 0).await;
+            }
 
             tracing::info!("💰 Updated balances for trade: {} {} @ {} between users {} and {}", 
                 trade.quantity, 
@@ -788,7 +1203,7 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
     }
 
     // KEEP THIS FUNCTION
-    async fn update_user_balance(&mut self, user_id: Uuid, token_id: Uuid, amount_delta: i64, locked_delta: i64) {
+    async fn update_user_balance(&mut self, user_id: Uuid, token_id: Uuid, amount_delta: i128, locked_delta: i128) {
         let user_balance = self.balances.entry(user_id).or_insert_with(|| UserBalance {
             user_id,
             token_balances: HashMap::new(),
@@ -799,132 +1214,240 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             locked: 0,
         });
 
-        token_balance.available += amount_delta;
-        token_balance.locked += locked_delta;
+        let (Some(available), Some(locked)) = (
+            token_balance.available.checked_add(amount_delta),
+            token_balance.locked.checked_add(locked_delta),
+        ) else {
+            tracing::error!("❌ Balance overflow for user {} token {}, update skipped", user_id, token_id);
+            return;
+        };
+        token_balance.available = available;
+        token_balance.locked = locked;
+        let balance = token_balance.clone();
 
         // Queue balance update for database
-        let balance_data = serde_json::json!({
-            "user_id": user_id,
-            "token_id": token_id,
-            "available": token_balance.available,
-            "locked": token_balance.locked
-        });
+        let record = BalanceRecord { user_id, token_id, available, locked };
+        self.queue_db_event("balance_updated", serde_json::to_string(&record).unwrap()).await;
 
-        // Send to db-updater queue
-        let mut conn = self.redis_manager.clone();
-        let _: Result<String, _> = redis::cmd("XADD")
-            .arg("db_update_queue")
-            .arg("*")
-            .arg("type")
-            .arg("balance_updated")
-            .arg("data")
-            .arg(balance_data.to_string())
-            .query_async(&mut conn)
-            .await;
+        // Tell the owner; tokens outside every loaded market have no decimals to render with
+        if let Some(token) = self.token_info(token_id).cloned() {
+            let channel = format!("balances:{}", user_id);
+            let update = convert_balance_update(&token, &balance, self.sequencer.next(&channel));
+            let mut conn = self.redis_manager.clone();
+            let _: Result<(), _> = conn.publish(
+                &channel,
+                serde_json::to_string(&update).unwrap()
+            ).await;
+        }
 }
     /// Update market ticker from trades
     async fn update_ticker_from_trades(&mut self, trades: &[Trade], market_info: &MarketInfo) {
         if trades.is_empty() { return; }
+        let market_id = market_info.id;
 
-        let ticker = self.tickers.entry(trades[0].market_id).or_insert_with(|| MarketTicker {
-            market_id: trades[0].market_id,
-            last_price: 0,
-            volume_24h: 0,
-            high_24h: 0,
-            low_24h: 0,
-            change_24h: 0.0,
-            timestamp: Utc::now().timestamp_millis(),
-        });
+        let rolling = self.rolling_tickers.entry(market_id).or_default();
+        for trade in trades {
+            let quote_volume = notional(trade.price, trade.quantity)
+                .and_then(|notional| quote_amount(notional, market_info.base_currency.decimals, RoundingMode::Down))
+                .unwrap_or(0);
+            rolling.record(trade.price, trade.quantity, quote_volume, trade.timestamp);
+        }
+        let ticker = rolling.stats(market_id, Utc::now().timestamp_millis());
 
-        // Update with latest trade price
-        if let Some(last_trade) = trades.last() {
-            ticker.last_price = last_trade.price;
-            ticker.timestamp = last_trade.timestamp;
-            
-            // Update high/low (simplified - in production you'd track 24h period)
-            if ticker.high_24h == 0 || last_trade.price > ticker.high_24h {
-                ticker.high_24h = last_trade.price;
-            }
-            if ticker.low_24h == 0 || last_trade.price < ticker.low_24h {
-                ticker.low_24h = last_trade.price;
-            }
+        // Persist the window right away so a restart does not lose it
+        let mut conn = self.redis_manager.clone();
+        if let Ok(json) = serde_json::to_string(rolling) {
+            let _: Result<(), _> = conn.set(format!("snapshot:ticker:{}", market_id), json).await;
+        }
 
-            // Add volume
-            for trade in trades {
-                ticker.volume_24h += trade.quantity;
+        tracing::info!("📊 Updated ticker for {}: last_price={}, volume_24h={}", 
+            market_info.symbol, 
+            ticker.last_price, 
+            ticker.volume_24h
+        );
+        self.tickers.insert(market_id, ticker);
+    }
+
+    /// Move every ticker's 24h window forward and publish those that changed without new trades
+    pub async fn roll_tickers(&mut self) {
+        let now = Utc::now().timestamp_millis();
+        let mut changed = Vec::new();
+        for (&market_id, rolling) in self.rolling_tickers.iter_mut() {
+            rolling.roll(now);
+            let ticker = rolling.stats(market_id, now);
+            let unchanged = self.tickers.get(&market_id).is_some_and(|old| {
+                old.open_24h == ticker.open_24h
+                    && old.high_24h == ticker.high_24h
+                    && old.low_24h == ticker.low_24h
+                    && old.volume_24h == ticker.volume_24h
+                    && old.trade_count_24h == ticker.trade_count_24h
+            });
+            self.tickers.insert(market_id, ticker);
+            if !unchanged {
+                changed.push(market_id);
             }
+        }
+        for market_id in changed {
+            self.publish_ticker(market_id).await;
+        }
+    }
 
-            tracing::info!("📊 Updated ticker for {}: last_price={}, volume_24h={}", 
-                market_info.symbol, 
-                ticker.last_price, 
-                ticker.volume_24h
-            );
+    /// Load persisted 24h windows so tickers stay correct across restarts
+    pub async fn load_ticker_snapshots(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.redis_manager.clone();
+        let now = Utc::now().timestamp_millis();
+        for (&market_id, _) in self.markets.iter() {
+            let raw: Option<String> = conn.get(format!("snapshot:ticker:{}", market_id)).await?;
+            // Older snapshots held a plain MarketTicker; those are skipped
+            let Some(mut rolling) = raw.and_then(|json| serde_json::from_str::<RollingTicker>(&json).ok()) else { continue };
+            rolling.roll(now);
+            self.tickers.insert(market_id, rolling.stats(market_id, now));
+            self.rolling_tickers.insert(market_id, rolling);
         }
+        tracing::info!("✅ Loaded {} ticker windows", self.rolling_tickers.len());
+        Ok(())
     }
     
     // KEEP THIS FUNCTION
     async fn queue_order_created(&mut self, order: &Order) {
-        let mut conn = self.redis_manager.clone();
         let order_json = serde_json::to_string(&order).unwrap();
-        let _: Result<String, _> = redis::cmd("XADD")
-            .arg("db_update_queue")
-            .arg("*")
-            .arg("type")
-            .arg("order_created")
-            .arg("data")
-            .arg(order_json)
-            .query_async(&mut conn)
-            .await;
+        self.queue_db_event("order_created", order_json).await;
         tracing::info!("📤 Queued order_created for {}", order.id);
     }
     // KEEP THIS FUNCTION
     /// Queue updates for db-updater service
     async fn queue_db_updates(&mut self, order: &Order, matched_orders: &[Order], trades: &[Trade]) {
-        let mut conn = self.redis_manager.clone();
-        
         // Queue order update
         let order_json = serde_json::to_string(&order).unwrap();
-        let _: Result<String, _> = redis::cmd("XADD")
-            .arg("db_update_queue")
-            .arg("*")
-            .arg("type")
-            .arg("order_updated")
-            .arg("data")
-            .arg(order_json)
-            .query_async(&mut conn)
-            .await; 
+        self.queue_db_event("order_updated", order_json).await;
 
         // Queue matched orders updates
         for maker in matched_orders {
             let maker_json = serde_json::to_string(&maker).unwrap();
-            let _: Result<String, _> = redis::cmd("XADD")
-                .arg("db_update_queue")
-                .arg("*")
-                .arg("type")
-                .arg("order_updated")
-                .arg("data")
-                .arg(maker_json)
-                .query_async(&mut conn)
-                .await;
+            self.queue_db_event("order_updated", maker_json).await;
         }
 
         // Queue trade events
         for trade in trades {
             let trade_json = serde_json::to_string(&trade).unwrap();
-            let _: Result<String, _> = redis::cmd("XADD")
-                .arg("db_update_queue")
-                .arg("*")
-                .arg("type")
-                .arg("trade_executed")
-                .arg("data")
-                .arg(trade_json)
-                .query_async(&mut conn)
-                .await;
+            self.queue_db_event("trade_executed", trade_json).await;
         }
         
         tracing::info!("📤 Queued {} DB updates", 1 + matched_orders.len() + trades.len());
     }
     
+    /// Append one stamped event to the db-updater stream
+    async fn queue_db_event(&mut self, event_type: &str, data: String) {
+        let meta = self.sequencer.next("db_update_queue");
+        let mut conn = self.redis_manager.clone();
+        let _: Result<String, _> = redis::cmd("XADD")
+            .arg("db_update_queue")
+            .arg("*")
+            .arg("type")
+            .arg(event_type)
+            .arg("data")
+            .arg(data)
+            .arg("seq")
+            .arg(meta.event_seq)
+            .arg("prev_seq")
+            .arg(meta.prev_event_seq)
+            .arg("input_id")
+            .arg(meta.input_id.unwrap_or_default())
+            .query_async(&mut conn)
+            .await;
+    }
+
+    /// Run the balance-conservation checks (if enabled) and report violations on `engine:invariants`
+    pub async fn check_invariants(&mut self) {
+        if self.invariant_mode == InvariantMode::Off {
+            return;
+        }
+
+        let violations = invariants::check(&self.balances, &self.orderbooks, &self.markets, &self.net_deposits);
+        if violations.is_empty() {
+            return;
+        }
+
+        if self.invariant_mode == InvariantMode::Halt && !self.halted {
+            tracing::error!("🛑 Halting trading after invariant violation");
+            self.halted = true;
+        }
+        tracing::error!("⚠️ {} invariant violations: {:?}", violations.len(), violations);
+
+        let report = InvariantReport {
+            input_id: self.sequencer.input_id.clone(),
+            halted: self.halted,
+            violations,
+            timestamp: Utc::now().timestamp_millis(),
+        };
+        let mut conn = self.redis_manager.clone();
+        let _: Result<(), _> = conn.publish(
+            "engine:invariants",
+            serde_json::to_string(&report).unwrap()
+        ).await;
+    }
+
+    /// Mark the start of a command read from `engine_processing_queue`; every event
+    /// emitted until `end_input` carries its stream id
+    pub fn begin_input(&mut self, stream_id: &str) {
+        self.sequencer.input_id = Some(stream_id.to_string());
+    }
+
+    /// Finish the current command and persist the sequence so it survives a restart
+    pub async fn end_input(&mut self) {
+        self.sequencer.input_id = None;
+        let mut conn = self.redis_manager.clone();
+        let _: Result<(), _> = conn.set("engine:event_seq", self.sequencer.event_seq).await;
+    }
+
+    /// Stamp for a response; responses go to one-off channels so there is no previous event
+    pub fn next_response_meta(&mut self) -> EventMeta {
+        let mut meta = self.sequencer.next("engine_response");
+        meta.prev_event_seq = 0;
+        meta
+    }
+
+    /// Resume the event sequence after a restart. The persisted counter can lag behind
+    /// a crash, so also look at the last event already written to the db-updater stream.
+    pub async fn load_event_seq(&mut self) {
+        let mut conn = self.redis_manager.clone();
+        let persisted: u64 = conn.get::<_, Option<u64>>("engine:event_seq").await
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+
+        let last_db_event: redis::Value = redis::cmd("XREVRANGE")
+            .arg("db_update_queue")
+            .arg("+")
+            .arg("-")
+            .arg("COUNT")
+            .arg(1)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(redis::Value::Nil);
+
+        let mut last_db_seq = 0u64;
+        if let redis::Value::Bulk(entries) = last_db_event {
+            if let Some(redis::Value::Bulk(entry)) = entries.first() {
+                if let Some(redis::Value::Bulk(fields)) = entry.get(1) {
+                    for chunk in fields.chunks(2) {
+                        if let (Some(redis::Value::Data(key)), Some(redis::Value::Data(value))) = (chunk.first(), chunk.get(1)) {
+                            if key.as_slice() == b"seq" {
+                                last_db_seq = String::from_utf8_lossy(value).parse().unwrap_or(0);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.sequencer.event_seq = persisted.max(last_db_seq);
+        if last_db_seq > 0 {
+            self.sequencer.channel_seq.insert("db_update_queue".to_string(), last_db_seq);
+        }
+        tracing::info!("✅ Event sequence resumes after {}", self.sequencer.event_seq);
+    }
+
     // /// Publish real-time market events
     // async fn publish_market_events(&mut self, order: &Order, trades: &[Trade]) {
     //     let mut conn = self.redis_manager.clone();
@@ -1038,18 +1561,16 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
         }
     }
     
-    // Snapshot tickers
-    for (market_id, ticker) in &self.tickers {
+    // Snapshot ticker windows (no TTL: they must survive a restart)
+    for (market_id, rolling) in &self.rolling_tickers {
         let key = format!("snapshot:ticker:{}", market_id);
         tracing::debug!("💾 Saving ticker snapshot for market: {}", market_id);
         
-        match serde_json::to_string(ticker) {
+        match serde_json::to_string(rolling) {
             Ok(ticker_json) => {
                 match redis::cmd("SET")
                     .arg(&key)
                     .arg(&ticker_json)
-                    .arg("EX")
-                    .arg(300) // 5 minutes TTL
                     .query_async::<_, ()>(&mut conn)
                     .await
                 {
@@ -1060,14 +1581,14 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                     Err(e) => {
                         tracing::error!("❌ Failed to save ticker snapshot {}: {}", key, e);
                     }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("❌ Failed to serialize ticker for {}: {}", market_id, e);
                 }
             }
+            Err(e) => {
+                tracing::error!("❌ Failed to serialize ticker for {}: {}", market_id, e);
+            }
         }
-    
+    }
+
         tracing::info!("✅ Snapshot process completed: {} snapshots saved at {}", snapshot_count, timestamp);
     }
     
@@ -1084,54 +1605,28 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             filled_quantity: 0,
             status: OrderStatus::Pending,
             created_at: req.timestamp,
+            client_order_id: req.client_order_id,
+            reserved: 0,
+            filled_notional: U256::zero(),
         }
     }
     
-    fn calculate_average_price(&self, trades: &[Trade], market_id: Uuid) -> Option<i64> {
+    fn calculate_average_price(&self, trades: &[Trade], _market_id: Uuid) -> Option<i128> {
         if trades.is_empty() {
             return None;
         }
-        let market = self.markets.get(&market_id)?;
-        let mut total_value = 0i64;
-        let mut total_quantity = 0i64;
-
-        // Calculate total value using safe_multiply_divide for each trade
+        let mut total_notional = U256::zero();
+        let mut total_quantity = 0i128;
         for trade in trades {
-            match self.safe_multiply_divide(trade.price, trade.quantity, market.base_currency.decimals) {
-                Ok(value) => total_value += value,
-                Err(e) => {
-                    tracing::error!("Error calculating trade value for average price: {}", e);
-                    return None; // If any calculation fails, we can't compute average
-                }
-            }
-            total_quantity += trade.quantity;
+            total_notional = total_notional.checked_add(notional(trade.price, trade.quantity).ok()?)?;
+            total_quantity = total_quantity.checked_add(trade.quantity)?;
         }
         
         if total_quantity == 0 {
             return None;
         }
-        
-        // Calculate average price: total_value / total_quantity
-        // But we need to reverse the division we did in safe_multiply_divide
-        // So we multiply by the base_multiplier again
-        let decimals = market.base_currency.decimals;
-        if decimals > 18 {
-            tracing::error!("Decimals {} exceeds maximum supported (18)", decimals);
-            return None;
-        }
-        
-        let total_value_u256 = U256::from(total_value as u64);
-        let total_quantity_u256 = U256::from(total_quantity as u64);
-        let multiplier_u256 = U256::from(10u64).pow(U256::from(decimals as u64));
-        
-        let result = (total_value_u256 * multiplier_u256) / total_quantity_u256;
-        
-        if result > U256::from(i64::MAX as u64) {
-            tracing::error!("Average price calculation exceeds i64::MAX");
-            return None;
-        }
-        
-        Some(result.as_u64() as i64)
+        // Between the lowest and highest trade price, so it fits in an i128
+        Some((total_notional / U256::from(total_quantity as u128)).as_u128() as i128)
     }
     
     // fn create_orderbook_snapshot(&self, orderbook: &OrderBook) -> OrderBookSnapshot {
@@ -1194,6 +1689,17 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             (token_balance.available, token_balance.locked)
         };
 
+        if request.amount <= 0 || available_balance.checked_add(request.amount).is_none() {
+            tracing::warn!("❌ Deposit amount {} rejected", request.amount);
+            return crate::redis_manager::BalanceResponse {
+                request_id: request.request_id,
+                success: false,
+                message: "Invalid deposit amount".to_string(),
+                new_balance: available_balance,
+                balances: None,
+            };
+        }
+
         let locked_amount = {
             let user_balance = self.balances.get(&request.user_id).unwrap();
             let tb = user_balance.token_balances.get(&request.token_id).unwrap();
@@ -1206,6 +1712,8 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             request.amount, 
             0
         ).await;
+        let net = self.net_deposits.entry(request.token_id).or_insert(0);
+        *net = net.saturating_add(request.amount);
         
         let new_balance = {
             let user_balance = self.balances.get(&request.user_id).unwrap();
@@ -1240,13 +1748,24 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             request.user_id
         );
     
+        if self.halted {
+            tracing::warn!("❌ Trading halted, rejecting withdrawal {}", request.request_id);
+            return crate::redis_manager::BalanceResponse {
+                request_id: request.request_id,
+                success: false,
+                message: "Trading is halted".to_string(),
+                new_balance: 0,
+                balances: None,
+            };
+        }
+
         // Check if user has sufficient balance and get the new balance value
         let withdrawal_result = {
             if let Some(user_balance) = self.balances.get_mut(&request.user_id) {
                 if let Some(token_balance) = user_balance.token_balances.get_mut(&request.token_id) {
                     let available_balance = token_balance.available;
                     
-                    if available_balance >= request.amount {
+                    if request.amount > 0 && available_balance >= request.amount {
                         Some(())
                     } else {
                         tracing::warn!("❌ Insufficient balance for withdrawal. Available: {}, Requested: {}", 
@@ -1272,6 +1791,8 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                     -request.amount, 
                     0
                 ).await;
+                let net = self.net_deposits.entry(request.token_id).or_insert(0);
+                *net = net.saturating_sub(request.amount);
 
                 let new_balance = {
                     let user_balance = self.balances.get(&request.user_id).unwrap();
@@ -1364,10 +1885,53 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
             }
         }
         
+        // Loaded balances are the baseline for the supply invariant
+        self.net_deposits.clear();
+        for user_balance in self.balances.values() {
+            for (&token_id, tb) in &user_balance.token_balances {
+                let net = self.net_deposits.entry(token_id).or_insert(0);
+                *net = net.saturating_add(tb.available).saturating_add(tb.locked);
+            }
+        }
         tracing::info!("✅ Loaded {} balance snapshots", loaded_count);
         Ok(())
     }
 
+    /// Restore the order books saved alongside the balance snapshots, so restored locks
+    /// are backed by their resting orders again
+    pub async fn load_orderbook_snapshots(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.redis_manager.clone();
+        let market_ids: Vec<Uuid> = self.markets.keys().copied().collect();
+        for market_id in market_ids {
+            let raw: Option<String> = conn.get(format!("snapshot:orderbook:{}", market_id)).await?;
+            let Some(mut orderbook) = raw.and_then(|json| serde_json::from_str::<OrderBook>(&json).ok()) else { continue };
+            orderbook.rebuild_reservations(&self.markets[&market_id]);
+            self.orderbooks.insert(market_id, orderbook);
+            self.publish_depth(market_id).await;
+        }
+        tracing::info!("✅ Restored {} order books", self.orderbooks.len());
+        Ok(())
+    }
+
+    /// Load client order ids of persisted orders so duplicates are still rejected after a restart
+    pub async fn load_client_order_ids(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut connection = establish_connection();
+
+        let rows: Vec<(Uuid, Uuid, Option<String>)> = orders::table
+            .filter(orders::client_order_id.is_not_null())
+            .select((orders::id, orders::user_id, orders::client_order_id))
+            .load(&mut connection)?;
+
+        for (order_id, user_id, client_order_id) in rows {
+            if let Some(client_order_id) = client_order_id {
+                self.client_order_ids.insert((user_id, client_order_id), order_id);
+            }
+        }
+
+        tracing::info!("✅ Loaded {} client order ids", self.client_order_ids.len());
+        Ok(())
+    }
+
      // Add this method to load markets from database
     pub async fn load_markets(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         
@@ -1444,29 +2008,6 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
         Ok(())
     }
 
-    // Add this helper function
-    fn safe_multiply_divide(&self, price: i64, quantity: i64, decimals: i32) -> Result<i64, String> {
-        
-        if price < 0 || quantity < 0 {
-            return Err("Price and quantity must be non-negative".to_string());
-        }
-        
-        if decimals > 18 {
-            return Err(format!("Decimals {} exceeds maximum supported (18)", decimals));
-        }
-        let price_u256 = U256::from(price as u64);
-        let quantity_u256 = U256::from(quantity as u64);
-        let divisor_u256 = U256::from(10u64).pow(U256::from(decimals as u64));
-        
-        let result = (price_u256 * quantity_u256) / divisor_u256;
-        
-        if result > U256::from(i64::MAX as u64) {
-            return Err(format!("Result {} exceeds i64::MAX", result));
-        }
-        
-        Ok(result.as_u64() as i64)
-    }
-
     async fn validate_and_lock_order_balance(&mut self, order: &Order) -> Result<Reservation, String> {
         // Market
         let market = self.markets.get(&order.market_id)
@@ -1483,13 +2024,11 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                 let required_quote = match order.order_kind {
                     OrderKind::Limit => {
                         let price = order.price.ok_or_else(|| "Limit buy requires price".to_string())?;
-                        self.safe_multiply_divide(price, order.quantity, market.base_currency.decimals)?
+                        quote_amount(notional(price, order.quantity)?, market.base_currency.decimals, RoundingMode::Up)?
                     }
                     OrderKind::Market => {
                         // Estimate cost for market buy; if no liquidity, reject
-                        let est = self.estimate_market_buy_price(order.market_id, order.quantity)
-                            .ok_or_else(|| format!("No liquidity available for market buy in {}", market.symbol))?;
-                        self.safe_multiply_divide(est, order.quantity, market.base_currency.decimals)?
+                        self.estimate_market_buy_cost(order.market_id, order.quantity)?
                     }
                 };
 
@@ -1534,61 +2073,67 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
         }
     }
     //KEEP THIS FUNCTION
-    fn estimate_market_buy_price(&self, market_id: Uuid, quantity: i64) -> Option<i64> {
-        let orderbook = self.orderbooks.get(&market_id)?;
-        let market = self.markets.get(&market_id)?;
+    /// Quote to reserve for a market buy: exactly what walking the asks will cost
+    /// (rounded up once, as settlement does), or the highest ask + 10% for the
+    /// whole quantity if the book is too thin.
+    fn estimate_market_buy_cost(&self, market_id: Uuid, quantity: i128) -> Result<i128, String> {
+        let overflow = || "Order value overflows".to_string();
+        let market = self.markets.get(&market_id).ok_or("Market not found")?;
+        let no_liquidity = || format!("No liquidity available for market buy in {}", market.symbol);
+        let orderbook = self.orderbooks.get(&market_id).ok_or_else(no_liquidity)?;
+        let (&highest_price, _) = orderbook.asks.iter().last().ok_or_else(no_liquidity)?;
         let mut remaining_quantity = quantity;
-        let mut total_cost = 0i64;
+        let mut total_notional = U256::zero();
         
         // Calculate cost by walking through asks (ascending price order)
         for (&price, orders) in &orderbook.asks {
             if remaining_quantity <= 0 { break; }
             
-            let available_at_price: i64 = orders.iter()
-                .map(|o| o.quantity - o.filled_quantity)
-                .sum();
+            let available_at_price = orders.iter()
+                .try_fold(0i128, |total, o| total.checked_add(o.quantity - o.filled_quantity))
+                .ok_or_else(overflow)?;
             
             let quantity_to_buy = remaining_quantity.min(available_at_price);
-            
-            // FIX: Use safe_multiply_divide to avoid overflow
-            match self.safe_multiply_divide(price, quantity_to_buy, market.base_currency.decimals) {
-                Ok(cost) => total_cost += cost,
-                Err(e) => {
-                    tracing::error!("Error calculating cost in estimate_market_buy_price: {}", e);
-                    return None;
-                }
-            }
+            total_notional = total_notional.checked_add(notional(price, quantity_to_buy)?).ok_or_else(overflow)?;
             remaining_quantity -= quantity_to_buy;
         }
         
         if remaining_quantity > 0 {
-            // Not enough liquidity - return conservative estimate using highest price found
-            if let Some((&highest_price, _)) = orderbook.asks.iter().last() {
-                Some(highest_price + (highest_price / 10)) // Add 10% buffer
-            } else {
-                None // No asks available
-            }
-        } else {
-            // We can fulfill the order - return average price
-            // FIX: Use U256 for the reverse calculation to avoid overflow
-            let decimals = market.base_currency.decimals;
-            if decimals > 18 {
-                tracing::error!("Decimals {} exceeds maximum supported (18)", decimals);
-                return None;
-            }
-            
-            let total_cost_u256 = U256::from(total_cost as u64);
-            let quantity_u256 = U256::from(quantity as u64);
-            let multiplier_u256 = U256::from(10u64).pow(U256::from(decimals as u64));
-            
-            let result = (total_cost_u256 * multiplier_u256) / quantity_u256;
-            
-            if result > U256::from(i64::MAX as u64) {
-                tracing::error!("Average price calculation exceeds i64::MAX");
-                return None;
-            }
-            
-            Some(result.as_u64() as i64)
+            // Not enough liquidity - conservative estimate using highest price found
+            total_notional = notional(highest_price.checked_add(highest_price / 10).ok_or_else(overflow)?, quantity)?;
+        }
+
+        quote_amount(total_notional, market.base_currency.decimals, RoundingMode::Up)
+    }
+
+    /// Clear an invariant halt. The check runs again after this command, so trading
+    /// halts again straight away if the violation is still there.
+    pub fn resume(&mut self, req: crate::redis_manager::ResumeRequest) -> crate::redis_manager::ResumeResponse {
+        let was_halted = std::mem::replace(&mut self.halted, false);
+        if was_halted {
+            tracing::warn!("▶️ Trading resumed by request {}", req.request_id);
+        }
+        crate::redis_manager::ResumeResponse {
+            request_id: req.request_id,
+            success: true,
+            message: if was_halted { "Trading resumed" } else { "Trading was not halted" }.to_string(),
+            was_halted,
+        }
+    }
+
+    /// Resting orders of a user, newest first
+    pub fn open_orders(&self, req: crate::redis_manager::OpenOrdersRequest) -> crate::redis_manager::OpenOrdersResponse {
+        let mut orders: Vec<Order> = self.orderbooks.values()
+            .filter(|book| req.market_id.is_none_or(|market_id| book.market_id == market_id))
+            .flat_map(|book| book.orders_of(req.user_id))
+            .cloned()
+            .collect();
+        orders.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        crate::redis_manager::OpenOrdersResponse {
+            request_id: req.request_id,
+            success: true,
+            message: format!("{} open orders", orders.len()),
+            orders,
         }
     }
 
@@ -1599,7 +2144,13 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
     ) -> crate::redis_manager::OrderResponse {
         tracing::info!("🔄 Processing cancel order: {}", req.request_id);
 
-        let maybe_order = self.remove_order_from_orderbook(req.market_id, req.order_id);
+        // Cancel by order id, or by the client order id the user chose
+        let order_id = req.order_id.or_else(|| {
+            req.client_order_id.as_ref()
+                .and_then(|client_order_id| self.client_order_ids.get(&(req.user_id, client_order_id.clone())))
+                .copied()
+        });
+        let maybe_order = order_id.and_then(|order_id| self.remove_order_from_orderbook(req.market_id, order_id));
 
         match maybe_order {
             Some(mut order) => {
@@ -1610,6 +2161,7 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                         success: false,
                         status: "REJECTED".to_string(),
                         order_id: Some(order.id),
+                        client_order_id: None,
                         message: "Order does not belong to user".to_string(),
                         filled_quantity: Some(order.filled_quantity),
                         remaining_quantity: Some(order.quantity - order.filled_quantity),
@@ -1620,42 +2172,33 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
 
                 // Update order status
                 order.status = OrderStatus::Cancelled;
-                // unlock remaining reservation for LIMIT orders
-                let remaining = order.quantity - order.filled_quantity;
-                match order.order_type {
-                    OrderType::Buy => {
-                        if matches!(order.order_kind, OrderKind::Limit) {
-                            if let Some(price) = order.price {
-                                // unlock remaining quote = remaining * price
-                                let market = &self.markets[&order.market_id];
-                                match self.safe_multiply_divide(price, remaining, market.base_currency.decimals) {
-                                    Ok(quote_amount) => {
-                                        let quote_id = market.quote_currency.id;
-                                        self.unlock(order.user_id, quote_id, quote_amount).await;
-                                    }
-                                    Err(e) => {
-                                        tracing::error!("Error calculating unlock amount for cancelled buy order: {}", e);
-                                        // You might want to handle this error more gracefully
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    OrderType::Sell => {
-                        if matches!(order.order_kind, OrderKind::Limit) {
-                            // unlock remaining base = remaining
-                            let base_id = self.markets[&order.market_id].base_currency.id;
-                            self.unlock(order.user_id, base_id, remaining).await;
-                        }
-                    }
-}
+                // Release exactly what is still locked for this order
+                let market = &self.markets[&order.market_id];
+                let token_id = match order.order_type {
+                    OrderType::Buy => market.quote_currency.id,
+                    OrderType::Sell => market.base_currency.id,
+                };
+                let reserved = std::mem::take(&mut order.reserved);
+                self.unlock(order.user_id, token_id, reserved).await;
+                if let Some(price) = order.price {
+                    let market = &self.markets[&order.market_id];
+                    let event = L3Event::Remove {
+                        order_id: order.id,
+                        side: l3_side(&order.order_type),
+                        price: price_from_atomic_units(price, market),
+                        reason: L3RemoveReason::Cancelled,
+                    };
+                    self.publish_l3(req.market_id, vec![event]).await;
+                }
                 self.queue_db_updates(&order, &[], &[]).await;
+                self.publish_order_updates(std::iter::once(&order)).await;
                 self.publish_depth(req.market_id).await;
                 crate::redis_manager::OrderResponse {
                     request_id: req.request_id,
                     success: true,
                     status: "CANCELLED".to_string(),
                     order_id: Some(order.id),
+                    client_order_id: order.client_order_id.clone(),
                     message: "Order cancelled successfully".to_string(),
                     filled_quantity: Some(order.filled_quantity),
                     remaining_quantity: Some(order.quantity - order.filled_quantity),
@@ -1669,6 +2212,7 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
                     success: false,
                     status: "REJECTED".to_string(),
                     order_id: None,
+                    client_order_id: req.client_order_id,
                     message: "Order not found".to_string(),
                     filled_quantity: None,
                     remaining_quantity: None,
@@ -1696,7 +2240,7 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
         None
     }
     // Free = available - locked
-    fn free_amount(&self, user: Uuid, token: Uuid) -> i64 {
+    fn free_amount(&self, user: Uuid, token: Uuid) -> i128 {
         self.balances
             .get(&user)
             .and_then(|ub| ub.token_balances.get(&token))
@@ -1705,7 +2249,7 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
     }
 
     // Try to lock funds: available unchanged, locked += amount
-    async fn lock(&mut self, user: Uuid, token: Uuid, amount: i64) -> Result<(), &'static str> {
+    async fn lock(&mut self, user: Uuid, token: Uuid, amount: i128) -> Result<(), &'static str> {
         if amount <= 0 { return Ok(()); }
         if self.free_amount(user, token) < amount {
             return Err("insufficient free balance to lock");
@@ -1716,9 +2260,187 @@ async fn publish_ticker(&mut self, market_id: Uuid) {
     }
 
     // Unlock funds: locked -= amount (available unchanged)
-    async fn unlock(&mut self, user: Uuid, token: Uuid, amount: i64) {
+    async fn unlock(&mut self, user: Uuid, token: Uuid, amount: i128) {
         if amount <= 0 { return; }
         // locked -= amount
         self.update_user_balance(user, token, amount, -amount).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH: i128 = 1_000_000_000_000_000_000; // one whole 18-decimal token
+
+    fn order(order_type: OrderType) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            market_id: Uuid::nil(),
+            order_type,
+            order_kind: OrderKind::Limit,
+            price: None,
+            quantity: 0,
+            filled_quantity: 0,
+            status: OrderStatus::Pending,
+            created_at: 0,
+            client_order_id: None,
+            reserved: 0,
+            filled_notional: U256::zero(),
+        }
+    }
+
+    fn limit(order_type: OrderType, price: i128, quantity: i128) -> Order {
+        Order { price: Some(price), quantity, ..order(order_type) }
+    }
+
+    fn market() -> MarketInfo {
+        let token = |symbol: &str, decimals| TokenInfo {
+            id: Uuid::new_v4(),
+            symbol: symbol.to_string(),
+            name: symbol.to_string(),
+            decimals,
+            is_active: true,
+        };
+        MarketInfo {
+            id: Uuid::nil(),
+            symbol: "ETH-USDC".to_string(),
+            base_currency: token("ETH", 18),
+            quote_currency: token("USDC", 6),
+            min_order_size: 1,
+            tick_size: 1,
+            is_active: true,
+            created_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn notional_beyond_i128() {
+        // 3000 quote at 18 decimals times 10 whole 18-decimal base tokens is 3e40
+        let value = notional(3000 * ETH, 10 * ETH).unwrap();
+        assert!(value > U256::from(i128::MAX as u128));
+        assert_eq!(quote_amount(value, 18, RoundingMode::Down), Ok(30_000 * ETH));
+        assert!(notional(-1, 1).is_err());
+    }
+
+    #[test]
+    fn quote_amount_rounding() {
+        let n = U256::from(1_234_567u64);
+        assert_eq!(quote_amount(n, 3, RoundingMode::Down), Ok(1234));
+        assert_eq!(quote_amount(n, 3, RoundingMode::Up), Ok(1235));
+        assert_eq!(quote_amount(n, 3, RoundingMode::HalfUp), Ok(1235));
+        assert!(quote_amount(n, 3, RoundingMode::Exact).is_err());
+        // Whole amounts are the same in every mode
+        let whole = U256::from(1_234_000u64);
+        for mode in [RoundingMode::Down, RoundingMode::Up, RoundingMode::HalfUp, RoundingMode::Exact] {
+            assert_eq!(quote_amount(whole, 3, mode), Ok(1234));
+        }
+    }
+
+    #[test]
+    fn quote_amount_rejects_overflow() {
+        assert!(quote_amount(U256::MAX, 18, RoundingMode::Down).is_err());
+        assert!(quote_amount(U256::from(i128::MAX as u128) + 1, 0, RoundingMode::Down).is_err());
+        // Rounding up past i128::MAX is an overflow as well
+        let top = U256::from(i128::MAX as u128) * 10 + 1;
+        assert_eq!(quote_amount(top, 1, RoundingMode::Down), Ok(i128::MAX));
+        assert!(quote_amount(top, 1, RoundingMode::Up).is_err());
+        assert!(quote_amount(U256::zero(), 19, RoundingMode::Down).is_err());
+    }
+
+    #[test]
+    fn settle_fill_is_cumulative() {
+        // Three fills of 0.5 quote units each: 1 + 0 + 1 rounded up, 0 + 1 + 0 rounded down
+        let mut buyer = order(OrderType::Buy);
+        let mut seller = order(OrderType::Sell);
+        let mut paid = Vec::new();
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let (cost, proceeds) = settle_trade(&mut buyer, &mut seller, 5, 1, 1).unwrap();
+            paid.push(cost);
+            received.push(proceeds);
+        }
+        assert_eq!(paid, [1, 0, 1]);
+        assert_eq!(received, [0, 1, 0]);
+        assert_eq!(buyer.filled_notional, U256::from(15u64));
+    }
+
+    #[test]
+    fn settle_trade_leaves_orders_untouched_on_overflow() {
+        let mut buyer = order(OrderType::Buy);
+        let mut seller = order(OrderType::Sell);
+        seller.filled_notional = U256::MAX;
+        assert!(settle_trade(&mut buyer, &mut seller, 5, 1, 0).is_err());
+        assert_eq!(buyer.filled_notional, U256::zero());
+        assert_eq!(seller.filled_notional, U256::MAX);
+    }
+
+    #[test]
+    fn dust_is_never_negative() {
+        // Orders trade against each other with unrelated fill histories; a single trade can
+        // round in the seller's favour, but the dust account as a whole never goes negative
+        let decimals = 3;
+        let mut buyers: Vec<Order> = (0..4).map(|_| order(OrderType::Buy)).collect();
+        let mut sellers: Vec<Order> = (0..3).map(|_| order(OrderType::Sell)).collect();
+        let mut dust = 0i128;
+        let mut state = 7u64;
+        for _ in 0..2000 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let price = 1 + (state >> 33) as i128 % 997;
+            let quantity = 1 + (state >> 13) as i128 % 1499;
+            let buyer = &mut buyers[(state >> 7) as usize % 4];
+            let seller = &mut sellers[(state >> 3) as usize % 3];
+            let (cost, proceeds) = settle_trade(buyer, seller, price, quantity, decimals).unwrap();
+            assert!(cost >= 0 && proceeds >= 0);
+            dust += cost - proceeds;
+            assert!(dust >= 0, "dust went negative: {dust}");
+        }
+        // Each side paid or received exactly its rounded total
+        let paid: i128 = buyers.iter().map(|b| quote_amount(b.filled_notional, decimals, RoundingMode::Up).unwrap()).sum();
+        let received: i128 = sellers.iter().map(|s| quote_amount(s.filled_notional, decimals, RoundingMode::Down).unwrap()).sum();
+        assert_eq!(paid - received, dust);
+    }
+
+    #[test]
+    fn filled_notional_serializes_as_string() {
+        let mut o = order(OrderType::Buy);
+        o.filled_notional = notional(3000 * ETH, 10 * ETH).unwrap();
+        let json = serde_json::to_value(&o).unwrap();
+        assert_eq!(json["filled_notional"], "30000000000000000000000000000000000000000");
+        let back: Order = serde_json::from_value(json).unwrap();
+        assert_eq!(back.filled_notional, o.filled_notional);
+    }
+
+    #[test]
+    fn partially_filled_taker_rests_with_its_fills() {
+        let market = market();
+        let mut book = OrderBook { market_id: Uuid::nil(), bids: BTreeMap::new(), asks: BTreeMap::new(), last_updated: 0 };
+        let mut maker = limit(OrderType::Sell, 100, 4 * ETH);
+        assert!(book.match_limit_order(&mut maker, &market).0.is_empty());
+
+        let mut taker = limit(OrderType::Buy, 100, 10 * ETH);
+        let (trades, matched) = book.match_limit_order(&mut taker, &market);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, 4 * ETH);
+        assert!(matches!(matched[0].status, OrderStatus::Filled));
+
+        // Open orders show the original size, the fill and the status, as the order history does
+        let open: Vec<&Order> = book.orders_of(taker.user_id).collect();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].id, taker.id);
+        assert_eq!(open[0].quantity, 10 * ETH);
+        assert_eq!(open[0].filled_quantity, 4 * ETH);
+        assert!(matches!(open[0].status, OrderStatus::PartiallyFilled));
+        assert!(matches!(taker.status, OrderStatus::PartiallyFilled));
+
+        // The resting order only trades what is left of it
+        let mut seller = limit(OrderType::Sell, 100, 20 * ETH);
+        let (trades, matched) = book.match_limit_order(&mut seller, &market);
+        assert_eq!(trades[0].quantity, 6 * ETH);
+        assert_eq!(matched[0].filled_quantity, 10 * ETH);
+        assert!(matches!(matched[0].status, OrderStatus::Filled));
+        assert_eq!(book.orders_of(taker.user_id).count(), 0);
+        assert_eq!(book.orders_of(seller.user_id).next().map(|o| o.filled_quantity), Some(6 * ETH));
+    }
+}
@@ -33,7 +33,7 @@ struct LoginResp { token: String }
 #[derive(Serialize)]
 struct DepositReq { 
     token_id: Uuid, 
-    amount: f64  // Change to f64 if your API expects decimal deposits
+    amount: String, // decimal string, the api rejects fractional JSON numbers
 }
 
 #[derive(Serialize)]
@@ -41,8 +41,8 @@ struct CreateOrderReq {
     market_id: Uuid,
     order_type: String, // "Buy" | "Sell"
     order_kind: String, // "Limit"
-    price: Option<f64>,
-    quantity: f64,
+    price: Option<String>, // decimal strings
+    quantity: String,
 }
 
 #[tokio::main]
@@ -148,7 +148,8 @@ async fn run_sim_for_10s(api: String, cfg: StartMsg) -> anyhow::Result<()> {
         let u = &users[rng.random_range(0..users.len())];
         let is_buy = rng.random_bool(0.5);
         let is_limit = true;
-        let qty = rng.random_range(cfg.min_qty..=cfg.max_qty);
+        // Amounts must fit the token's decimals exactly; 4 places is enough here
+        let qty = (rng.random_range(cfg.min_qty..=cfg.max_qty) * 10_000.0).round() / 10_000.0;
         
         let price = if is_limit {
             // Generate spread around current mid price
@@ -161,7 +162,7 @@ async fn run_sim_for_10s(api: String, cfg: StartMsg) -> anyhow::Result<()> {
             
             // Round to 2 decimal places and ensure minimum price
             let rounded_price = ((raw_price * 100.0).round() / 100.0).max(0.01);
-            Some(rounded_price)
+            Some(format!("{:.2}", rounded_price))
         } else { 
             None 
         };
@@ -171,7 +172,7 @@ async fn run_sim_for_10s(api: String, cfg: StartMsg) -> anyhow::Result<()> {
             order_type: if is_buy { "Buy".into() } else { "Sell".into() },
             order_kind: if is_limit { "Limit".into() } else { "Market".into() },
             price,
-            quantity: qty,
+            quantity: format!("{:.4}", qty),
         };
         let _ = place_order(&client, &api, &u.token, &req).await;
         sleep(Duration::from_millis(cfg.order_rate_ms)).await;
@@ -186,7 +187,7 @@ async fn ensure_user(client: &Client, api: &str, email: &str, pass: &str) -> any
     Ok(r.json::<LoginResp>().await?.token)
 }
 async fn deposit(client: &Client, api: &str, jwt: &str, token_id: Uuid, amount: f64) -> anyhow::Result<()> {
-    let r = client.post(format!("{api}/user/deposit")).bearer_auth(jwt).json(&DepositReq { token_id, amount }).send().await?;
+    let r = client.post(format!("{api}/user/deposit")).bearer_auth(jwt).json(&DepositReq { token_id, amount: amount.to_string() }).send().await?;
     if !r.status().is_success() { anyhow::bail!("deposit failed: {}", r.text().await?) }
     Ok(())
 }
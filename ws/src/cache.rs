@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::repr::decimal_to_atomic;
+use crate::types::{Feed, ServerMsg};
+
+/// Trades kept per market for new subscribers
+pub const RECENT_TRADES: usize = 50;
+
+/// One side of a cached book: atomic price -> (price, quantity) as published
+type Levels = BTreeMap<i128, (Value, Value)>;
+
+/// Full depth rebuilt from the engine's deltas; `seq` is the last delta applied
+struct DepthBook {
+    seq: u64,
+    ts: i64,
+    bids: Levels,
+    asks: Levels,
+    price_decimals: u64,
+    quantity_decimals: u64,
+}
+
+/// `[[price, quantity], ...]` keyed by atomic price; None if any level is malformed
+fn parse_levels(levels: Option<&Value>, price_decimals: u64) -> Option<Vec<(i128, Value, Value)>> {
+    levels?.as_array()?.iter().map(|level| {
+        let [price, quantity] = level.as_array()?.as_slice() else { return None };
+        let atomic = decimal_to_atomic(price.as_str()?, price_decimals as usize)?.parse().ok()?;
+        Some((atomic, price.clone(), quantity.clone()))
+    }).collect()
+}
+
+fn pairs<'a>(levels: impl Iterator<Item = &'a (Value, Value)>) -> Vec<Value> {
+    levels.map(|(price, quantity)| json!([price, quantity])).collect()
+}
+
+fn is_zero(quantity: &Value) -> bool {
+    quantity.as_str().is_some_and(|q| q.chars().all(|c| c == '0' || c == '.'))
+}
+
+impl DepthBook {
+    fn from_snapshot(payload: &Value) -> Option<Self> {
+        let price_decimals = payload.get("price_decimals")?.as_u64()?;
+        let collect = |key| -> Option<Levels> {
+            Some(parse_levels(payload.get(key), price_decimals)?
+                .into_iter()
+                .map(|(atomic, price, quantity)| (atomic, (price, quantity)))
+                .collect())
+        };
+        Some(DepthBook {
+            seq: payload.get("seq")?.as_u64()?,
+            ts: payload.get("ts").and_then(|v| v.as_i64()).unwrap_or_default(),
+            bids: collect("bids")?,
+            asks: collect("asks")?,
+            price_decimals,
+            quantity_decimals: payload.get("quantity_decimals")?.as_u64()?,
+        })
+    }
+
+    /// Apply delta `seq + 1`; false if the delta does not follow this book
+    fn apply(&mut self, delta: &Value) -> bool {
+        let Some(seq) = delta.get("seq").and_then(|v| v.as_u64()) else { return false };
+        if seq <= self.seq && seq != 1 {
+            return true; // already part of the book
+        }
+        if seq == 1 && self.seq != 0 {
+            // Engine restarted: it starts again from an empty book at seq 0
+            self.bids.clear();
+            self.asks.clear();
+        } else if seq != self.seq + 1 {
+            return false;
+        }
+
+        for (key, side) in [("bids", &mut self.bids), ("asks", &mut self.asks)] {
+            let Some(levels) = parse_levels(delta.get(key), self.price_decimals) else { return false };
+            for (atomic, price, quantity) in levels {
+                if is_zero(&quantity) {
+                    side.remove(&atomic);
+                } else {
+                    side.insert(atomic, (price, quantity));
+                }
+            }
+        }
+        self.seq = seq;
+        self.ts = delta.get("ts").and_then(|v| v.as_i64()).unwrap_or(self.ts);
+        true
+    }
+
+    /// Same shape as the engine's `snapshot:depth` entry
+    fn to_json(&self, market_id: Uuid) -> Value {
+        json!({
+            "market_id": market_id,
+            "seq": self.seq,
+            "ts": self.ts,
+            "bids": pairs(self.bids.values().rev()),
+            "asks": pairs(self.asks.values()),
+            "price_decimals": self.price_decimals,
+            "quantity_decimals": self.quantity_decimals,
+        })
+    }
+}
+
+/// Latest public state of one market, replayed to new subscribers
+#[derive(Default)]
+pub struct MarketCache {
+    depth: Option<DepthBook>,
+    ticker: Option<Value>,
+    trades: VecDeque<Value>, // oldest first
+}
+
+impl MarketCache {
+    pub fn ticker(&self) -> Option<&Value> {
+        self.ticker.as_ref()
+    }
+
+    pub fn has_depth(&self) -> bool {
+        self.depth.is_some()
+    }
+
+    /// Seed the book from the engine's stored snapshot unless the cached one is as new
+    pub fn seed_depth(&mut self, snapshot: &Value) {
+        let Some(book) = DepthBook::from_snapshot(snapshot) else { return };
+        if !matches!(&self.depth, Some(cached) if cached.seq >= book.seq) {
+            self.depth = Some(book);
+        }
+    }
+
+    pub fn record(&mut self, feed: Feed, payload: &Value) {
+        match feed {
+            // A gap drops the book; the next subscriber reseeds it from Redis
+            Feed::Depth if self.depth.as_mut().is_some_and(|book| !book.apply(payload)) => {
+                tracing::warn!("depth cache out of sequence, dropping it");
+                self.depth = None;
+            }
+            Feed::Ticker => self.ticker = Some(payload.clone()),
+            Feed::Trades => {
+                self.trades.push_back(payload.clone());
+                while self.trades.len() > RECENT_TRADES {
+                    self.trades.pop_front();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Cached state for `feed` as a snapshot message: depth is tagged with its delta `seq`,
+    /// ticker and trades with the `event_seq` of the last event they include
+    pub fn snapshot(&self, market_id: Uuid, feed: Feed) -> Option<ServerMsg> {
+        let payload = match feed {
+            Feed::Depth => self.depth.as_ref()?.to_json(market_id),
+            Feed::Ticker => self.ticker.clone()?,
+            Feed::Trades => {
+                let last = self.trades.back()?;
+                json!({
+                    "market_id": market_id,
+                    "event_seq": last.get("event_seq").cloned().unwrap_or(Value::from(0)),
+                    "trades": self.trades,
+                })
+            }
+            _ => return None,
+        };
+        Some(ServerMsg::Snapshot { channel: format!("{}:{market_id}", feed.prefix()), payload })
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use axum::extract::ws::Message;
+
+use crate::types::{Encoding, Feed, Repr, ServerMsg};
+
+/// A message already encoded for the wire, shared by every client that receives it
+#[derive(Debug, Clone)]
+pub enum Frame {
+    Text(Arc<str>),
+    Binary(Arc<[u8]>),
+}
+
+impl Frame {
+    pub fn encode(msg: &ServerMsg, encoding: Encoding) -> Frame {
+        match encoding {
+            Encoding::Json => {
+                let txt = serde_json::to_string(msg).unwrap_or_else(|_| "{\"type\":\"info\",\"message\":\"encode error\"}".to_string());
+                Frame::Text(txt.into())
+            }
+            // Named fields, so the payload decodes to the same shape as the JSON one
+            Encoding::MsgPack => Frame::Binary(rmp_serde::to_vec_named(msg).unwrap_or_default().into()),
+        }
+    }
+
+    pub fn into_message(self) -> Message {
+        match self {
+            Frame::Text(txt) => Message::Text(txt.to_string()),
+            Frame::Binary(bytes) => Message::Binary(bytes.to_vec()),
+        }
+    }
+}
+
+/// Encodes one fan-out message lazily, at most once per representation and encoding,
+/// however many clients receive it
+pub struct Encoded<'a> {
+    msg: &'a ServerMsg,
+    feed: Feed,
+    atomic: Option<ServerMsg>,
+    frames: [[Option<Frame>; 2]; 2], // [repr][encoding]
+}
+
+impl<'a> Encoded<'a> {
+    pub fn new(msg: &'a ServerMsg, feed: Feed) -> Self {
+        Encoded { msg, feed, atomic: None, frames: Default::default() }
+    }
+
+    pub fn get(&mut self, repr: Repr, encoding: Encoding) -> Frame {
+        let (msg, feed) = (self.msg, self.feed);
+        let slot = &mut self.frames[repr as usize][encoding as usize];
+        if let Some(frame) = slot {
+            return frame.clone();
+        }
+        let frame = match repr {
+            Repr::Decimal => Frame::encode(msg, encoding),
+            Repr::Atomic => Frame::encode(self.atomic.get_or_insert_with(|| msg.to_atomic(feed)), encoding),
+        };
+        slot.insert(frame).clone()
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+use crate::types::{Feed, ServerMsg};
+
+/// Messages kept per channel for `resume`
+pub const HISTORY_LEN: usize = 1024;
+
+/// Sequence number of a feed message and the one before it on the same channel:
+/// depth deltas carry a contiguous `seq`, everything else the engine's `event_seq` chain
+fn sequence(feed: Feed, msg: &ServerMsg) -> Option<(u64, u64)> {
+    let ServerMsg::Event { payload, .. } = msg else { return None };
+    let field = |key: &str| payload.get(key).and_then(|v| v.as_u64());
+    match feed {
+        Feed::Depth => field("seq").map(|seq| (seq, seq.saturating_sub(1))),
+        _ => Some((field("event_seq").filter(|&seq| seq > 0)?, field("prev_event_seq").unwrap_or(0))),
+    }
+}
+
+pub enum Replay<'a> {
+    /// Everything after `from_seq`, oldest first (empty when the client is up to date)
+    Messages(Vec<&'a ServerMsg>),
+    /// The message following `from_seq` is not buffered (anymore), or `from_seq` is
+    /// from before an engine restart
+    TooOld,
+}
+
+/// Bounded ring buffer of one channel's recent messages
+#[derive(Default)]
+pub struct ChannelHistory {
+    entries: VecDeque<(u64, u64, ServerMsg)>, // (seq, prev seq, message)
+}
+
+impl ChannelHistory {
+    pub fn push(&mut self, feed: Feed, msg: &ServerMsg) {
+        let Some((seq, prev)) = sequence(feed, msg) else { return };
+        // Depth seq restarts at 1 with the engine; older entries belong to another run
+        if feed == Feed::Depth && self.entries.back().is_some_and(|&(last, _, _)| seq <= last) {
+            self.entries.clear();
+        }
+        self.entries.push_back((seq, prev, msg.clone()));
+        while self.entries.len() > HISTORY_LEN {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Messages a client that last saw `from_seq` has missed
+    pub fn replay(&self, from_seq: u64) -> Replay<'_> {
+        // Nothing buffered (e.g. ws restarted): cannot tell what the client missed
+        let Some(&(newest, _, _)) = self.entries.back() else { return Replay::TooOld };
+        if from_seq > newest {
+            return Replay::TooOld;
+        }
+        let missed: Vec<_> = self.entries.iter().skip_while(|&&(seq, _, _)| seq <= from_seq).collect();
+        match missed.first() {
+            Some(&&(_, prev, _)) if prev > from_seq => Replay::TooOld,
+            _ => Replay::Messages(missed.into_iter().map(|(_, _, msg)| msg).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(seq: u64, prev: u64) -> ServerMsg {
+        ServerMsg::Event {
+            channel: "trades:m".to_string(),
+            payload: serde_json::json!({ "event_seq": seq, "prev_event_seq": prev }),
+        }
+    }
+
+    fn depth(seq: u64) -> ServerMsg {
+        ServerMsg::Event { channel: "depth:m".to_string(), payload: serde_json::json!({ "seq": seq }) }
+    }
+
+    /// Sequence numbers of a replay, or None for `TooOld`
+    fn replayed(history: &ChannelHistory, feed: Feed, from_seq: u64) -> Option<Vec<u64>> {
+        match history.replay(from_seq) {
+            Replay::Messages(messages) => Some(messages.into_iter().map(|msg| sequence(feed, msg).unwrap().0).collect()),
+            Replay::TooOld => None,
+        }
+    }
+
+    #[test]
+    fn replays_what_the_client_missed() {
+        // `event_seq` is global, so one channel's numbers are sparse
+        let mut history = ChannelHistory::default();
+        for (seq, prev) in [(10, 0), (14, 10), (20, 14), (21, 20)] {
+            history.push(Feed::Trades, &event(seq, prev));
+        }
+        assert_eq!(replayed(&history, Feed::Trades, 14), Some(vec![20, 21]));
+        assert_eq!(replayed(&history, Feed::Trades, 10), Some(vec![14, 20, 21]));
+        assert_eq!(replayed(&history, Feed::Trades, 21), Some(vec![]));
+        // A seq this channel never saw, but nothing on it in between was lost
+        assert_eq!(replayed(&history, Feed::Trades, 12), Some(vec![14, 20, 21]));
+        // From the future, i.e. another engine run
+        assert_eq!(replayed(&history, Feed::Trades, 22), None);
+    }
+
+    #[test]
+    fn empty_history_is_too_old() {
+        assert_eq!(replayed(&ChannelHistory::default(), Feed::Trades, 0), None);
+    }
+
+    #[test]
+    fn unsequenced_messages_are_not_kept() {
+        let mut history = ChannelHistory::default();
+        history.push(Feed::Trades, &event(0, 0));
+        history.push(Feed::Trades, &ServerMsg::Info { message: "hello".to_string() });
+        assert!(history.entries.is_empty());
+    }
+
+    #[test]
+    fn ring_overflow_is_too_old() {
+        let mut history = ChannelHistory::default();
+        let total = HISTORY_LEN as u64 + 10;
+        for seq in 1..=total {
+            history.push(Feed::Trades, &event(seq, seq - 1));
+        }
+        assert_eq!(history.entries.len(), HISTORY_LEN);
+        // Seq 11 is the oldest kept; whoever saw less than 10 missed an evicted message
+        assert_eq!(replayed(&history, Feed::Trades, 9), None);
+        assert_eq!(replayed(&history, Feed::Trades, 10).map(|seqs| seqs.len()), Some(HISTORY_LEN));
+        assert_eq!(replayed(&history, Feed::Trades, total - 2), Some(vec![total - 1, total]));
+    }
+
+    #[test]
+    fn depth_seq_reset_starts_over() {
+        let mut history = ChannelHistory::default();
+        for seq in 1..=5 {
+            history.push(Feed::Depth, &depth(seq));
+        }
+        assert_eq!(replayed(&history, Feed::Depth, 3), Some(vec![4, 5]));
+
+        // The engine restarted and numbers depth from 1 again
+        history.push(Feed::Depth, &depth(1));
+        history.push(Feed::Depth, &depth(2));
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(replayed(&history, Feed::Depth, 3), None);
+        assert_eq!(replayed(&history, Feed::Depth, 1), Some(vec![2]));
+        assert_eq!(replayed(&history, Feed::Depth, 0), Some(vec![1, 2]));
+    }
+}
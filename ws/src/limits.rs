@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::Instant;
+
+/// Abuse limits, from the environment
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub max_connections_per_ip: usize,
+    pub max_subscriptions: usize, // per connection, market and private feeds alike
+    pub messages_per_sec: f64,    // inbound client messages per IP, all its connections together
+    pub message_burst: f64,
+    pub max_violations: u32, // rejected messages before the connection is closed
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(key: &str, default: T) -> T {
+            std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+        Limits {
+            max_connections_per_ip: var("WS_MAX_CONNECTIONS_PER_IP", 20),
+            max_subscriptions: var("WS_MAX_SUBSCRIPTIONS", 200),
+            messages_per_sec: var("WS_MESSAGES_PER_SEC", 10.0),
+            message_burst: var("WS_MESSAGE_BURST", 50.0),
+            max_violations: var("WS_MAX_VIOLATIONS", 10),
+        }
+    }
+}
+
+/// Token bucket refilled at `messages_per_sec` up to `message_burst`
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+struct IpState {
+    connections: usize,
+    bucket: Bucket,
+}
+
+/// Connection counts and message budgets per client IP
+#[derive(Clone)]
+pub struct IpLimiter {
+    limits: Limits,
+    ips: Arc<Mutex<HashMap<IpAddr, IpState>>>,
+}
+
+/// One admitted connection; releases its slot when dropped
+pub struct ConnectionSlot {
+    limiter: IpLimiter,
+    pub ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut ips = self.limiter.ips.lock().unwrap();
+        if let Some(state) = ips.get_mut(&self.ip) {
+            state.connections -= 1;
+            if state.connections == 0 {
+                ips.remove(&self.ip);
+            }
+        }
+    }
+}
+
+impl IpLimiter {
+    pub fn new(limits: Limits) -> Self {
+        IpLimiter { limits, ips: Arc::default() }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// None once `ip` has `max_connections_per_ip` open connections
+    pub fn connect(&self, ip: IpAddr) -> Option<ConnectionSlot> {
+        let mut ips = self.ips.lock().unwrap();
+        let state = ips.entry(ip).or_insert_with(|| IpState {
+            connections: 0,
+            bucket: Bucket { tokens: self.limits.message_burst, refilled: Instant::now() },
+        });
+        if state.connections >= self.limits.max_connections_per_ip {
+            return None;
+        }
+        state.connections += 1;
+        Some(ConnectionSlot { limiter: self.clone(), ip })
+    }
+
+    /// Take one message from the IP's budget; false when it is exhausted
+    pub fn allow_message(&self, slot: &ConnectionSlot) -> bool {
+        let mut ips = self.ips.lock().unwrap();
+        let Some(state) = ips.get_mut(&slot.ip) else { return true };
+        let bucket = &mut state.bucket;
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.limits.messages_per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(self.limits.message_burst);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn limiter() -> IpLimiter {
+        IpLimiter::new(Limits {
+            max_connections_per_ip: 2,
+            max_subscriptions: 10,
+            messages_per_sec: 2.0,
+            message_burst: 3.0,
+            max_violations: 3,
+        })
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    fn allowed(limiter: &IpLimiter, slot: &ConnectionSlot, messages: usize) -> usize {
+        (0..messages).filter(|_| limiter.allow_message(slot)).count()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn burst_then_refill() {
+        let limiter = limiter();
+        let slot = limiter.connect(ip(1)).unwrap();
+        assert_eq!(allowed(&limiter, &slot, 5), 3);
+
+        // Two messages a second
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(allowed(&limiter, &slot, 5), 1);
+        tokio::time::advance(Duration::from_millis(250)).await;
+        assert_eq!(allowed(&limiter, &slot, 5), 0);
+        tokio::time::advance(Duration::from_millis(250)).await;
+        assert_eq!(allowed(&limiter, &slot, 5), 1);
+
+        // An idle IP refills up to the burst, not beyond
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(allowed(&limiter, &slot, 10), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn budget_is_per_ip() {
+        let limiter = limiter();
+        let first = limiter.connect(ip(1)).unwrap();
+        let second = limiter.connect(ip(1)).unwrap();
+        let other = limiter.connect(ip(2)).unwrap();
+        // Connections of one IP share its budget
+        assert_eq!(allowed(&limiter, &first, 2), 2);
+        assert_eq!(allowed(&limiter, &second, 5), 1);
+        assert_eq!(allowed(&limiter, &other, 5), 3);
+    }
+
+    #[tokio::test]
+    async fn connection_slots_are_released_on_drop() {
+        let limiter = limiter();
+        let first = limiter.connect(ip(1)).unwrap();
+        let second = limiter.connect(ip(1)).unwrap();
+        assert!(limiter.connect(ip(1)).is_none());
+        assert!(limiter.connect(ip(2)).is_some());
+
+        drop(first);
+        let third = limiter.connect(ip(1)).expect("a dropped slot frees room");
+        assert!(limiter.connect(ip(1)).is_none());
+
+        drop(second);
+        drop(third);
+        assert!(limiter.ips.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnecting_does_not_reset_the_budget() {
+        let limiter = limiter();
+        let first = limiter.connect(ip(1)).unwrap();
+        assert_eq!(allowed(&limiter, &first, 5), 3);
+        let second = limiter.connect(ip(1)).unwrap();
+        drop(first);
+        assert_eq!(allowed(&limiter, &second, 5), 0);
+    }
+}
@@ -1,29 +1,66 @@
 mod types;
 mod user_manager;
 mod redis_manager;
+mod repr;
+mod cache;
+mod frame;
+mod history;
+mod markets;
+mod limits;
+mod sse;
 
 use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    extract::State,
+    extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    extract::{ConnectInfo, Query, State},
+    http::StatusCode,
     routing::get,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Router,
 };
-use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use tokio::signal;
+use tokio::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TryRecvError;
 use tracing::{error, info};
-use user_manager::UserManager;
+use user_manager::{Outbox, UserManager};
 use uuid::Uuid;
 
-use crate::types::{ClientMsg, Feed, ServerMsg};
+use crate::frame::Frame;
+use crate::limits::{ConnectionSlot, IpLimiter, Limits};
+use crate::markets::MarketRegistry;
+use crate::types::{ClientMsg, Encoding, Feed, MarketSel, Repr, ServerMsg};
 
 #[derive(Clone)]
 struct AppState {
     users: UserManager,
     redis_url: Arc<String>,
+    redis: redis::aio::MultiplexedConnection,
+    markets: MarketRegistry,
+    liveness: Liveness,
+    limiter: IpLimiter,
+}
+
+/// Connection liveness settings
+#[derive(Clone, Copy)]
+struct Liveness {
+    ping_interval: Duration,
+    idle_timeout: Duration, // no frame at all from the client for this long closes the connection
+}
+
+impl Liveness {
+    fn from_env() -> Self {
+        let secs = |key: &str, default: u64| {
+            std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        };
+        Liveness {
+            ping_interval: Duration::from_secs(secs("WS_PING_INTERVAL_SECS", 20)),
+            idle_timeout: Duration::from_secs(secs("WS_IDLE_TIMEOUT_SECS", 60)),
+        }
+    }
 }
 
 #[tokio::main]
@@ -33,30 +70,58 @@ async fn main() {
         .init();
 
     let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379/".to_string());
+    let redis = redis::Client::open(redis_url.as_str())
+        .expect("invalid REDIS_URL")
+        .get_multiplexed_tokio_connection()
+        .await
+        .expect("failed to connect to Redis");
+    // Symbols resolve once this has loaded; subscribing by id works regardless
+    let markets = MarketRegistry::default();
+    if let Err(e) = markets.reload().await {
+        error!("failed to load markets: {e}");
+    }
     let state = AppState {
         users: UserManager::default(),
         redis_url: Arc::new(redis_url.clone()),
+        redis,
+        markets,
+        liveness: Liveness::from_env(),
+        limiter: IpLimiter::new(Limits::from_env()),
     };
 
     // start redis listener
     {
         let users = state.users.clone();
+        let markets = state.markets.clone();
         let url = redis_url.clone();
         tokio::spawn(async move {
-            if let Err(e) = redis_manager::run_redis_listener(&url, users).await {
+            if let Err(e) = redis_manager::run_redis_listener(&url, users, markets).await {
                 error!("Redis listener error: {e}");
             }
         });
     }
 
+    // batched mini ticker for "*" subscribers
+    {
+        let users = state.users.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                tick.tick().await;
+                users.publish_mini_ticker().await;
+            }
+        });
+    }
+
     // ws route
     let app = Router::new()
         .route("/ws", get(ws_handler))
+        .route("/sse", get(sse::sse_handler))
         .with_state(state);
 
     let addr: SocketAddr = "0.0.0.0:9000".parse().unwrap();
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown())
         .await
         .unwrap();
@@ -67,23 +132,115 @@ async fn shutdown() {
     tracing::info!("Shutting down WS server...");
 }
 
+#[derive(Deserialize)]
+struct HandshakeQuery {
+    token: Option<String>,
+    #[serde(default)]
+    encoding: Encoding,
+}
+
+/// User id of a JWT issued by the api
+fn authenticate(token: &str) -> Result<Uuid, String> {
+    let claims = database::auth::verify_jwt(token).map_err(|e| format!("invalid token: {e}"))?;
+    Uuid::parse_str(&claims.user_id).map_err(|_| "invalid token: bad user id".to_string())
+}
+
 async fn ws_handler(
     ws: WebSocketUpgrade,
+    Query(query): Query<HandshakeQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| client_socket(socket, state))
+) -> Response {
+    let Some(slot) = state.limiter.connect(addr.ip()) else {
+        return (StatusCode::TOO_MANY_REQUESTS, "too many connections from this address").into_response();
+    };
+    // A token on the handshake must be valid; without one the connection starts anonymous
+    let user_id = match query.token.as_deref().map(authenticate).transpose() {
+        Ok(user_id) => user_id,
+        Err(e) => return (StatusCode::UNAUTHORIZED, e).into_response(),
+    };
+    let encoding = query.encoding;
+    ws.on_upgrade(move |socket| client_socket(socket, state, slot, user_id, encoding))
+}
+
+/// Seed the depth cache from the engine's snapshot if this market has none yet
+async fn seed_depth(users: &UserManager, redis: &mut redis::aio::MultiplexedConnection, market_id: Uuid) {
+    if users.has_depth(market_id).await {
+        return;
+    }
+    match redis_manager::fetch_depth_snapshot(redis, market_id).await {
+        Ok(Some(snapshot)) => users.seed_depth(market_id, &snapshot).await,
+        Ok(None) => {}
+        Err(e) => error!("depth snapshot fetch failed: {e}"),
+    }
+}
+
+/// Answer a message that broke a limit; once the client has broken `max_violations`
+/// of them, close the connection. True if it is being closed.
+async fn violation(users: &UserManager, client_id: Uuid, outbox: &Outbox, violations: &mut u32, limits: Limits, message: String) -> bool {
+    *violations += 1;
+    if *violations >= limits.max_violations {
+        info!("client {client_id} closed after {violations} limit violations");
+        outbox.close(close_code::POLICY, format!("too many limit violations: {message}"));
+        return true;
+    }
+    users.send_to(client_id, ServerMsg::Info { message }).await;
+    false
+}
+
+/// False once the socket is gone
+async fn send_frame(ws_tx: &mut SplitSink<WebSocket, Message>, frame: Frame) -> bool {
+    ws_tx.send(frame.into_message()).await.is_ok()
 }
 
-async fn client_socket(mut socket: WebSocket, state: AppState) {
+async fn client_socket(socket: WebSocket, state: AppState, slot: ConnectionSlot, mut user_id: Option<Uuid>, encoding: Encoding) {
     // register new client
-    let (client_id, mut rx) = state.users.register_client().await;
+    let (client_id, mut rx, outbox) = state.users.register_client(encoding).await;
     let (mut ws_tx, mut ws_rx) = socket.split();
 
-    // writer task: forward ServerMsg from mpsc to websocket
+    // writer task: forward encoded frames from mpsc to websocket, pinging the client on an interval
+    let liveness = state.liveness;
+    let writer_outbox = outbox.clone();
     let writer = tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            let txt = serde_json::to_string(&msg).unwrap_or_else(|_| "{\"type\":\"info\",\"message\":\"encode error\"}".to_string());
-            if ws_tx.send(Message::Text(txt)).await.is_err() {
+        let outbox = writer_outbox;
+        let mut ping = tokio::time::interval_at(Instant::now() + liveness.ping_interval, liveness.ping_interval);
+        loop {
+            if let Some((code, reason)) = outbox.close_frame() {
+                // Slow consumer or idle client: tell it why before closing
+                let frame = CloseFrame { code, reason: reason.into() };
+                let _ = ws_tx.send(Message::Close(Some(frame))).await;
+                break;
+            }
+            let frame = match rx.try_recv() {
+                Ok(frame) => frame,
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {
+                    // Queue drained: conflated state is newer than anything that was queued
+                    let latest = outbox.take_latest();
+                    if !latest.is_empty() {
+                        for frame in latest {
+                            if !send_frame(&mut ws_tx, frame).await {
+                                return;
+                            }
+                        }
+                        continue;
+                    }
+                    tokio::select! {
+                        frame = rx.recv() => match frame {
+                            Some(frame) => frame,
+                            None => break,
+                        },
+                        _ = outbox.wait() => continue,
+                        _ = ping.tick() => {
+                            if ws_tx.send(Message::Ping(Vec::new())).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    }
+                }
+            };
+            if !send_frame(&mut ws_tx, frame).await {
                 break;
             }
         }
@@ -91,45 +248,203 @@ async fn client_socket(mut socket: WebSocket, state: AppState) {
 
     // reader loop: process client messages
     let users = state.users.clone();
+    let mut redis = state.redis.clone();
+    let markets = state.markets.clone();
+    let limiter = state.limiter.clone();
+    let limits = limiter.limits();
     let reader = tokio::spawn(async move {
-        while let Some(Ok(msg)) = ws_rx.next().await {
-            match msg {
-                Message::Text(txt) => {
-                    let parsed = serde_json::from_str::<ClientMsg>(&txt);
-                    match parsed {
-                        Ok(ClientMsg::Ping) => {
-                            let _ = users.broadcast((Feed::Ticker, Uuid::nil()), ServerMsg::Pong).await;
+        let mut violations = 0;
+        loop {
+            // Any frame counts as activity, including the pongs answering our pings
+            let msg = match tokio::time::timeout(liveness.idle_timeout, ws_rx.next()).await {
+                Ok(Some(Ok(msg))) => msg,
+                Ok(_) => break,
+                Err(_) => {
+                    info!("client {client_id} idle for {:?}, closing", liveness.idle_timeout);
+                    outbox.close(close_code::AWAY, "idle timeout".to_string());
+                    break;
+                }
+            };
+            // The budget is shared by every connection from the client's address
+            if matches!(msg, Message::Text(_) | Message::Binary(_)) && !limiter.allow_message(&slot) {
+                let message = format!("rate limit of {}/s exceeded, message dropped", limits.messages_per_sec);
+                if violation(&users, client_id, &outbox, &mut violations, limits, message).await {
+                    break;
+                }
+                continue;
+            }
+            let parsed = match msg {
+                Message::Text(txt) => serde_json::from_str::<ClientMsg>(&txt).map_err(|e| e.to_string()),
+                Message::Binary(bytes) if encoding == Encoding::MsgPack => {
+                    rmp_serde::from_slice::<ClientMsg>(&bytes).map_err(|e| e.to_string())
+                }
+                Message::Close(_) => break,
+                Message::Binary(_) | Message::Ping(_) | Message::Pong(_) => continue, // ignore
+            };
+            match parsed {
+                Ok(ClientMsg::Ping) => {
+                    users.send_to(client_id, ServerMsg::Pong).await;
+                }
+                Ok(ClientMsg::Subscribe { market_id, feeds, repr }) => {
+                    let market_id = match markets.select(&market_id) {
+                        Ok(market_id) => market_id,
+                        Err(message) => {
+                            users.send_to(client_id, ServerMsg::Info { message }).await;
+                            continue;
                         }
-                        Ok(ClientMsg::Subscribe { market_id, feeds }) => {
-                            for f in feeds.iter().copied() {
-                                users.subscribe(client_id, (f, market_id)).await;
+                    };
+                    let rejection = if feeds.iter().any(Feed::is_private) {
+                        Some("private feeds need subscribe_private")
+                    } else if market_id == MarketSel::All && !feeds.iter().all(Feed::allows_wildcard) {
+                        Some("\"*\" only supports ticker, trades, candles and mini_ticker")
+                    } else if market_id != MarketSel::All && feeds.contains(&Feed::MiniTicker) {
+                        Some("mini_ticker needs market_id \"*\"")
+                    } else {
+                        None
+                    };
+                    if let Some(message) = rejection {
+                        users.send_to(client_id, ServerMsg::Info { message: message.to_string() }).await;
+                        continue;
+                    }
+                    if !users.has_room(client_id, feeds.iter().map(|&f| (f, market_id.key())), limits.max_subscriptions).await {
+                        let message = format!("subscription limit of {} reached", limits.max_subscriptions);
+                        if violation(&users, client_id, &outbox, &mut violations, limits, message).await {
+                            break;
+                        }
+                        continue;
+                    }
+                    if let (MarketSel::One(market_id), true) = (market_id, feeds.contains(&Feed::Depth)) {
+                        seed_depth(&users, &mut redis, market_id).await;
+                    }
+                    users.subscribe_market(client_id, market_id, &feeds, repr).await;
+                    users.send_to(client_id, ServerMsg::Info{ message: format!("subscribed to {feeds:?} {market_id} ({repr:?})")}).await;
+                }
+                Ok(ClientMsg::Unsubscribe { market_id, feeds }) => {
+                    let market_id = match markets.select(&market_id) {
+                        Ok(market_id) => market_id,
+                        Err(message) => {
+                            users.send_to(client_id, ServerMsg::Info { message }).await;
+                            continue;
+                        }
+                    };
+                    for f in feeds.iter().copied().filter(|f| !f.is_private()) {
+                        users.unsubscribe(client_id, (f, market_id.key())).await;
+                    }
+                    users.send_to(client_id, ServerMsg::Info{ message: format!("unsubscribed from {feeds:?} {market_id}")}).await;
+                }
+                Ok(ClientMsg::Auth { token }) => {
+                    let message = match (authenticate(&token), user_id) {
+                        (Ok(id), Some(current)) if id != current => "already authenticated as another user".to_string(),
+                        (Ok(id), _) => {
+                            user_id = Some(id);
+                            format!("authenticated as {id}")
+                        }
+                        (Err(e), _) => e,
+                    };
+                    users.send_to(client_id, ServerMsg::Info { message }).await;
+                }
+                Ok(ClientMsg::SubscribePrivate { feeds, repr }) => {
+                    // Keyed by the authenticated user, so nobody can follow another user's feeds
+                    let message = match user_id {
+                        None => "authenticate first".to_string(),
+                        Some(_) if !feeds.iter().all(Feed::is_private) => "subscribe_private only accepts orders, fills and balances".to_string(),
+                        Some(id) if !users.has_room(client_id, feeds.iter().map(|&f| (f, id)), limits.max_subscriptions).await => {
+                            let message = format!("subscription limit of {} reached", limits.max_subscriptions);
+                            if violation(&users, client_id, &outbox, &mut violations, limits, message).await {
+                                break;
                             }
-                            let _ = users.inner.read().await.clients.get(&client_id)
-                                .map(|tx| tx.try_send(ServerMsg::Info{ message: format!("subscribed to {feeds:?} {market_id}")}));
+                            continue;
                         }
-                        Ok(ClientMsg::Unsubscribe { market_id, feeds }) => {
+                        Some(id) => {
                             for f in feeds.iter().copied() {
-                                users.unsubscribe(client_id, (f, market_id)).await;
+                                users.subscribe(client_id, (f, id), repr).await;
                             }
-                            let _ = users.inner.read().await.clients.get(&client_id)
-                                .map(|tx| tx.try_send(ServerMsg::Info{ message: format!("unsubscribed from {feeds:?} {market_id}")}));
+                            format!("subscribed to {feeds:?} ({repr:?})")
                         }
-                        Err(e) => {
-                            if let Some(tx) = users.inner.read().await.clients.get(&client_id) {
-                                let _ = tx.try_send(ServerMsg::Info { message: format!("invalid message: {e}") });
-                            }
+                    };
+                    users.send_to(client_id, ServerMsg::Info { message }).await;
+                }
+                Ok(ClientMsg::UnsubscribePrivate { feeds }) => {
+                    if let Some(id) = user_id {
+                        for f in feeds.iter().copied().filter(Feed::is_private) {
+                            users.unsubscribe(client_id, (f, id)).await;
                         }
                     }
+                    users.send_to(client_id, ServerMsg::Info { message: format!("unsubscribed from {feeds:?}") }).await;
+                }
+                Ok(ClientMsg::Resume { channel, from_seq }) => {
+                    let message = match redis_manager::parse_channel(&channel) {
+                        Some(sub) => users.resume(client_id, sub, from_seq).await,
+                        None => format!("unknown channel {channel}"),
+                    };
+                    users.send_to(client_id, ServerMsg::Info { message }).await;
+                }
+                Ok(ClientMsg::DepthSnapshot { market_id, repr }) => {
+                    let market_id = match markets.select(&market_id) {
+                        Ok(MarketSel::One(market_id)) => market_id,
+                        Ok(MarketSel::All) => {
+                            users.send_to(client_id, ServerMsg::Info { message: "depth_snapshot needs a single market".to_string() }).await;
+                            continue;
+                        }
+                        Err(message) => {
+                            users.send_to(client_id, ServerMsg::Info { message }).await;
+                            continue;
+                        }
+                    };
+                    let reply = match redis_manager::fetch_depth_snapshot(&mut redis, market_id).await {
+                        Ok(Some(payload)) => {
+                            let msg = ServerMsg::Snapshot { channel: format!("depth:{market_id}"), payload };
+                            match repr {
+                                Repr::Decimal => msg,
+                                Repr::Atomic => msg.to_atomic(Feed::Depth),
+                            }
+                        }
+                        Ok(None) => ServerMsg::Info { message: format!("no depth snapshot for {market_id}") },
+                        Err(e) => {
+                            error!("depth snapshot fetch failed: {e}");
+                            ServerMsg::Info { message: "depth snapshot unavailable".to_string() }
+                        }
+                    };
+                    users.send_to(client_id, reply).await;
+                }
+                Ok(ClientMsg::Markets) => {
+                    let payload = serde_json::json!({ "markets": markets.list() });
+                    users.send_to(client_id, ServerMsg::Snapshot { channel: "markets".to_string(), payload }).await;
+                }
+                Err(e) => {
+                    users.send_to(client_id, ServerMsg::Info { message: format!("invalid message: {e}") }).await;
                 }
-                Message::Close(_) => break,
-                Message::Binary(_) | Message::Ping(_) | Message::Pong(_) => {} // ignore
             }
         }
     });
 
-    // wait for either to finish
-    let _ = tokio::join!(writer, reader);
-
-    // cleanup
+    // Once the client is gone or idle, unregistering drops the queue's sender,
+    // which lets the writer finish after flushing a pending close frame
+    let _ = reader.await;
     state.users.unregister_client(client_id).await;
-}
\ No newline at end of file
+    let _ = writer.await;
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn violations_warn_until_the_limit_then_close() {
+        let users = UserManager::default();
+        let (client_id, mut rx, outbox) = users.register_client(Encoding::Json).await;
+        let limits = Limits { max_connections_per_ip: 1, max_subscriptions: 1, messages_per_sec: 1.0, message_burst: 1.0, max_violations: 3 };
+        let mut violations = 0;
+
+        for n in 1..limits.max_violations {
+            assert!(!violation(&users, client_id, &outbox, &mut violations, limits, format!("warning {n}")).await);
+            assert_eq!(violations, n);
+            let Ok(Frame::Text(text)) = rx.try_recv() else { panic!("expected an info frame") };
+            assert!(text.contains(&format!("warning {n}")), "{text}");
+            assert!(outbox.close_frame().is_none());
+        }
+
+        assert!(violation(&users, client_id, &outbox, &mut violations, limits, "one too many".to_string()).await);
+        assert_eq!(outbox.close_frame(), Some((close_code::POLICY, "too many limit violations: one too many".to_string())));
+        assert!(rx.try_recv().is_err(), "the closing violation is not also sent as info");
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use database::schema::{markets, tokens};
+use database::Decimal;
+
+use crate::types::{MarketRef, MarketSel};
+
+/// Redis channel the api publishes admin market changes on
+pub const MARKETS_CHANNEL: &str = "markets:updated";
+
+/// Tradable market as listed to clients; sizes are decimal strings
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketInfo {
+    pub market_id: Uuid,
+    pub symbol: String, // e.g. "SOL_USDC"
+    pub base: String,
+    pub quote: String,
+    pub tick_size: Decimal,
+    pub min_order_size: Decimal,
+    pub price_decimals: u32,
+    pub quantity_decimals: u32,
+}
+
+/// (id, symbol, tick size, min order size, base symbol, base decimals, quote symbol, quote decimals)
+type MarketRow = (Uuid, String, i64, i64, String, i32, String, i32);
+
+/// Active markets with the symbols and decimals of their tokens
+fn load_markets(conn: &mut PgConnection) -> QueryResult<Vec<MarketInfo>> {
+    let (base, quote) = diesel::alias!(tokens as base, tokens as quote);
+    let rows: Vec<MarketRow> = markets::table
+        .inner_join(base.on(base.field(tokens::id).eq(markets::base_currency_id)))
+        .inner_join(quote.on(quote.field(tokens::id).eq(markets::quote_currency_id)))
+        .filter(markets::is_active.eq(true))
+        .select((
+            markets::id,
+            markets::symbol,
+            markets::tick_size,
+            markets::min_order_size,
+            base.field(tokens::symbol),
+            base.field(tokens::decimals),
+            quote.field(tokens::symbol),
+            quote.field(tokens::decimals),
+        ))
+        .load(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(market_id, symbol, tick_size, min_order_size, base, base_decimals, quote, quote_decimals)| {
+            let (price_decimals, quantity_decimals) = (quote_decimals.max(0) as u32, base_decimals.max(0) as u32);
+            MarketInfo {
+                market_id,
+                symbol,
+                base,
+                quote,
+                tick_size: Decimal::from_atomic(tick_size as i128, price_decimals),
+                min_order_size: Decimal::from_atomic(min_order_size as i128, quantity_decimals),
+                price_decimals,
+                quantity_decimals,
+            }
+        })
+        .collect())
+}
+
+/// Tradable markets by id, reloaded from the `markets` table whenever the api reports a change
+#[derive(Clone, Default)]
+pub struct MarketRegistry {
+    markets: Arc<RwLock<HashMap<Uuid, MarketInfo>>>,
+}
+
+impl MarketRegistry {
+    pub async fn reload(&self) -> Result<(), String> {
+        let markets = tokio::task::spawn_blocking(|| load_markets(&mut database::establish_connection()))
+            .await
+            .map_err(|e| format!("market load task failed: {e}"))?
+            .map_err(|e| format!("market query failed: {e}"))?;
+        *self.markets.write().unwrap() = markets.into_iter().map(|m| (m.market_id, m)).collect();
+        Ok(())
+    }
+
+    pub fn get(&self, market_id: Uuid) -> Option<MarketInfo> {
+        self.markets.read().unwrap().get(&market_id).cloned()
+    }
+
+    /// Market id of a symbol such as "SOL_USDC" (case-insensitive)
+    pub fn resolve(&self, symbol: &str) -> Option<Uuid> {
+        self.markets.read().unwrap().values()
+            .find(|m| m.symbol.eq_ignore_ascii_case(symbol))
+            .map(|m| m.market_id)
+    }
+
+    /// Ids pass through as given; symbols must name a tradable market
+    pub fn select(&self, market: &MarketRef) -> Result<MarketSel, String> {
+        match market {
+            MarketRef::All => Ok(MarketSel::All),
+            MarketRef::Id(market_id) => Ok(MarketSel::One(*market_id)),
+            MarketRef::Symbol(symbol) => self.resolve(symbol).map(MarketSel::One).ok_or_else(|| format!("unknown market {symbol}")),
+        }
+    }
+
+    /// Every tradable market, sorted by symbol
+    pub fn list(&self) -> Vec<MarketInfo> {
+        let mut markets: Vec<_> = self.markets.read().unwrap().values().cloned().collect();
+        markets.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        markets
+    }
+}
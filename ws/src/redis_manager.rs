@@ -1,44 +1,162 @@
-use redis::aio::ConnectionLike;
+use std::collections::HashMap;
+
+use redis::aio::{ConnectionLike, MultiplexedConnection};
 use redis::{AsyncCommands, RedisResult};
 use tokio_stream::StreamExt;
 use uuid::Uuid;
 
+use crate::markets::{MarketRegistry, MARKETS_CHANNEL};
 use crate::types::{Feed, ServerMsg};
 use crate::user_manager::{UserManager, SubKey};
 
-fn parse_channel(channel: &str) -> Option<(Feed, Uuid)> {
-    // expected: "depth:<uuid>", "ticker:<uuid>", "trades:<uuid>"
+pub fn parse_channel(channel: &str) -> Option<(Feed, Uuid)> {
+    // expected: "depth:<uuid>", "ticker:<uuid>", "trades:<uuid>", "l3:<uuid>", "candles:<uuid>",
+    // and per user "orders:<uuid>", "fills:<uuid>", "balances:<uuid>"
     let (pfx, rest) = channel.split_once(':')?;
     let id = Uuid::parse_str(rest).ok()?;
-    let feed = match pfx {
-        "depth" => Feed::Depth,
-        "ticker" => Feed::Ticker,
-        "trades" => Feed::Trades,
-        _ => return None,
-    };
+    let feed = Feed::ALL.into_iter().find(|feed| feed.prefix() == pfx)?;
     Some((feed, id))
 }
 
-pub async fn run_redis_listener(redis_url: &str, users: UserManager) -> RedisResult<()> {
+/// A `seq` this far below the last forwarded one cannot be a redelivery; the engine's counter was reset
+const RESET_DISTANCE: u64 = 100_000;
+
+/// Checks the engine's `event_seq`/`prev_event_seq` stamps per channel.
+/// Returns false for a duplicate that must not be forwarded; gaps are only logged.
+fn check_sequence(last_seq: &mut HashMap<String, u64>, channel: &str, payload: &serde_json::Value) -> bool {
+    let seq = payload.get("event_seq").and_then(|v| v.as_u64()).unwrap_or(0);
+    if seq == 0 {
+        return true;
+    }
+    let prev = payload.get("prev_event_seq").and_then(|v| v.as_u64()).unwrap_or(0);
+    let last = last_seq.get(channel).copied().unwrap_or(0);
+    if seq <= last {
+        // An engine that lost `engine:event_seq` starts over with no previous event
+        if prev == 0 || last - seq >= RESET_DISTANCE {
+            tracing::warn!("engine sequence reset on {channel}: seq {seq} after {last}, resuming from it");
+            last_seq.insert(channel.to_string(), seq);
+            return true;
+        }
+        tracing::warn!("dropping duplicate event on {channel}: seq {seq} <= {last}");
+        return false;
+    }
+    if last != 0 && prev != last {
+        tracing::warn!("sequence gap on {channel}: expected prev {last}, got {prev} (seq {seq})");
+    }
+    last_seq.insert(channel.to_string(), seq);
+    true
+}
+
+/// Latest depth snapshot stored by the engine under `snapshot:depth:<market_id>`
+pub async fn fetch_depth_snapshot(conn: &mut MultiplexedConnection, market_id: Uuid) -> RedisResult<Option<serde_json::Value>> {
+    let raw: Option<String> = conn.get(format!("snapshot:depth:{market_id}")).await?;
+    Ok(raw.and_then(|s| serde_json::from_str(&s).ok()))
+}
+
+/// An admin created, updated or deactivated a market: reload the registry and tell every
+/// connected client, with the market as now listed (null once it is no longer tradable)
+async fn market_changed(users: &UserManager, markets: &MarketRegistry, payload: &str) {
+    let change: serde_json::Value = serde_json::from_str(payload).unwrap_or_default();
+    if let Err(e) = markets.reload().await {
+        tracing::error!("failed to reload markets: {e}");
+        return;
+    }
+    let market = change.get("market_id")
+        .and_then(|v| v.as_str())
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .and_then(|id| markets.get(id));
+    let payload = serde_json::json!({
+        "action": change.get("action"),
+        "market_id": change.get("market_id"),
+        "market": market,
+    });
+    users.send_all(ServerMsg::Event { channel: "markets".to_string(), payload }).await;
+}
+
+pub async fn run_redis_listener(redis_url: &str, users: UserManager, markets: MarketRegistry) -> RedisResult<()> {
     let client = redis::Client::open(redis_url)?;
     let mut conn = client.get_async_connection().await?;
     let mut pubsub = conn.into_pubsub();
     pubsub.psubscribe("depth:*").await?;
     pubsub.psubscribe("ticker:*").await?;
     pubsub.psubscribe("trades:*").await?;
+    pubsub.psubscribe("l3:*").await?;
+    pubsub.psubscribe("candles:*").await?;
+    pubsub.psubscribe("orders:*").await?;
+    pubsub.psubscribe("fills:*").await?;
+    pubsub.psubscribe("balances:*").await?;
+    pubsub.subscribe(MARKETS_CHANNEL).await?;
 
+    let mut last_seq: HashMap<String, u64> = HashMap::new();
     let mut stream = pubsub.on_message();
     while let Some(msg) = stream.next().await {
         let channel: String = msg.get_channel_name().into();
         let payload: String = msg.get_payload()?;
+        if channel == MARKETS_CHANNEL {
+            market_changed(&users, &markets, &payload).await;
+            continue;
+        }
         if let Some((feed, market_id)) = parse_channel(&channel) {
             // Try parse JSON; if not JSON, wrap as string
             let json = serde_json::from_str::<serde_json::Value>(&payload)
                 .unwrap_or_else(|_| serde_json::Value::String(payload.clone()));
+            if !check_sequence(&mut last_seq, &channel, &json) {
+                continue;
+            }
             users
-                .broadcast((feed, market_id) as SubKey, ServerMsg::Event { channel, payload: json })
+                .publish((feed, market_id) as SubKey, ServerMsg::Event { channel, payload: json })
                 .await;
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(seq: u64, prev: u64) -> serde_json::Value {
+        serde_json::json!({ "event_seq": seq, "prev_event_seq": prev })
+    }
+
+    #[test]
+    fn duplicates_are_dropped() {
+        let mut last = HashMap::new();
+        assert!(check_sequence(&mut last, "trades:a", &stamp(10, 0)));
+        assert!(check_sequence(&mut last, "trades:a", &stamp(11, 10)));
+        assert!(!check_sequence(&mut last, "trades:a", &stamp(11, 10)));
+        assert!(!check_sequence(&mut last, "trades:a", &stamp(5, 4)));
+        // Channels are tracked separately
+        assert!(check_sequence(&mut last, "depth:a", &stamp(5, 4)));
+    }
+
+    #[test]
+    fn gaps_are_forwarded() {
+        let mut last = HashMap::new();
+        assert!(check_sequence(&mut last, "trades:a", &stamp(10, 0)));
+        assert!(check_sequence(&mut last, "trades:a", &stamp(20, 15)));
+        assert_eq!(last["trades:a"], 20);
+    }
+
+    #[test]
+    fn engine_reset_is_accepted() {
+        let mut last = HashMap::new();
+        assert!(check_sequence(&mut last, "trades:a", &stamp(500, 499)));
+        // Restarted counter: first event on the channel has no predecessor
+        assert!(check_sequence(&mut last, "trades:a", &stamp(3, 0)));
+        assert_eq!(last["trades:a"], 3);
+        assert!(check_sequence(&mut last, "trades:a", &stamp(4, 3)));
+
+        assert!(check_sequence(&mut last, "depth:a", &stamp(RESET_DISTANCE + 50, RESET_DISTANCE + 49)));
+        assert!(check_sequence(&mut last, "depth:a", &stamp(50, 40)));
+        assert_eq!(last["depth:a"], 50);
+    }
+
+    #[test]
+    fn unstamped_events_pass() {
+        let mut last = HashMap::new();
+        assert!(check_sequence(&mut last, "trades:a", &stamp(10, 0)));
+        assert!(check_sequence(&mut last, "trades:a", &serde_json::json!({ "price": "1" })));
+        assert_eq!(last["trades:a"], 10);
+    }
+}
@@ -0,0 +1,149 @@
+use serde_json::Value;
+
+use crate::types::{Feed, ServerMsg};
+
+/// Atomic units of a decimal string, e.g. ("50.25", 6) -> "50250000".
+/// None if the string is not a plain decimal or has more places than `decimals`.
+pub(crate) fn decimal_to_atomic(s: &str, decimals: usize) -> Option<String> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if int_part.is_empty() || !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // Trailing zeros beyond the token's decimals carry no value
+    let frac_part = if frac_part.len() > decimals {
+        let (kept, dropped) = frac_part.split_at(decimals);
+        if dropped.chars().any(|c| c != '0') {
+            return None;
+        }
+        kept
+    } else {
+        frac_part
+    };
+
+    let digits = format!("{}{:0<width$}", int_part, frac_part, width = decimals);
+    let digits = digits.trim_start_matches('0');
+    Some(match (digits.is_empty(), negative) {
+        (true, _) => "0".to_string(),
+        (false, true) => format!("-{}", digits),
+        (false, false) => digits.to_string(),
+    })
+}
+
+fn convert_field(obj: &mut serde_json::Map<String, Value>, key: &str, decimals: usize) {
+    if let Some(Value::String(s)) = obj.get(key) {
+        if let Some(atomic) = decimal_to_atomic(s, decimals) {
+            obj.insert(key.to_string(), Value::String(atomic));
+        }
+    }
+}
+
+fn convert_levels(levels: Option<&mut Value>, price_decimals: usize, quantity_decimals: usize) {
+    let Some(Value::Array(levels)) = levels else { return };
+    for level in levels {
+        if let Value::Array(pair) = level {
+            if let [Value::String(price), Value::String(quantity)] = pair.as_mut_slice() {
+                if let Some(p) = decimal_to_atomic(price, price_decimals) {
+                    *price = p;
+                }
+                if let Some(q) = decimal_to_atomic(quantity, quantity_decimals) {
+                    *quantity = q;
+                }
+            }
+        }
+    }
+}
+
+/// Rewrite an engine feed payload from decimal strings into atomic units using
+/// the `price_decimals`/`quantity_decimals` it carries. Unknown shapes pass through unchanged.
+pub fn to_atomic(feed: Feed, payload: &Value) -> Value {
+    let mut payload = payload.clone();
+    let Value::Object(obj) = &mut payload else { return payload };
+    // One ticker per market, each with its own decimals
+    if feed == Feed::MiniTicker {
+        if let Some(Value::Array(tickers)) = obj.get_mut("tickers") {
+            for ticker in tickers.iter_mut() {
+                *ticker = to_atomic(Feed::Ticker, ticker);
+            }
+        }
+        return payload;
+    }
+    // Balances are per token and carry that token's decimals only
+    if feed == Feed::Balances {
+        if let Some(decimals) = obj.get("decimals").and_then(|v| v.as_u64()) {
+            convert_field(obj, "available", decimals as usize);
+            convert_field(obj, "locked", decimals as usize);
+        }
+        return payload;
+    }
+    let decimals = |key: &str| obj.get(key).and_then(|v| v.as_u64()).map(|d| d as usize);
+    let (Some(price_decimals), Some(quantity_decimals)) = (decimals("price_decimals"), decimals("quantity_decimals")) else {
+        return payload;
+    };
+
+    match feed {
+        Feed::Depth => {
+            convert_levels(obj.get_mut("bids"), price_decimals, quantity_decimals);
+            convert_levels(obj.get_mut("asks"), price_decimals, quantity_decimals);
+        }
+        Feed::Ticker => {
+            // Quote volume is in quote token units, the same scale as prices
+            for key in ["last_price", "open_24h", "high_24h", "low_24h", "quote_volume_24h"] {
+                convert_field(obj, key, price_decimals);
+            }
+            convert_field(obj, "volume_24h", quantity_decimals);
+        }
+        Feed::Trades => {
+            convert_field(obj, "price", price_decimals);
+            convert_field(obj, "quantity", quantity_decimals);
+        }
+        Feed::L3 => {
+            convert_field(obj, "price", price_decimals);
+            convert_field(obj, "size", quantity_decimals);
+        }
+        Feed::Candles => {
+            for key in ["open", "high", "low", "close"] {
+                convert_field(obj, key, price_decimals);
+            }
+            convert_field(obj, "volume", quantity_decimals);
+        }
+        Feed::Orders => {
+            convert_field(obj, "price", price_decimals);
+            convert_field(obj, "quantity", quantity_decimals);
+            convert_field(obj, "filled_quantity", quantity_decimals);
+        }
+        Feed::Fills => {
+            convert_field(obj, "price", price_decimals);
+            convert_field(obj, "quantity", quantity_decimals);
+            convert_field(obj, "quote_amount", price_decimals);
+        }
+        Feed::MiniTicker | Feed::Balances => {}
+    }
+    payload
+}
+
+impl ServerMsg {
+    /// The same message in atomic representation; only feed events and snapshots are affected
+    pub fn to_atomic(&self, feed: Feed) -> ServerMsg {
+        match self {
+            ServerMsg::Event { channel, payload } => ServerMsg::Event {
+                channel: channel.clone(),
+                payload: to_atomic(feed, payload),
+            },
+            // Trades snapshots carry a list of trade payloads
+            ServerMsg::Snapshot { channel, payload } => {
+                let mut payload = to_atomic(feed, payload);
+                if let Some(Value::Array(trades)) = payload.get_mut("trades") {
+                    for trade in trades.iter_mut() {
+                        *trade = to_atomic(feed, trade);
+                    }
+                }
+                ServerMsg::Snapshot { channel: channel.clone(), payload }
+            }
+            other => other.clone(),
+        }
+    }
+}
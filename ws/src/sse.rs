@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Response},
+};
+use futures_util::stream;
+use serde::Deserialize;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::info;
+
+use crate::frame::Frame;
+use crate::limits::ConnectionSlot;
+use crate::types::{Encoding, Feed, MarketRef, MarketSel, Repr, ServerMsg};
+use crate::user_manager::{ClientId, Outbox, Rx, UserManager};
+use crate::{seed_depth, AppState};
+
+#[derive(Deserialize)]
+pub struct SseQuery {
+    market_id: MarketRef,
+    feeds: String, // comma separated, e.g. "depth,trades"
+    #[serde(default)]
+    repr: Repr,
+}
+
+/// Last seq seen per feed, sent as the event id, e.g. `depth:812,trades:40317`.
+/// Depth is positioned by its delta `seq`, the other feeds by `event_seq`.
+type Positions = BTreeMap<Feed, u64>;
+
+fn parse_positions(last_event_id: &str) -> Positions {
+    last_event_id.split(',')
+        .filter_map(|entry| {
+            let (prefix, seq) = entry.split_once(':')?;
+            let feed = Feed::ALL.into_iter().find(|feed| feed.prefix() == prefix)?;
+            Some((feed, seq.parse().ok()?))
+        })
+        .collect()
+}
+
+fn format_positions(positions: &Positions) -> String {
+    positions.iter().map(|(feed, seq)| format!("{}:{seq}", feed.prefix())).collect::<Vec<_>>().join(",")
+}
+
+/// Feed and seq a message moves the client to, if any
+fn position(msg: &ServerMsg) -> Option<(Feed, u64)> {
+    let (ServerMsg::Event { channel, payload } | ServerMsg::Snapshot { channel, payload }) = msg else { return None };
+    let prefix = channel.split_once(':')?.0;
+    let feed = Feed::ALL.into_iter().find(|feed| feed.prefix() == prefix)?;
+    let key = if feed == Feed::Depth { "seq" } else { "event_seq" };
+    let seq = payload.get(key)?.as_u64().filter(|&seq| seq > 0)?;
+    Some((feed, seq))
+}
+
+/// One SSE connection: a regular `UserManager` client that is never written to by the
+/// socket side, unregistered when the response stream is dropped
+struct SseClient {
+    users: UserManager,
+    client_id: ClientId,
+    rx: Rx,
+    outbox: Arc<Outbox>,
+    pending: VecDeque<Frame>, // conflated state taken from the outbox
+    positions: Positions,
+    closed: bool,
+    _slot: ConnectionSlot,
+}
+
+impl Drop for SseClient {
+    fn drop(&mut self) {
+        let (users, client_id) = (self.users.clone(), self.client_id);
+        tokio::spawn(async move { users.unregister_client(client_id).await });
+    }
+}
+
+impl SseClient {
+    /// Same order as the ws writer: queued frames, then conflated state once the queue drains
+    async fn next_frame(&mut self) -> Result<Frame, Option<String>> {
+        loop {
+            if let Some((_, reason)) = self.outbox.close_frame() {
+                return Err(Some(reason));
+            }
+            if let Some(frame) = self.pending.pop_front() {
+                return Ok(frame);
+            }
+            match self.rx.try_recv() {
+                Ok(frame) => return Ok(frame),
+                Err(TryRecvError::Disconnected) => return Err(None),
+                Err(TryRecvError::Empty) => {
+                    self.pending.extend(self.outbox.take_latest());
+                    if !self.pending.is_empty() {
+                        continue;
+                    }
+                    tokio::select! {
+                        frame = self.rx.recv() => return frame.ok_or(None),
+                        _ = self.outbox.wait() => continue,
+                    }
+                }
+            }
+        }
+    }
+
+    async fn next_event(&mut self) -> Option<Event> {
+        if self.closed {
+            return None;
+        }
+        let frame = match self.next_frame().await {
+            Ok(frame) => frame,
+            Err(reason) => {
+                // Slow consumer: say why before ending the stream
+                self.closed = true;
+                return reason.map(|reason| Event::default().event("close").data(reason));
+            }
+        };
+        // Registered with JSON encoding, so every frame is text
+        let Frame::Text(txt) = frame else { return Some(Event::default().comment("skipped binary frame")) };
+        let msg = serde_json::from_str::<ServerMsg>(&txt).ok();
+        let kind = match &msg {
+            Some(ServerMsg::Event { .. }) => "event",
+            Some(ServerMsg::Snapshot { .. }) => "snapshot",
+            Some(ServerMsg::Pong) => "pong",
+            Some(ServerMsg::Info { .. }) | None => "info",
+        };
+        let mut event = Event::default().event(kind).data(&*txt);
+        if let Some((feed, seq)) = msg.as_ref().and_then(position) {
+            self.positions.insert(feed, seq);
+            event = event.id(format_positions(&self.positions));
+        }
+        Some(event)
+    }
+}
+
+/// `GET /sse?market_id=&feeds=depth,trades&repr=`: the public feeds of one market as
+/// Server-Sent Events carrying the same messages as the WebSocket. A reconnect with
+/// `Last-Event-ID` resumes each feed where it left off; otherwise each starts with a snapshot.
+pub async fn sse_handler(
+    Query(query): Query<SseQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let market_id = match state.markets.select(&query.market_id) {
+        Ok(MarketSel::One(market_id)) => market_id,
+        Ok(MarketSel::All) => return (StatusCode::BAD_REQUEST, "sse needs a single market").into_response(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let mut feeds = Vec::new();
+    for name in query.feeds.split(',').map(str::trim) {
+        match Feed::ALL.into_iter().find(|feed| feed.prefix() == name) {
+            Some(feed) if !feed.is_private() && feed != Feed::MiniTicker => feeds.push(feed),
+            _ => return (StatusCode::BAD_REQUEST, format!("unsupported feed {name:?}")).into_response(),
+        }
+    }
+    if feeds.len() > state.limiter.limits().max_subscriptions {
+        return (StatusCode::BAD_REQUEST, "too many feeds").into_response();
+    }
+    let Some(slot) = state.limiter.connect(addr.ip()) else {
+        return (StatusCode::TOO_MANY_REQUESTS, "too many connections from this address").into_response();
+    };
+
+    let positions = headers.get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .map(parse_positions)
+        .unwrap_or_default();
+    let users = state.users.clone();
+    let (client_id, rx, outbox) = users.register_client(Encoding::Json).await;
+    let mut redis = state.redis.clone();
+    for &feed in &feeds {
+        // Also on resume: after a ws restart the history is empty and the
+        // depth snapshot is the only way back for a client that cannot resubscribe
+        if feed == Feed::Depth {
+            seed_depth(&users, &mut redis, market_id).await;
+        }
+        match positions.get(&feed) {
+            Some(&from_seq) => {
+                let message = users.subscribe_from(client_id, (feed, market_id), query.repr, from_seq).await;
+                users.send_to(client_id, ServerMsg::Info { message }).await;
+            }
+            None => users.subscribe_market(client_id, MarketSel::One(market_id), &[feed], query.repr).await,
+        }
+    }
+    info!("sse client {client_id} following {feeds:?} {market_id}");
+
+    let client = SseClient { users, client_id, rx, outbox, pending: VecDeque::new(), positions, closed: false, _slot: slot };
+    let events = stream::unfold(client, |mut client| async move {
+        let event = client.next_event().await?;
+        Some((Ok::<_, Infallible>(event), client))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::new().interval(state.liveness.ping_interval))
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn positions_round_trip() {
+        let positions = Positions::from([(Feed::Depth, 812), (Feed::Trades, 40317), (Feed::MiniTicker, 7)]);
+        let id = format_positions(&positions);
+        assert_eq!(id, "depth:812,trades:40317,mini_ticker:7");
+        assert_eq!(parse_positions(&id), positions);
+    }
+
+    #[test]
+    fn malformed_entries_are_skipped() {
+        for id in ["", ",", "depth", "depth:", "depth:abc", "depth:-1", "depth:1.5", ":12", "depth 12", "depth:18446744073709551616"] {
+            assert!(parse_positions(id).is_empty(), "{id:?}");
+        }
+    }
+
+    #[test]
+    fn valid_entries_survive_bad_neighbours() {
+        let positions = parse_positions("depth:812,garbage,trades:,ticker:9,");
+        assert_eq!(positions, Positions::from([(Feed::Depth, 812), (Feed::Ticker, 9)]));
+    }
+
+    #[test]
+    fn unknown_channels_are_ignored() {
+        let positions = parse_positions("bogus:5,DEPTH:6,trades:7,depth_v2:8");
+        assert_eq!(positions, Positions::from([(Feed::Trades, 7)]));
+    }
+
+    #[test]
+    fn later_duplicates_win() {
+        assert_eq!(parse_positions("depth:1,depth:2"), Positions::from([(Feed::Depth, 2)]));
+    }
+
+    #[test]
+    fn position_follows_the_feed_seq() {
+        let depth = ServerMsg::Event { channel: "depth:BTC-USD".into(), payload: json!({ "seq": 12, "event_seq": 99 }) };
+        assert_eq!(position(&depth), Some((Feed::Depth, 12)));
+        let trade = ServerMsg::Snapshot { channel: "trades:BTC-USD".into(), payload: json!({ "seq": 12, "event_seq": 99 }) };
+        assert_eq!(position(&trade), Some((Feed::Trades, 99)));
+
+        let unstamped = ServerMsg::Event { channel: "trades:BTC-USD".into(), payload: json!({ "event_seq": 0 }) };
+        assert_eq!(position(&unstamped), None);
+        let unknown = ServerMsg::Event { channel: "bogus:BTC-USD".into(), payload: json!({ "event_seq": 3 }) };
+        assert_eq!(position(&unknown), None);
+        assert_eq!(position(&ServerMsg::Info { message: "hi".into() }), None);
+    }
+}
@@ -1,21 +1,189 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Feed {
     Depth,
     Ticker,
     Trades,
+    L3, // order-by-order events
+    Candles, // in-progress OHLCV bars of every interval
+    #[serde(rename = "mini_ticker")]
+    MiniTicker, // every market's ticker in one message per second; `"*"` only
+    // Private feeds, keyed by the authenticated user instead of a market
+    Orders,   // own order status changes
+    Fills,    // own side of every trade
+    Balances, // own balance changes
+}
+
+impl Feed {
+    pub const ALL: [Feed; 9] = [
+        Feed::Depth, Feed::Ticker, Feed::Trades, Feed::L3, Feed::Candles, Feed::MiniTicker,
+        Feed::Orders, Feed::Fills, Feed::Balances,
+    ];
+
+    /// Redis channel prefix, `<prefix>:<market_id or user_id>`
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Feed::Depth => "depth",
+            Feed::Ticker => "ticker",
+            Feed::Trades => "trades",
+            Feed::L3 => "l3",
+            Feed::Candles => "candles",
+            Feed::MiniTicker => "mini_ticker",
+            Feed::Orders => "orders",
+            Feed::Fills => "fills",
+            Feed::Balances => "balances",
+        }
+    }
+
+    /// Depth and ticker are state: a lagging client only needs the latest.
+    /// The others are event streams a client cannot recover from a gap in.
+    pub fn overflow(&self) -> Overflow {
+        match self {
+            Feed::Depth | Feed::Ticker | Feed::MiniTicker => Overflow::Conflate,
+            _ => Overflow::Disconnect,
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        matches!(self, Feed::Orders | Feed::Fills | Feed::Balances)
+    }
+
+    /// Feeds that can be followed for every market at once. Depth and l3 are
+    /// excluded: each market's book needs its own snapshot and sequence.
+    pub fn allows_wildcard(&self) -> bool {
+        matches!(self, Feed::Ticker | Feed::Trades | Feed::Candles | Feed::MiniTicker)
+    }
+}
+
+/// Wire encoding of server messages, chosen with `?encoding=` on the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,    // text frames
+    MsgPack, // binary frames; client messages may also be sent as MessagePack
+}
+
+/// A resolved market selection: one market id, or every market
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketSel {
+    All,
+    One(Uuid),
+}
+
+impl MarketSel {
+    /// Subscription key; every market is keyed by the nil id
+    pub fn key(&self) -> Uuid {
+        match self {
+            MarketSel::All => Uuid::nil(),
+            MarketSel::One(market_id) => *market_id,
+        }
+    }
+}
+
+impl fmt::Display for MarketSel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MarketSel::All => f.write_str("*"),
+            MarketSel::One(market_id) => market_id.fmt(f),
+        }
+    }
+}
+
+/// A market as named by a client: its id, its symbol (e.g. `"SOL_USDC"`) or `"*"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketRef {
+    All,
+    Id(Uuid),
+    Symbol(String),
+}
+
+impl fmt::Display for MarketRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MarketRef::All => f.write_str("*"),
+            MarketRef::Id(market_id) => market_id.fmt(f),
+            MarketRef::Symbol(symbol) => f.write_str(symbol),
+        }
+    }
+}
+
+impl Serialize for MarketRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MarketRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(match (s.as_str(), Uuid::parse_str(&s)) {
+            ("*", _) => MarketRef::All,
+            (_, Ok(market_id)) => MarketRef::Id(market_id),
+            _ => MarketRef::Symbol(s),
+        })
+    }
+}
+
+/// What happens when a client's buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Conflate,   // keep only the latest state per subscription until the client catches up
+    Disconnect, // close the connection with a reason
+}
+
+/// How prices and quantities are encoded for one subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Repr {
+    #[default]
+    Decimal, // exact decimal strings as published by the engine, e.g. "50.25"
+    Atomic,  // integer strings in token atomic units, e.g. "50250000"
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "action")]
 pub enum ClientMsg {
     #[serde(rename = "subscribe")]
-    Subscribe { market_id: Uuid, feeds: Vec<Feed> },
+    Subscribe {
+        market_id: MarketRef,
+        feeds: Vec<Feed>,
+        #[serde(default)]
+        repr: Repr,
+    },
     #[serde(rename = "unsubscribe")]
-    Unsubscribe { market_id: Uuid, feeds: Vec<Feed> },
+    Unsubscribe { market_id: MarketRef, feeds: Vec<Feed> },
+    /// Authenticate the connection with an api JWT (alternative to `?token=` on the handshake)
+    #[serde(rename = "auth")]
+    Auth { token: String },
+    /// Private feeds of the authenticated user
+    #[serde(rename = "subscribe_private")]
+    SubscribePrivate {
+        feeds: Vec<Feed>,
+        #[serde(default)]
+        repr: Repr,
+    },
+    #[serde(rename = "unsubscribe_private")]
+    UnsubscribePrivate { feeds: Vec<Feed> },
+    /// Replay a subscribed channel (e.g. `depth:<market_id>`) after the last seq the client
+    /// saw: `seq` for depth, `event_seq` for the others
+    #[serde(rename = "resume")]
+    Resume { channel: String, from_seq: u64 },
+    /// Full depth as of a delta `seq`, for clients that (re)build a local book
+    #[serde(rename = "depth_snapshot")]
+    DepthSnapshot {
+        market_id: MarketRef,
+        #[serde(default)]
+        repr: Repr,
+    },
+    /// Tradable markets with their tick size and decimals
+    #[serde(rename = "markets")]
+    Markets,
     #[serde(rename = "ping")]
     Ping,
 }
@@ -30,4 +198,7 @@ pub enum ServerMsg {
     // Raw pass-through from Redis (already JSON strings), but we wrap with meta
     #[serde(rename = "event")]
     Event { channel: String, payload: serde_json::Value },
+    // Reply to a snapshot request; `payload.seq` is the last delta it includes
+    #[serde(rename = "snapshot")]
+    Snapshot { channel: String, payload: serde_json::Value },
 }
\ No newline at end of file
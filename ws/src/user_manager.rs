@@ -1,22 +1,203 @@
-use std::{collections::{HashMap, HashSet}, sync::Arc};
-use tokio::sync::{mpsc, RwLock};
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex}};
+use std::sync::atomic::{AtomicU64, Ordering};
+use axum::extract::ws::close_code;
+use tokio::sync::{mpsc, Notify, RwLock};
 use uuid::Uuid;
 
-use crate::types::{Feed, ServerMsg};
+use crate::cache::MarketCache;
+use crate::frame::{Encoded, Frame};
+use crate::history::{ChannelHistory, Replay};
+use crate::types::{Encoding, Feed, MarketSel, Overflow, Repr, ServerMsg};
 
 pub type ClientId = Uuid;
-pub type SubKey = (Feed, Uuid); // (feed, market_id)
-pub type Tx = mpsc::Sender<ServerMsg>;
-pub type Rx = mpsc::Receiver<ServerMsg>;
+pub type SubKey = (Feed, Uuid); // (feed, market_id), (feed, ALL_MARKETS) or (private feed, user_id)
+
+/// Market key of `"*"` subscriptions
+pub const ALL_MARKETS: Uuid = Uuid::nil();
+pub type Tx = mpsc::Sender<Frame>;
+pub type Rx = mpsc::Receiver<Frame>;
+
+/// Messages buffered per client before the overflow policy kicks in
+const CLIENT_BUFFER: usize = 1024;
+
+/// Per-connection state shared between the fan-out and the connection's writer task
+#[derive(Default)]
+pub struct Outbox {
+    // Latest state of conflated subscriptions, sent once the queue has drained
+    latest: Mutex<HashMap<SubKey, Frame>>,
+    close: Mutex<Option<(u16, String)>>, // close frame code and reason
+    wake: Notify,
+    pub conflated: AtomicU64, // messages replaced by a newer state
+    pub dropped: AtomicU64,   // messages lost because the client was disconnected
+}
+
+impl Outbox {
+    pub fn close_frame(&self) -> Option<(u16, String)> {
+        self.close.lock().unwrap().clone()
+    }
+
+    pub fn take_latest(&self) -> Vec<Frame> {
+        self.latest.lock().unwrap().drain().map(|(_, frame)| frame).collect()
+    }
+
+    /// Resolves after a conflated update or a close was queued
+    pub async fn wait(&self) {
+        self.wake.notified().await;
+    }
+
+    /// Ask the writer to close the connection; the first reason wins
+    pub fn close(&self, code: u16, reason: String) {
+        self.close.lock().unwrap().get_or_insert((code, reason));
+        self.wake.notify_one();
+    }
+}
+
+/// Sending side of one connection
+pub struct ClientHandle {
+    tx: Tx,
+    encoding: Encoding,
+    pub outbox: Arc<Outbox>,
+}
+
+impl ClientHandle {
+    /// Queue `frame` for the client. When its buffer is full, the feed's `Overflow` policy
+    /// decides: conflated feeds park `replacement(frame)` until the client catches up (later
+    /// messages of that subscription replace it too, so ordering holds); everything else
+    /// closes the connection, since a gap would leave the client with corrupt state.
+    fn deliver(&self, sub: Option<SubKey>, frame: Frame, replacement: impl FnOnce(Frame) -> Option<Frame>) {
+        if self.outbox.close_frame().is_some() {
+            self.outbox.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let conflate = sub.filter(|sub| sub.0.overflow() == Overflow::Conflate);
+        let frame = match conflate {
+            Some(sub) if self.outbox.latest.lock().unwrap().contains_key(&sub) => frame,
+            _ => match self.tx.try_send(frame) {
+                Ok(()) | Err(mpsc::error::TrySendError::Closed(_)) => return,
+                Err(mpsc::error::TrySendError::Full(frame)) => frame,
+            },
+        };
+
+        let feed = sub.map_or("control".to_string(), |sub| sub.0.prefix().to_string());
+        match conflate.zip(replacement(frame)) {
+            Some((sub, latest)) => {
+                self.outbox.latest.lock().unwrap().insert(sub, latest);
+                self.outbox.conflated.fetch_add(1, Ordering::Relaxed);
+                self.outbox.wake.notify_one();
+            }
+            None => {
+                self.outbox.dropped.fetch_add(1, Ordering::Relaxed);
+                self.outbox.close(close_code::AGAIN, format!("slow consumer: {feed} buffer full"));
+            }
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Inner {
-    // client_id -> sender
-    pub clients: HashMap<ClientId, Tx>,
+    // client_id -> sending side
+    pub clients: HashMap<ClientId, ClientHandle>,
     // client_id -> set of subscriptions
     pub client_subs: HashMap<ClientId, HashSet<SubKey>>,
-    // reverse index: subscription -> set of client_ids
-    pub subs_index: HashMap<SubKey, HashSet<ClientId>>,
+    // reverse index: subscription -> client_ids with their chosen representation
+    pub subs_index: HashMap<SubKey, HashMap<ClientId, Repr>>,
+    // market_id -> latest depth/ticker/trades, updated under the same lock as the fan-out
+    // so a new subscriber's snapshot and the live events after it never overlap or miss
+    pub markets: HashMap<Uuid, MarketCache>,
+    // recent messages per channel, replayed on `resume`; private channels only
+    // while their user has a subscriber, so idle users cost nothing
+    pub history: HashMap<SubKey, ChannelHistory>,
+}
+
+impl Inner {
+    fn remove_subscriber(&mut self, id: ClientId, sub: SubKey) {
+        let Some(s) = self.subs_index.get_mut(&sub) else { return };
+        s.remove(&id);
+        if s.is_empty() {
+            self.subs_index.remove(&sub);
+            if sub.0.is_private() {
+                self.history.remove(&sub);
+            }
+        }
+    }
+
+    fn send(&self, sub: SubKey, msg: ServerMsg) {
+        let specific = self.subs_index.get(&sub);
+        let wildcard = match sub.0.allows_wildcard() && sub.1 != ALL_MARKETS {
+            true => self.subs_index.get(&(sub.0, ALL_MARKETS)),
+            false => None,
+        };
+        // A client subscribed both to the market and to "*" gets the message once,
+        // in the representation of its market subscription
+        let recipients = specific.into_iter().flatten().chain(
+            wildcard.into_iter().flatten().filter(|(id, _)| !specific.is_some_and(|ids| ids.contains_key(id))),
+        );
+
+        // Encoded once per representation and encoding in use, not once per client
+        let mut frames = Encoded::new(&msg, sub.0);
+        for (id, &repr) in recipients {
+            if let Some(client) = self.clients.get(id) {
+                let frame = frames.get(repr, client.encoding);
+                client.deliver(Some(sub), frame, |frame| self.conflated(sub, repr, client.encoding, frame));
+            }
+        }
+    }
+
+    /// Every cached ticker in one message, with only the fields a market list needs
+    fn mini_ticker(&self) -> Option<ServerMsg> {
+        const FIELDS: [&str; 12] = [
+            "market_id", "last_price", "open_24h", "high_24h", "low_24h", "volume_24h", "quote_volume_24h",
+            "change_24h", "trade_count_24h", "timestamp", "price_decimals", "quantity_decimals",
+        ];
+        let tickers: Vec<serde_json::Value> = self.markets.values()
+            .filter_map(MarketCache::ticker)
+            .map(|ticker| FIELDS.iter().filter_map(|&key| Some((key.to_string(), ticker.get(key)?.clone()))).collect())
+            .collect();
+        if tickers.is_empty() {
+            return None;
+        }
+        Some(ServerMsg::Event {
+            channel: Feed::MiniTicker.prefix().to_string(),
+            payload: serde_json::json!({
+                "ts": now_millis(),
+                "tickers": tickers,
+            }),
+        })
+    }
+
+    fn replay(&self, id: ClientId, sub: SubKey, repr: Repr, from_seq: u64) -> String {
+        let channel = format!("{}:{}", sub.0.prefix(), sub.1);
+        let Some(client) = self.clients.get(&id) else { return "client gone".to_string() };
+        let replay = self.history.get(&sub).map_or(Replay::TooOld, |history| history.replay(from_seq));
+        match replay {
+            Replay::Messages(missed) => {
+                let count = missed.len();
+                for msg in missed {
+                    let frame = Encoded::new(msg, sub.0).get(repr, client.encoding);
+                    client.deliver(Some(sub), frame, |frame| self.conflated(sub, repr, client.encoding, frame));
+                }
+                format!("replayed {count} messages on {channel}")
+            }
+            Replay::TooOld => match self.markets.get(&sub.1).and_then(|cache| cache.snapshot(sub.1, sub.0)) {
+                Some(snapshot) => {
+                    let frame = Encoded::new(&snapshot, sub.0).get(repr, client.encoding);
+                    client.deliver(Some(sub), frame, |frame| self.conflated(sub, repr, client.encoding, frame));
+                    format!("{channel} gap too old to replay, sent a snapshot")
+                }
+                None => format!("{channel} gap too old to replay and no snapshot available, resubscribe"),
+            },
+        }
+    }
+
+    /// What replaces `msg` for a lagging client: the cached full book for depth
+    /// (a later delta alone would not apply), the message itself otherwise
+    fn conflated(&self, sub: SubKey, repr: Repr, encoding: Encoding, frame: Frame) -> Option<Frame> {
+        if sub.0 != Feed::Depth {
+            return Some(frame);
+        }
+        let snapshot = self.markets.get(&sub.1)?.snapshot(sub.1, Feed::Depth)?;
+        Some(Encoded::new(&snapshot, Feed::Depth).get(repr, encoding))
+    }
 }
 
 #[derive(Clone, Default)]
@@ -25,34 +206,63 @@ pub struct UserManager {
 }
 
 impl UserManager {
-    pub async fn register_client(&self) -> (ClientId, Rx) {
-        let (tx, rx) = mpsc::channel(1024);
+    pub async fn register_client(&self, encoding: Encoding) -> (ClientId, Rx, Arc<Outbox>) {
+        let (tx, rx) = mpsc::channel(CLIENT_BUFFER);
         let id = Uuid::new_v4();
+        let outbox = Arc::new(Outbox::default());
         let mut g = self.inner.write().await;
-        g.clients.insert(id, tx);
+        g.clients.insert(id, ClientHandle { tx, encoding, outbox: outbox.clone() });
         g.client_subs.insert(id, HashSet::new());
-        (id, rx)
+        (id, rx, outbox)
     }
 
     pub async fn unregister_client(&self, id: ClientId) {
         let mut g = self.inner.write().await;
         if let Some(set) = g.client_subs.remove(&id) {
             for sub in set {
-                if let Some(s) = g.subs_index.get_mut(&sub) {
-                    s.remove(&id);
-                    if s.is_empty() { g.subs_index.remove(&sub); }
-                }
+                g.remove_subscriber(id, sub);
             }
         }
-        g.clients.remove(&id);
+        if let Some(client) = g.clients.remove(&id) {
+            let (conflated, dropped) = (client.outbox.conflated.load(Ordering::Relaxed), client.outbox.dropped.load(Ordering::Relaxed));
+            if conflated > 0 || dropped > 0 {
+                tracing::info!("client {id} disconnected: {conflated} messages conflated, {dropped} dropped");
+            }
+        }
+    }
+
+    /// Reply to one client (control messages are never conflated)
+    pub async fn send_to(&self, id: ClientId, msg: ServerMsg) {
+        if let Some(client) = self.inner.read().await.clients.get(&id) {
+            client.deliver(None, Frame::encode(&msg, client.encoding), |_| None);
+        }
     }
 
-    pub async fn subscribe(&self, id: ClientId, sub: SubKey) {
+    /// Notify every connected client, e.g. of a market change
+    pub async fn send_all(&self, msg: ServerMsg) {
+        let g = self.inner.read().await;
+        let mut frames = [None, None]; // per encoding
+        for client in g.clients.values() {
+            let frame = frames[client.encoding as usize].get_or_insert_with(|| Frame::encode(&msg, client.encoding)).clone();
+            client.deliver(None, frame, |_| None);
+        }
+    }
+
+    /// Whether the client can add `subs` without holding more than `max` subscriptions
+    pub async fn has_room(&self, id: ClientId, subs: impl Iterator<Item = SubKey>, max: usize) -> bool {
+        let g = self.inner.read().await;
+        let Some(current) = g.client_subs.get(&id) else { return false };
+        let new: HashSet<SubKey> = subs.filter(|sub| !current.contains(sub)).collect();
+        current.len() + new.len() <= max
+    }
+
+    /// Subscribing again to the same key only changes the representation
+    pub async fn subscribe(&self, id: ClientId, sub: SubKey, repr: Repr) {
         let mut g = self.inner.write().await;
         if let Some(set) = g.client_subs.get_mut(&id) {
             set.insert(sub);
         }
-        g.subs_index.entry(sub).or_default().insert(id);
+        g.subs_index.entry(sub).or_default().insert(id, repr);
     }
 
     pub async fn unsubscribe(&self, id: ClientId, sub: SubKey) {
@@ -60,20 +270,251 @@ impl UserManager {
         if let Some(set) = g.client_subs.get_mut(&id) {
             set.remove(&sub);
         }
-        if let Some(s) = g.subs_index.get_mut(&sub) {
-            s.remove(&id);
-            if s.is_empty() { g.subs_index.remove(&sub); }
+        g.remove_subscriber(id, sub);
+    }
+
+    /// Publish the batched mini ticker, if anyone listens; called every second
+    pub async fn publish_mini_ticker(&self) {
+        let g = self.inner.read().await;
+        let sub = (Feed::MiniTicker, ALL_MARKETS);
+        if !g.subs_index.contains_key(&sub) {
+            return;
         }
+        if let Some(msg) = g.mini_ticker() {
+            g.send(sub, msg);
+        }
+    }
+
+    /// Live event from Redis: remember it for later subscribers, then fan it out
+    pub async fn publish(&self, sub: SubKey, msg: ServerMsg) {
+        let mut g = self.inner.write().await;
+        if let (ServerMsg::Event { payload, .. }, Feed::Depth | Feed::Ticker | Feed::Trades) = (&msg, sub.0) {
+            g.markets.entry(sub.1).or_default().record(sub.0, payload);
+        }
+        if !sub.0.is_private() || g.subs_index.contains_key(&sub) {
+            g.history.entry(sub).or_default().push(sub.0, &msg);
+        }
+        g.send(sub, msg);
     }
 
-    pub async fn broadcast(&self, sub: SubKey, msg: ServerMsg) {
+    /// Replay what a subscribed client missed on `sub` after `from_seq`; if that is no longer
+    /// buffered, send the cached snapshot instead. Returns a status line for the client.
+    pub async fn resume(&self, id: ClientId, sub: SubKey, from_seq: u64) -> String {
         let g = self.inner.read().await;
-        if let Some(ids) = g.subs_index.get(&sub) {
-            for id in ids {
-                if let Some(tx) = g.clients.get(id) {
-                    let _ = tx.try_send(msg.clone());
-                }
+        let subscribed = |key: SubKey| g.subs_index.get(&key).and_then(|ids| ids.get(&id));
+        let repr = subscribed(sub)
+            .or_else(|| subscribed((sub.0, ALL_MARKETS)).filter(|_| sub.0.allows_wildcard()))
+            .copied();
+        match repr {
+            Some(repr) => g.replay(id, sub, repr, from_seq),
+            None => format!("not subscribed to {}:{}", sub.0.prefix(), sub.1),
+        }
+    }
+
+    /// Subscribe and replay what the client missed after `from_seq` under one lock,
+    /// so no live message lands between (or twice around) the replay
+    pub async fn subscribe_from(&self, id: ClientId, sub: SubKey, repr: Repr, from_seq: u64) -> String {
+        let mut g = self.inner.write().await;
+        let Some(set) = g.client_subs.get_mut(&id) else { return "client gone".to_string() };
+        set.insert(sub);
+        g.subs_index.entry(sub).or_default().insert(id, repr);
+        g.replay(id, sub, repr, from_seq)
+    }
+
+    pub async fn has_depth(&self, market_id: Uuid) -> bool {
+        self.inner.read().await.markets.get(&market_id).is_some_and(MarketCache::has_depth)
+    }
+
+    pub async fn seed_depth(&self, market_id: Uuid, snapshot: &serde_json::Value) {
+        self.inner.write().await.markets.entry(market_id).or_default().seed_depth(snapshot);
+    }
+
+    /// Subscribe to market feeds and send the cached state of each first;
+    /// a `"*"` subscription gets the cached state of every market
+    pub async fn subscribe_market(&self, id: ClientId, market: MarketSel, feeds: &[Feed], repr: Repr) {
+        let mut g = self.inner.write().await;
+        for &feed in feeds {
+            let key = (feed, market.key());
+            if let Some(set) = g.client_subs.get_mut(&id) {
+                set.insert(key);
+            }
+            g.subs_index.entry(key).or_default().insert(id, repr);
+
+            let snapshots: Vec<(SubKey, ServerMsg)> = match market {
+                MarketSel::All if feed == Feed::MiniTicker => g.mini_ticker().map(|msg| (key, msg)).into_iter().collect(),
+                MarketSel::All => g.markets.iter()
+                    .filter_map(|(&market_id, cache)| Some(((feed, market_id), cache.snapshot(market_id, feed)?)))
+                    .collect(),
+                MarketSel::One(market_id) => g.markets.get(&market_id)
+                    .and_then(|cache| cache.snapshot(market_id, feed))
+                    .map(|msg| (key, msg))
+                    .into_iter()
+                    .collect(),
+            };
+            let Some(client) = g.clients.get(&id) else { continue };
+            for (sub, msg) in snapshots {
+                let frame = Encoded::new(&msg, feed).get(repr, client.encoding);
+                client.deliver(Some(sub), frame, |frame| g.conflated(sub, repr, client.encoding, frame));
             }
         }
     }
-}
\ No newline at end of file
+}
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client whose buffer holds a single frame
+    fn client() -> (ClientHandle, Rx) {
+        let (tx, rx) = mpsc::channel(1);
+        (ClientHandle { tx, encoding: Encoding::Json, outbox: Arc::default() }, rx)
+    }
+
+    fn frame(text: &str) -> Frame {
+        Frame::Text(text.into())
+    }
+
+    fn text(frame: &Frame) -> &str {
+        match frame {
+            Frame::Text(text) => text,
+            Frame::Binary(_) => panic!("expected a text frame"),
+        }
+    }
+
+    #[test]
+    fn state_feeds_conflate_to_the_latest_frame() {
+        let market = Uuid::new_v4();
+        for feed in [Feed::Depth, Feed::Ticker, Feed::MiniTicker] {
+            let (client, mut rx) = client();
+            let sub = (feed, market);
+            for n in 1..=4 {
+                client.deliver(Some(sub), frame(&n.to_string()), Some);
+            }
+            assert!(client.outbox.close_frame().is_none(), "{feed:?} must not disconnect");
+            assert_eq!(text(&rx.try_recv().unwrap()), "1");
+            assert!(rx.try_recv().is_err());
+            let latest = client.outbox.take_latest();
+            assert_eq!(latest.iter().map(text).collect::<Vec<_>>(), ["4"]);
+            assert_eq!(client.outbox.conflated.load(Ordering::Relaxed), 3);
+            assert_eq!(client.outbox.dropped.load(Ordering::Relaxed), 0);
+        }
+    }
+
+    #[test]
+    fn parked_state_keeps_replacing_until_taken() {
+        // Once a state is parked, newer ones replace it even with room in the queue,
+        // so an older frame never overtakes it
+        let (client, mut rx) = client();
+        let sub = (Feed::Ticker, Uuid::new_v4());
+        client.deliver(Some(sub), frame("1"), Some);
+        client.deliver(Some(sub), frame("2"), Some);
+        assert_eq!(text(&rx.try_recv().unwrap()), "1");
+        client.deliver(Some(sub), frame("3"), Some);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(client.outbox.take_latest().iter().map(text).collect::<Vec<_>>(), ["3"]);
+
+        client.deliver(Some(sub), frame("4"), Some);
+        assert_eq!(text(&rx.try_recv().unwrap()), "4");
+    }
+
+    #[test]
+    fn event_feeds_disconnect_on_overflow() {
+        let id = Uuid::new_v4();
+        for feed in [Feed::Trades, Feed::L3, Feed::Candles, Feed::Orders, Feed::Fills, Feed::Balances] {
+            let (client, mut rx) = client();
+            let sub = (feed, id);
+            client.deliver(Some(sub), frame("1"), Some);
+            client.deliver(Some(sub), frame("2"), Some);
+            let (code, reason) = client.outbox.close_frame().expect("overflow closes the connection");
+            assert_eq!(code, close_code::AGAIN);
+            assert_eq!(reason, format!("slow consumer: {} buffer full", feed.prefix()));
+            assert!(client.outbox.take_latest().is_empty());
+
+            // Nothing is queued after the close
+            client.deliver(Some(sub), frame("3"), Some);
+            assert_eq!(text(&rx.try_recv().unwrap()), "1");
+            assert!(rx.try_recv().is_err());
+            assert_eq!(client.outbox.dropped.load(Ordering::Relaxed), 2);
+            assert_eq!(client.outbox.conflated.load(Ordering::Relaxed), 0);
+        }
+    }
+
+    #[test]
+    fn depth_without_a_cached_book_disconnects() {
+        // A depth delta alone cannot be conflated; without a snapshot to replace it the client goes
+        let (client, _rx) = client();
+        let sub = (Feed::Depth, Uuid::new_v4());
+        client.deliver(Some(sub), frame("1"), |_| None);
+        client.deliver(Some(sub), frame("2"), |_| None);
+        assert!(client.outbox.close_frame().is_some());
+    }
+
+    #[test]
+    fn control_messages_disconnect_on_overflow() {
+        let (client, _rx) = client();
+        client.deliver(None, frame("1"), |_| None);
+        client.deliver(None, frame("2"), |_| None);
+        assert_eq!(client.outbox.close_frame().map(|(_, reason)| reason), Some("slow consumer: control buffer full".to_string()));
+    }
+
+    fn trade(seq: u64, prev: u64) -> ServerMsg {
+        ServerMsg::Event {
+            channel: "trades".to_string(),
+            payload: serde_json::json!({ "event_seq": seq, "prev_event_seq": prev }),
+        }
+    }
+
+    fn drain(rx: &mut Rx) -> usize {
+        std::iter::from_fn(|| rx.try_recv().ok()).count()
+    }
+
+    #[tokio::test]
+    async fn wildcard_subscriber_resumes_a_market() {
+        let users = UserManager::default();
+        let market = Uuid::new_v4();
+        let (direct, mut direct_rx, _) = users.register_client(Encoding::Json).await;
+        let (wildcard, mut wildcard_rx, _) = users.register_client(Encoding::Json).await;
+        users.subscribe(direct, (Feed::Trades, market), Repr::Decimal).await;
+        users.subscribe(wildcard, (Feed::Trades, ALL_MARKETS), Repr::Decimal).await;
+
+        for (seq, prev) in [(1, 0), (2, 1), (3, 2)] {
+            users.publish((Feed::Trades, market), trade(seq, prev)).await;
+        }
+        assert_eq!(drain(&mut direct_rx), 3);
+        assert_eq!(drain(&mut wildcard_rx), 3);
+
+        // The market also has a direct subscriber; the "*" client must still be found
+        let status = users.resume(wildcard, (Feed::Trades, market), 1).await;
+        assert_eq!(status, format!("replayed 2 messages on trades:{market}"));
+        assert_eq!(drain(&mut wildcard_rx), 2);
+        assert_eq!(drain(&mut direct_rx), 0);
+
+        // Depth cannot be followed with "*", so it cannot be resumed through it either
+        users.subscribe(wildcard, (Feed::Depth, ALL_MARKETS), Repr::Decimal).await;
+        let status = users.resume(wildcard, (Feed::Depth, market), 1).await;
+        assert_eq!(status, format!("not subscribed to depth:{market}"));
+    }
+
+    #[tokio::test]
+    async fn private_history_ends_with_its_last_subscriber() {
+        let users = UserManager::default();
+        let user = Uuid::new_v4();
+        let sub = (Feed::Orders, user);
+        // Nobody listens: nothing is kept
+        users.publish(sub, trade(1, 0)).await;
+        assert!(users.inner.read().await.history.is_empty());
+
+        let (id, _rx, _) = users.register_client(Encoding::Json).await;
+        users.subscribe(id, sub, Repr::Decimal).await;
+        users.publish(sub, trade(2, 1)).await;
+        assert!(users.inner.read().await.history.contains_key(&sub));
+        users.unregister_client(id).await;
+        assert!(users.inner.read().await.history.is_empty());
+    }
+}